@@ -1,5 +1,8 @@
 use aa_bundler_grpc::{bundler_client::BundlerClient, uo_pool_client::UoPoolClient};
-use aa_bundler_rpc::{DebugApiServer, DebugApiServerImpl, EthApiServer, EthApiServerImpl};
+use aa_bundler_rpc::{
+    BundlerApiServer, BundlerApiServerImpl, DebugApiServer, DebugApiServerImpl, EthApiServer,
+    EthApiServerImpl,
+};
 use anyhow::Result;
 use clap::Parser;
 use jsonrpsee::{core::server::rpc_module::Methods, server::ServerBuilder, tracing::info};
@@ -20,7 +23,7 @@ pub struct Opt {
     #[clap(long, default_value = "127.0.0.1:3002")]
     pub bundler_grpc_listen_address: String,
 
-    #[clap(long, value_delimiter=',', default_value = "eth", value_parser = ["eth", "debug"])]
+    #[clap(long, value_delimiter=',', default_value = "eth", value_parser = ["eth", "debug", "bundler"])]
     pub rpc_api: Vec<String>,
 }
 
@@ -50,6 +53,15 @@ async fn main() -> Result<()> {
         )?;
     }
 
+    if rpc_api.contains("bundler") {
+        api.merge(
+            BundlerApiServerImpl {
+                uopool_grpc_client: uopool_grpc_client.clone(),
+            }
+            .into_rpc(),
+        )?;
+    }
+
     if rpc_api.contains("debug") {
         let bundler_grpc_client =
             BundlerClient::connect(format!("http://{}", opt.bundler_grpc_listen_address)).await?;
@@ -2,10 +2,16 @@ use aa_bundler_grpc::{
     bundler_client::BundlerClient, bundler_service_run, uo_pool_client::UoPoolClient,
     uopool_service_run, BundlerService, BundlerServiceOpts, UoPoolServiceOpts,
 };
-use aa_bundler_primitives::{parse_address, parse_u256, Wallet};
-use aa_bundler_rpc::{DebugApiServer, DebugApiServerImpl, EthApiServer, EthApiServerImpl};
+use aa_bundler_primitives::{
+    parse_address, parse_u256, read_keystore_password, read_private_key, RemoteSigner,
+    SharedSigner, Wallet,
+};
+use aa_bundler_rpc::{
+    BundlerApiServer, BundlerApiServerImpl, DebugApiServer, DebugApiServerImpl, EthApiServer,
+    EthApiServerImpl,
+};
 use anyhow::{format_err, Result};
-use clap::Parser;
+use clap::{ArgGroup, Parser};
 use ethers::{
     providers::{Http, Middleware, Provider},
     types::{Address, U256},
@@ -19,9 +25,49 @@ use std::{collections::HashSet, future::pending, panic, sync::Arc};
     name = "aa-bundler",
     about = "Bundler for EIP-4337 Account Abstraction"
 )]
+#[clap(group(
+    ArgGroup::new("wallet")
+        .required(true)
+        .args([
+            "mnemonic_file",
+            "keystore_file",
+            "private_key_env",
+            "private_key_file",
+            "remote_signer_url",
+        ])
+))]
 pub struct Opt {
     #[clap(long)]
-    pub mnemonic_file: ExpandedPathBuf,
+    pub mnemonic_file: Option<ExpandedPathBuf>,
+
+    // encrypted JSON keystore, password is read from env variable or file
+    #[clap(long)]
+    pub keystore_file: Option<ExpandedPathBuf>,
+
+    #[clap(long, requires = "keystore_file")]
+    pub keystore_password_env: Option<String>,
+
+    #[clap(
+        long,
+        requires = "keystore_file",
+        conflicts_with = "keystore_password_env"
+    )]
+    pub keystore_password_file: Option<ExpandedPathBuf>,
+
+    // raw hex private key, read from env variable or file
+    #[clap(long)]
+    pub private_key_env: Option<String>,
+
+    #[clap(long, conflicts_with = "private_key_env")]
+    pub private_key_file: Option<ExpandedPathBuf>,
+
+    // external signer (e.g. Web3Signer) that supports eth_signTransaction
+    #[clap(long)]
+    pub remote_signer_url: Option<String>,
+
+    // account used by the remote signer, defaults to the first account returned by eth_accounts
+    #[clap(long, value_parser=parse_address, requires = "remote_signer_url")]
+    pub remote_signer_address: Option<Address>,
 
     #[clap(long, value_delimiter=',', value_parser=parse_address)]
     pub entry_points: Vec<Address>,
@@ -41,7 +87,7 @@ pub struct Opt {
     #[clap(long, default_value = "127.0.0.1:3000")]
     pub rpc_listen_address: String,
 
-    #[clap(long, value_delimiter=',', default_value = "eth", value_parser = ["eth", "debug"])]
+    #[clap(long, value_delimiter=',', default_value = "eth", value_parser = ["eth", "debug", "bundler"])]
     pub rpc_api: Vec<String>,
 
     // execution client rpc endpoint
@@ -52,6 +98,43 @@ pub struct Opt {
     pub bundler_opts: BundlerServiceOpts,
 }
 
+async fn load_signer(opt: &Opt, chain_id: U256) -> Result<SharedSigner> {
+    if let Some(remote_signer_url) = opt.remote_signer_url.as_ref() {
+        let signer = RemoteSigner::new(remote_signer_url, opt.remote_signer_address, chain_id)
+            .await
+            .map_err(|error| format_err!("Could not connect to remote signer: {}", error))?;
+        return Ok(Arc::new(signer));
+    }
+
+    Ok(Arc::new(load_wallet(opt, chain_id)?))
+}
+
+fn load_wallet(opt: &Opt, chain_id: U256) -> Result<Wallet> {
+    if let Some(keystore_file) = opt.keystore_file.clone() {
+        let password = read_keystore_password(
+            opt.keystore_password_env.as_deref(),
+            opt.keystore_password_file.as_ref(),
+        )?;
+        return Wallet::from_keystore(keystore_file, &password, chain_id)
+            .map_err(|error| format_err!("Could not load keystore file: {}", error));
+    }
+
+    if opt.private_key_env.is_some() || opt.private_key_file.is_some() {
+        let private_key = read_private_key(
+            opt.private_key_env.as_deref(),
+            opt.private_key_file.as_ref(),
+        )?;
+        return Wallet::from_private_key(&private_key, chain_id)
+            .map_err(|error| format_err!("Could not load private key: {}", error));
+    }
+
+    match opt.mnemonic_file.clone() {
+        Some(mnemonic_file) => Wallet::from_file(mnemonic_file, chain_id)
+            .map_err(|error| format_err!("Could not load mnemonic file: {}", error)),
+        None => Err(format_err!("Bundler wallet is not specified")),
+    }
+}
+
 fn main() -> Result<()> {
     let opt: Opt = Opt::parse();
 
@@ -78,9 +161,8 @@ fn main() -> Result<()> {
 
                 let chain_id = eth_provider.get_chainid().await?;
 
-                let wallet = Wallet::from_file(opt.mnemonic_file.clone(), chain_id)
-                    .map_err(|error| format_err!("Could not load mnemonic file: {}", error))?;
-                info!("{:?}", wallet.signer);
+                let signer = load_signer(&opt, chain_id).await?;
+                info!("Bundler signer address: {:?}", signer.address());
 
                 let eth_provider =
                     Arc::new(Provider::<Http>::try_from(opt.eth_client_address.clone())?);
@@ -88,7 +170,7 @@ fn main() -> Result<()> {
                 if !opt.no_uopool {
                     info!("Starting op pool with bundler");
                     uopool_service_run(
-                        opt.uopool_opts,
+                        opt.uopool_opts.clone(),
                         opt.entry_points.clone(),
                         eth_provider,
                         opt.max_verification_gas,
@@ -104,13 +186,20 @@ fn main() -> Result<()> {
                 .await?;
                 info!("Connected to uopool grpc");
 
+                let submitter = opt.bundler_opts.submitter(&opt.eth_client_address)?;
+                info!(
+                    "Submitting bundles with {:?}",
+                    opt.bundler_opts.send_bundle_mode
+                );
+
                 let bundler_service = BundlerService::new(
-                    wallet,
+                    signer,
                     opt.bundler_opts.beneficiary,
                     uopool_grpc_client.clone(),
                     opt.entry_points,
                     chain_id,
                     opt.eth_client_address.clone(),
+                    submitter,
                 );
                 info!("Starting bundler manager");
                 bundler_service.start_bundling(opt.bundler_opts.bundle_interval);
@@ -147,6 +236,15 @@ fn main() -> Result<()> {
                                 )?;
                             }
 
+                            if rpc_api.contains("bundler") {
+                                api.merge(
+                                    BundlerApiServerImpl {
+                                        uopool_grpc_client: uopool_grpc_client.clone(),
+                                    }
+                                    .into_rpc(),
+                                )?;
+                            }
+
                             if rpc_api.contains("debug") {
                                 let bundler_grpc_client = BundlerClient::connect(format!(
                                     "http://{}",
@@ -1,4 +1,4 @@
-use aa_bundler_primitives::{parse_u256, Wallet};
+use aa_bundler_primitives::{parse_u256, read_keystore_password, Wallet};
 use anyhow::Result;
 use clap::Parser;
 use dirs::home_dir;
@@ -17,6 +17,16 @@ pub struct Opt {
 
     #[clap(long, value_parser=parse_u256, default_value="1")]
     pub chain_id: U256,
+
+    // store the wallet as an encrypted JSON keystore instead of a plaintext mnemonic
+    #[clap(long)]
+    pub keystore: bool,
+
+    #[clap(long, requires = "keystore")]
+    pub keystore_password_env: Option<String>,
+
+    #[clap(long, requires = "keystore", conflicts_with = "keystore_password_env")]
+    pub keystore_password_file: Option<ExpandedPathBuf>,
 }
 
 fn main() -> Result<()> {
@@ -33,7 +43,15 @@ fn main() -> Result<()> {
             .map(ExpandedPathBuf)?
     };
 
-    let wallet = Wallet::new(path, opt.chain_id)?;
+    let wallet = if opt.keystore {
+        let password = read_keystore_password(
+            opt.keystore_password_env.as_deref(),
+            opt.keystore_password_file.as_ref(),
+        )?;
+        Wallet::new_keystore(path, &password, opt.chain_id)?
+    } else {
+        Wallet::new(path, opt.chain_id)?
+    };
     info!("{:?}", wallet.signer);
 
     Ok(())
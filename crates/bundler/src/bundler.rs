@@ -1,55 +1,56 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
+use crate::submitter::SharedSubmitter;
 use aa_bundler_contracts::EntryPointAPI;
-use aa_bundler_primitives::{UserOperation, Wallet};
+use aa_bundler_primitives::{SharedSigner, StorageMap, UserOperation};
 use ethers::{
-    prelude::SignerMiddleware,
     providers::{Http, Middleware, Provider},
-    signers::Signer,
     types::{transaction::eip2718::TypedTransaction, Address, H256, U256},
 };
 use tracing::{info, trace};
 
 #[derive(Clone)]
 pub struct Bundler {
-    pub wallet: Wallet,
+    pub signer: SharedSigner,
     pub beneficiary: Address,
     pub entry_point: Address,
     pub chain_id: U256,
     pub eth_client_address: String,
+    pub submitter: SharedSubmitter,
 }
 
 impl Bundler {
     pub fn new(
-        wallet: Wallet,
+        signer: SharedSigner,
         beneficiary: Address,
         entry_point: Address,
         chain_id: U256,
         eth_client_address: String,
+        submitter: SharedSubmitter,
     ) -> Self {
         Self {
-            wallet,
+            signer,
             beneficiary,
             entry_point,
             chain_id,
             eth_client_address,
+            submitter,
         }
     }
 
-    pub async fn send_next_bundle(&self, bundle: &Vec<UserOperation>) -> anyhow::Result<H256> {
+    pub async fn send_next_bundle(
+        &self,
+        bundle: &Vec<UserOperation>,
+        storage_map: &StorageMap,
+    ) -> anyhow::Result<H256> {
         info!(
             "Creating the next bundle, got {} user operations",
             bundle.len()
         );
         let provider = Provider::<Http>::try_from(self.eth_client_address.clone())?;
-        let client = Arc::new(SignerMiddleware::new(
-            provider.clone(),
-            self.wallet.signer.clone(),
-        ));
-        let entry_point = EntryPointAPI::new(self.entry_point, client.clone());
-        let nonce = client
-            .clone()
-            .get_transaction_count(self.wallet.signer.address(), None)
+        let entry_point = EntryPointAPI::new(self.entry_point, Arc::new(provider.clone()));
+        let nonce = provider
+            .get_transaction_count(self.signer.address(), None)
             .await?;
         let mut tx: TypedTransaction = entry_point
             .handle_ops(
@@ -58,18 +59,15 @@ impl Bundler {
             )
             .tx
             .clone();
-        tx.set_nonce(nonce).set_chain_id(self.chain_id.as_u64());
+        tx.set_from(self.signer.address())
+            .set_nonce(nonce)
+            .set_chain_id(self.chain_id.as_u64());
+        provider.fill_transaction(&mut tx, None).await?;
 
         trace!("Prepare the transaction {tx:?} send to execution client!");
-        let tx = client
-            .send_transaction(tx, None)
-            .await?
-            .interval(Duration::from_millis(75));
-        let tx_hash = tx.tx_hash();
-        trace!("Send bundle with transaction: {tx:?}");
-
-        let tx_receipt = tx.await?;
-        trace!("Bundle transaction receipt: {tx_receipt:?}");
+        let raw_tx = self.signer.sign_transaction(&tx).await?;
+        let tx_hash = self.submitter.submit(raw_tx, storage_map).await?;
+        trace!("Bundle transaction {tx_hash:?} included");
 
         Ok(tx_hash)
     }
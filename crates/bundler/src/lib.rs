@@ -1,5 +1,11 @@
 #![allow(dead_code)]
 
 mod bundler;
+mod submitter;
 
 pub use bundler::Bundler;
+pub use submitter::{
+    BundleSubmitter, ConditionalSubmitter, EthClientSubmitter, FlashbotsSubmitter,
+    PrivateRpcSubmitter, SendBundleMode, SharedSubmitter, DEFAULT_MAX_BLOCKS,
+    DEFAULT_POLL_INTERVAL,
+};
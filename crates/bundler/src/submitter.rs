@@ -0,0 +1,514 @@
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use aa_bundler_primitives::StorageMap;
+use anyhow::format_err;
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{BlockId, Bytes, H256, U64},
+    utils::keccak256,
+};
+use serde_json::{json, Value};
+use tracing::trace;
+
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(75);
+pub const DEFAULT_MAX_BLOCKS: u64 = 25;
+
+pub type SharedSubmitter = Arc<dyn BundleSubmitter>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendBundleMode {
+    // eth_sendRawTransaction to the execution client (public mempool)
+    EthClient,
+    // eth_sendRawTransaction to a private RPC endpoint
+    PrivateRpc,
+    // eth_sendBundle to a Flashbots compatible relay
+    Flashbots,
+    // eth_sendRawTransactionConditional to the execution client (L2 sequencer)
+    Conditional,
+}
+
+impl FromStr for SendBundleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eth-client" => Ok(Self::EthClient),
+            "private-rpc" => Ok(Self::PrivateRpc),
+            "flashbots" => Ok(Self::Flashbots),
+            "conditional" => Ok(Self::Conditional),
+            _ => Err(format!(
+                "{s} is not a valid send bundle mode (eth-client, private-rpc, flashbots, conditional)"
+            )),
+        }
+    }
+}
+
+// Submits signed bundle (handleOps) transactions and waits until they are included.
+// Storage map contains slots read during validation of the bundled user operations.
+#[async_trait]
+pub trait BundleSubmitter: Send + Sync {
+    async fn submit(&self, raw_tx: Bytes, storage_map: &StorageMap) -> anyhow::Result<H256>;
+}
+
+async fn wait_for_receipt(
+    provider: &Provider<Http>,
+    tx_hash: H256,
+    interval: Duration,
+    max_blocks: u64,
+) -> anyhow::Result<()> {
+    let start_block = provider.get_block_number().await?;
+    loop {
+        if let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? {
+            trace!("Bundle transaction receipt: {receipt:?}");
+            return Ok(());
+        }
+        if provider.get_block_number().await? > start_block + max_blocks {
+            return Err(format_err!(
+                "Bundle transaction {tx_hash:?} was not included in {max_blocks} blocks"
+            ));
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+// Sends bundles to the public mempool through the execution client.
+pub struct EthClientSubmitter {
+    provider: Provider<Http>,
+}
+
+impl EthClientSubmitter {
+    pub fn new(provider: Provider<Http>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl BundleSubmitter for EthClientSubmitter {
+    async fn submit(&self, raw_tx: Bytes, _storage_map: &StorageMap) -> anyhow::Result<H256> {
+        let tx = self
+            .provider
+            .send_raw_transaction(raw_tx)
+            .await?
+            .interval(DEFAULT_POLL_INTERVAL);
+        let tx_hash = tx.tx_hash();
+        trace!("Send bundle with transaction: {tx:?}");
+
+        let tx_receipt = tx.await?;
+        trace!("Bundle transaction receipt: {tx_receipt:?}");
+
+        Ok(tx_hash)
+    }
+}
+
+// Sends bundles with eth_sendRawTransaction to a private RPC (e.g. Flashbots Protect), so they never
+// appear in the public mempool. Inclusion is tracked through the execution client.
+pub struct PrivateRpcSubmitter {
+    relay: Provider<Http>,
+    provider: Provider<Http>,
+    interval: Duration,
+    max_blocks: u64,
+}
+
+impl PrivateRpcSubmitter {
+    pub fn new(relay_url: &str, provider: Provider<Http>) -> anyhow::Result<Self> {
+        Ok(Self {
+            relay: Provider::<Http>::try_from(relay_url)?,
+            provider,
+            interval: DEFAULT_POLL_INTERVAL,
+            max_blocks: DEFAULT_MAX_BLOCKS,
+        })
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn max_blocks(mut self, max_blocks: u64) -> Self {
+        self.max_blocks = max_blocks;
+        self
+    }
+}
+
+#[async_trait]
+impl BundleSubmitter for PrivateRpcSubmitter {
+    async fn submit(&self, raw_tx: Bytes, _storage_map: &StorageMap) -> anyhow::Result<H256> {
+        let tx_hash: H256 = self
+            .relay
+            .request("eth_sendRawTransaction", [raw_tx])
+            .await?;
+        trace!("Send bundle with transaction {tx_hash:?} to private rpc");
+
+        wait_for_receipt(&self.provider, tx_hash, self.interval, self.max_blocks).await?;
+
+        Ok(tx_hash)
+    }
+}
+
+// Sends bundles with eth_sendBundle to a Flashbots compatible relay. The bundle targets the next
+// block and is resubmitted for every following block until it is included or max blocks is reached.
+pub struct FlashbotsSubmitter {
+    client: reqwest::Client,
+    relay_url: String,
+    // key used only to authenticate requests to the relay (X-Flashbots-Signature header)
+    auth_signer: LocalWallet,
+    provider: Provider<Http>,
+    interval: Duration,
+    max_blocks: u64,
+}
+
+impl FlashbotsSubmitter {
+    pub fn new(relay_url: &str, auth_signer: LocalWallet, provider: Provider<Http>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            relay_url: relay_url.to_string(),
+            auth_signer,
+            provider,
+            interval: DEFAULT_POLL_INTERVAL,
+            max_blocks: DEFAULT_MAX_BLOCKS,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn max_blocks(mut self, max_blocks: u64) -> Self {
+        self.max_blocks = max_blocks;
+        self
+    }
+
+    async fn send_bundle(&self, raw_tx: &Bytes, block_number: U64) -> anyhow::Result<()> {
+        let body = serde_json::to_string(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_sendBundle",
+            "params": [{
+                "txs": [raw_tx],
+                "blockNumber": block_number,
+            }],
+        }))?;
+
+        let signature = self
+            .auth_signer
+            .sign_message(format!("{:?}", H256::from(keccak256(body.as_bytes()))))
+            .await?;
+
+        let response: Value = self
+            .client
+            .post(&self.relay_url)
+            .header("Content-Type", "application/json")
+            .header(
+                "X-Flashbots-Signature",
+                format!("{:?}:0x{}", self.auth_signer.address(), signature),
+            )
+            .body(body)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(error) = response.get("error") {
+            return Err(format_err!("Relay rejected bundle: {error}"));
+        }
+        trace!("Relay accepted bundle for block {block_number}: {response:?}");
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BundleSubmitter for FlashbotsSubmitter {
+    async fn submit(&self, raw_tx: Bytes, _storage_map: &StorageMap) -> anyhow::Result<H256> {
+        let tx_hash = H256::from(keccak256(&raw_tx));
+        let mut block_number = self.provider.get_block_number().await?;
+
+        for _ in 0..self.max_blocks {
+            let target_block = block_number + 1;
+            self.send_bundle(&raw_tx, target_block).await?;
+
+            while block_number < target_block {
+                tokio::time::sleep(self.interval).await;
+                block_number = self.provider.get_block_number().await?;
+            }
+
+            if let Some(receipt) = self.provider.get_transaction_receipt(tx_hash).await? {
+                trace!("Bundle transaction receipt: {receipt:?}");
+                return Ok(tx_hash);
+            }
+        }
+
+        Err(format_err!(
+            "Bundle transaction {tx_hash:?} was not included in {} blocks",
+            self.max_blocks
+        ))
+    }
+}
+
+// Sends bundles with eth_sendRawTransactionConditional (supported by Arbitrum and Optimism sequencers).
+// The storage slots read during validation are passed as knownAccounts, so the sequencer rejects the
+// bundle if any of them changed instead of including a reverting transaction.
+pub struct ConditionalSubmitter {
+    provider: Provider<Http>,
+    interval: Duration,
+    max_blocks: u64,
+}
+
+impl ConditionalSubmitter {
+    pub fn new(provider: Provider<Http>) -> Self {
+        Self {
+            provider,
+            interval: DEFAULT_POLL_INTERVAL,
+            max_blocks: DEFAULT_MAX_BLOCKS,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn max_blocks(mut self, max_blocks: u64) -> Self {
+        self.max_blocks = max_blocks;
+        self
+    }
+
+    async fn known_accounts(&self, storage_map: &StorageMap) -> anyhow::Result<Value> {
+        let block = BlockId::from(self.provider.get_block_number().await?);
+        let mut known_accounts = serde_json::Map::new();
+
+        for (address, slots) in storage_map {
+            let mut values = serde_json::Map::new();
+            for slot in slots {
+                let value = self
+                    .provider
+                    .get_storage_at(*address, *slot, Some(block))
+                    .await?;
+                values.insert(format!("{slot:?}"), json!(value));
+            }
+            known_accounts.insert(format!("{address:?}"), Value::Object(values));
+        }
+
+        Ok(Value::Object(known_accounts))
+    }
+}
+
+#[async_trait]
+impl BundleSubmitter for ConditionalSubmitter {
+    async fn submit(&self, raw_tx: Bytes, storage_map: &StorageMap) -> anyhow::Result<H256> {
+        let known_accounts = self.known_accounts(storage_map).await?;
+        trace!("Send bundle with known accounts {known_accounts:?}");
+
+        let tx_hash: H256 = self
+            .provider
+            .request(
+                "eth_sendRawTransactionConditional",
+                (raw_tx, json!({ "knownAccounts": known_accounts })),
+            )
+            .await?;
+
+        wait_for_receipt(&self.provider, tx_hash, self.interval, self.max_blocks).await?;
+
+        Ok(tx_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        prelude::rand,
+        types::{Address, TransactionReceipt},
+    };
+    use jsonrpsee::{server::ServerBuilder, RpcModule};
+    use std::collections::HashSet;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    };
+
+    // stand-in for both the execution client and the relay
+    #[derive(Default)]
+    struct MockRelay {
+        block_number: AtomicU64,
+        bundles: Mutex<Vec<Value>>,
+        raw_txs: Mutex<Vec<Bytes>>,
+        conditional_txs: Mutex<Vec<(Bytes, Value)>>,
+        // number of submissions before the transaction is included
+        include_after: usize,
+    }
+
+    impl MockRelay {
+        fn submissions(&self) -> usize {
+            self.bundles.lock().unwrap().len()
+                + self.raw_txs.lock().unwrap().len()
+                + self.conditional_txs.lock().unwrap().len()
+        }
+    }
+
+    async fn start_relay(include_after: usize) -> (String, Arc<MockRelay>) {
+        let server = ServerBuilder::default().build("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap();
+
+        let relay = Arc::new(MockRelay {
+            include_after,
+            ..Default::default()
+        });
+        let mut module = RpcModule::new(relay.clone());
+        module
+            .register_method("eth_blockNumber", |_, relay| {
+                Ok(U64::from(
+                    relay.block_number.fetch_add(1, Ordering::SeqCst) + 1,
+                ))
+            })
+            .unwrap();
+        module
+            .register_method("eth_sendBundle", |params, relay| {
+                relay.bundles.lock().unwrap().push(params.one::<Value>()?);
+                Ok(json!({ "bundleHash": H256::random() }))
+            })
+            .unwrap();
+        module
+            .register_method("eth_sendRawTransaction", |params, relay| {
+                let raw_tx: Bytes = params.one()?;
+                relay.raw_txs.lock().unwrap().push(raw_tx.clone());
+                Ok(H256::from(keccak256(&raw_tx)))
+            })
+            .unwrap();
+        module
+            .register_method("eth_sendRawTransactionConditional", |params, relay| {
+                let (raw_tx, options): (Bytes, Value) = params.parse()?;
+                relay
+                    .conditional_txs
+                    .lock()
+                    .unwrap()
+                    .push((raw_tx.clone(), options));
+                Ok(H256::from(keccak256(&raw_tx)))
+            })
+            .unwrap();
+        module
+            .register_method("eth_getStorageAt", |params, _| {
+                let (_, slot, _): (Address, H256, Value) = params.parse()?;
+                Ok(H256::from(keccak256(slot)))
+            })
+            .unwrap();
+        module
+            .register_method("eth_getTransactionReceipt", |params, relay| {
+                let tx_hash: H256 = params.one()?;
+                Ok(
+                    (relay.submissions() >= relay.include_after).then(|| TransactionReceipt {
+                        transaction_hash: tx_hash,
+                        ..Default::default()
+                    }),
+                )
+            })
+            .unwrap();
+
+        let handle = server.start(module).unwrap();
+        tokio::spawn(handle.stopped());
+
+        (format!("http://{address}"), relay)
+    }
+
+    fn raw_tx() -> Bytes {
+        Bytes::from(vec![1, 2, 3])
+    }
+
+    #[tokio::test]
+    async fn flashbots_submitter_resubmits_until_included() {
+        let (url, relay) = start_relay(3).await;
+        let submitter = FlashbotsSubmitter::new(
+            &url,
+            LocalWallet::new(&mut rand::thread_rng()),
+            Provider::<Http>::try_from(url.as_str()).unwrap(),
+        )
+        .interval(Duration::from_millis(1));
+
+        assert_eq!(
+            submitter
+                .submit(raw_tx(), &StorageMap::new())
+                .await
+                .unwrap(),
+            H256::from(keccak256(raw_tx()))
+        );
+
+        let bundles = relay.bundles.lock().unwrap();
+        assert_eq!(bundles.len(), 3);
+        let target_blocks: Vec<U64> = bundles
+            .iter()
+            .map(|bundle| serde_json::from_value(bundle["blockNumber"].clone()).unwrap())
+            .collect();
+        assert!(target_blocks.windows(2).all(|blocks| blocks[0] < blocks[1]));
+        assert_eq!(bundles[0]["txs"], json!([raw_tx()]));
+    }
+
+    #[tokio::test]
+    async fn flashbots_submitter_gives_up() {
+        let (url, relay) = start_relay(usize::MAX).await;
+        let submitter = FlashbotsSubmitter::new(
+            &url,
+            LocalWallet::new(&mut rand::thread_rng()),
+            Provider::<Http>::try_from(url.as_str()).unwrap(),
+        )
+        .interval(Duration::from_millis(1))
+        .max_blocks(2);
+
+        assert!(submitter
+            .submit(raw_tx(), &StorageMap::new())
+            .await
+            .is_err());
+        assert_eq!(relay.bundles.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn private_rpc_submitter() {
+        let (url, relay) = start_relay(1).await;
+        let submitter =
+            PrivateRpcSubmitter::new(&url, Provider::<Http>::try_from(url.as_str()).unwrap())
+                .unwrap()
+                .interval(Duration::from_millis(1));
+
+        assert_eq!(
+            submitter
+                .submit(raw_tx(), &StorageMap::new())
+                .await
+                .unwrap(),
+            H256::from(keccak256(raw_tx()))
+        );
+        assert_eq!(*relay.raw_txs.lock().unwrap(), vec![raw_tx()]);
+    }
+
+    #[tokio::test]
+    async fn conditional_submitter_known_accounts() {
+        let (url, relay) = start_relay(1).await;
+        let submitter =
+            ConditionalSubmitter::new(Provider::<Http>::try_from(url.as_str()).unwrap())
+                .interval(Duration::from_millis(1));
+
+        let address = Address::random();
+        let slot = H256::from_low_u64_be(1);
+        let storage_map = StorageMap::from([(address, HashSet::from([slot]))]);
+
+        assert_eq!(
+            submitter.submit(raw_tx(), &storage_map).await.unwrap(),
+            H256::from(keccak256(raw_tx()))
+        );
+
+        let conditional_txs = relay.conditional_txs.lock().unwrap();
+        assert_eq!(conditional_txs.len(), 1);
+        assert_eq!(conditional_txs[0].0, raw_tx());
+        assert_eq!(
+            conditional_txs[0].1,
+            json!({
+                "knownAccounts": {
+                    format!("{address:?}"): {
+                        format!("{slot:?}"): H256::from(keccak256(slot)),
+                    },
+                },
+            })
+        );
+    }
+}
@@ -2,20 +2,23 @@ use std::fmt::Display;
 use std::sync::Arc;
 
 use super::gen::entry_point_api::{
-    EntryPointAPIErrors, FailedOp, SenderAddressResult, UserOperation, ValidationResult,
-    ValidationResultWithAggregation,
+    EntryPointAPIErrors, ExecutionResult, FailedOp, SenderAddressResult, UserOperation,
+    ValidationResult, ValidationResultWithAggregation,
 };
 use super::gen::stake_manager_api::DepositInfo;
 use super::gen::{EntryPointAPI, EntryPointAPIEvents, StakeManagerAPI};
-use super::tracer::JS_TRACER;
-use ethers::abi::AbiDecode;
-use ethers::prelude::{ContractError, Event};
-use ethers::providers::{Middleware, ProviderError};
+use super::tracer::{CallTracerFrame, ValidationTracer};
+use super::utils::parse_from_input_data;
+use ethers::abi::{AbiDecode, Detokenize};
+use ethers::prelude::{ContractCall, ContractError, Event};
+use ethers::providers::{call_raw::spoof, Middleware, ProviderError};
 use ethers::types::{
-    Address, Bytes, GethDebugTracerType, GethDebugTracingCallOptions, GethDebugTracingOptions,
-    GethTrace, TransactionRequest, U256,
+    Address, BlockNumber, Bytes, GethDebugTracingCallOptions, GethDebugTracingOptions, GethTrace,
+    H256, U256,
 };
 use ethers_providers::{JsonRpcError, MiddlewareError};
+use serde::Serialize;
+use serde_json::json;
 use thiserror::Error;
 use tracing::trace;
 
@@ -77,19 +80,51 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         }
     }
 
-    pub async fn simulate_validation<U: Into<UserOperation>>(
+    // eth_call that should revert with one of the entry point errors. The state override set
+    // isn't supported by the contract bindings, so eth_call is requested directly if it is set.
+    async fn call_with_state_override<D: Detokenize>(
         &self,
-        user_operation: U,
-    ) -> Result<SimulateValidationResult, EntryPointErr> {
+        call: ContractCall<M, D>,
+        state_override: &spoof::State,
+    ) -> Result<EntryPointAPIErrors, EntryPointErr> {
+        if *state_override == spoof::State::default() {
+            return match call.call().await {
+                Ok(_) => Err(EntryPointErr::UnknownErr(
+                    "Call should expect revert".to_string(),
+                )),
+                Err(e) => Self::deserialize_error_msg(e),
+            };
+        }
+
         let request_result = self
-            .entry_point_api
-            .simulate_validation(user_operation.into())
+            .provider
+            .provider()
+            .request::<_, Bytes>("eth_call", (call.tx, BlockNumber::Latest, state_override))
             .await;
         match request_result {
             Ok(_) => Err(EntryPointErr::UnknownErr(
-                "Simulate validation should expect revert".to_string(),
+                "Call should expect revert".to_string(),
             )),
-            Err(e) => Self::deserialize_error_msg(e).and_then(|op| match op {
+            Err(e) => match EntryPointErr::from(e) {
+                EntryPointErr::JsonRpcError(err) => err
+                    .decode_revert_data::<EntryPointAPIErrors>()
+                    .ok_or(EntryPointErr::JsonRpcError(err)),
+                error => Err(error),
+            },
+        }
+    }
+
+    pub async fn simulate_validation<U: Into<UserOperation>>(
+        &self,
+        user_operation: U,
+        state_override: &spoof::State,
+    ) -> Result<SimulateValidationResult, EntryPointErr> {
+        let call = self
+            .entry_point_api
+            .simulate_validation(user_operation.into());
+        self.call_with_state_override(call, state_override)
+            .await
+            .and_then(|op| match op {
                 EntryPointAPIErrors::FailedOp(failed_op) => Err(EntryPointErr::FailedOp(failed_op)),
                 EntryPointAPIErrors::ValidationResult(res) => {
                     Ok(SimulateValidationResult::ValidationResult(res))
@@ -100,36 +135,55 @@ impl<M: Middleware + 'static> EntryPoint<M> {
                 _ => Err(EntryPointErr::UnknownErr(format!(
                     "Simulate validation with invalid error: {op:?}"
                 ))),
-            }),
-        }
+            })
     }
 
     pub async fn simulate_validation_trace<U: Into<UserOperation>>(
         &self,
         user_operation: U,
+        state_override: &spoof::State,
+        tracer: ValidationTracer,
     ) -> Result<GethTrace, EntryPointErr> {
         let call = self
             .entry_point_api
             .simulate_validation(user_operation.into());
+        let tracing_options = GethDebugTracingOptions {
+            disable_storage: None,
+            disable_stack: None,
+            enable_memory: None,
+            enable_return_data: None,
+            tracer: Some(tracer.tracer_type()),
+            tracer_config: None,
+            timeout: None,
+        };
+
+        if *state_override == spoof::State::default() {
+            return self
+                .provider
+                .debug_trace_call(
+                    call.tx,
+                    None,
+                    GethDebugTracingCallOptions { tracing_options },
+                )
+                .await
+                .map_err(|e| EntryPointErr::from_middleware_err::<M>(e));
+        }
+
         let request_result = self
             .provider
-            .debug_trace_call(
-                call.tx,
-                None,
-                GethDebugTracingCallOptions {
-                    tracing_options: GethDebugTracingOptions {
-                        disable_storage: None,
-                        disable_stack: None,
-                        enable_memory: None,
-                        enable_return_data: None,
-                        tracer: Some(GethDebugTracerType::JsTracer(JS_TRACER.to_string())),
-                        tracer_config: None,
-                        timeout: None,
+            .provider()
+            .request(
+                "debug_traceCall",
+                (
+                    call.tx,
+                    BlockNumber::Latest,
+                    TraceCallOptions {
+                        tracing_options,
+                        state_overrides: state_override,
                     },
-                },
+                ),
             )
-            .await
-            .map_err(|e| EntryPointErr::from_middleware_err::<M>(e))?;
+            .await?;
         Ok(request_result)
     }
 
@@ -193,32 +247,64 @@ impl<M: Middleware + 'static> EntryPoint<M> {
         }
     }
 
-    pub async fn estimate_call_gas<U: Into<UserOperation>>(
+    // User operations of the bundle transaction sent to this entry point. If the entry point wasn't
+    // called directly (e.g. multicall or a smart contract bundler wallet), the transaction is
+    // traced with callTracer to find the (nested) calls to the entry point.
+    pub async fn get_user_operations(
         &self,
-        user_operation: U,
-    ) -> Result<U256, EntryPointErr> {
-        let user_operation = user_operation.into();
-
-        if user_operation.call_data.is_empty() {
-            Ok(U256::zero())
-        } else {
-            let result = self
-                .provider
-                .estimate_gas(
-                    &TransactionRequest::new()
-                        .from(self.address)
-                        .to(user_operation.sender)
-                        .data(user_operation.call_data.clone())
-                        .into(),
-                    None,
-                )
-                .await;
-            trace!("Estimate call gas on {user_operation:?} returned {result:?}");
-            match result {
-                Ok(gas) => Ok(gas),
-                Err(e) => Err(EntryPointErr::from_middleware_err::<M>(e)),
+        transaction_hash: H256,
+    ) -> Result<Vec<aa_bundler_primitives::UserOperation>, EntryPointErr> {
+        let transaction = self
+            .provider
+            .get_transaction(transaction_hash)
+            .await
+            .map_err(|e| EntryPointErr::from_middleware_err::<M>(e))?
+            .ok_or(EntryPointErr::UnknownErr(format!(
+                "Transaction {transaction_hash:?} not found"
+            )))?;
+        if transaction.to == Some(self.address) {
+            if let Some(user_operations) = parse_from_input_data(transaction.input) {
+                return Ok(user_operations);
             }
         }
+
+        let call_frame: CallTracerFrame = self
+            .provider
+            .provider()
+            .request(
+                "debug_traceTransaction",
+                (transaction_hash, json!({ "tracer": "callTracer" })),
+            )
+            .await?;
+        Ok(call_frame
+            .inputs_of_calls_to(self.address)
+            .into_iter()
+            .filter_map(parse_from_input_data)
+            .flatten()
+            .collect())
+    }
+
+    pub async fn simulate_handle_op<U: Into<UserOperation>>(
+        &self,
+        user_operation: U,
+        target: Address,
+        target_call_data: Bytes,
+        state_override: &spoof::State,
+    ) -> Result<ExecutionResult, EntryPointErr> {
+        let call = self.entry_point_api.simulate_handle_op(
+            user_operation.into(),
+            target,
+            target_call_data,
+        );
+        self.call_with_state_override(call, state_override)
+            .await
+            .and_then(|op| match op {
+                EntryPointAPIErrors::ExecutionResult(res) => Ok(res),
+                EntryPointAPIErrors::FailedOp(failed_op) => Err(EntryPointErr::FailedOp(failed_op)),
+                _ => Err(EntryPointErr::UnknownErr(format!(
+                    "Simulate handle op with invalid error: {op:?}"
+                ))),
+            })
     }
 
     pub async fn handle_aggregated_ops<U: Into<UserOperation>>(
@@ -280,6 +366,15 @@ impl EntryPointErr {
     }
 }
 
+// debug_traceCall options with the state override set
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceCallOptions<'a> {
+    #[serde(flatten)]
+    tracing_options: GethDebugTracingOptions,
+    state_overrides: &'a spoof::State,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SimulateValidationResult {
     ValidationResult(ValidationResult),
@@ -326,7 +421,7 @@ mod tests {
         };
 
         let simulate_validation = entry_point
-            .simulate_validation(user_operation.clone())
+            .simulate_validation(user_operation.clone(), &Default::default())
             .await
             .unwrap();
 
@@ -336,7 +431,7 @@ mod tests {
         ));
 
         let simulate_validation_trace = entry_point
-            .simulate_validation_trace(user_operation)
+            .simulate_validation_trace(user_operation, &Default::default(), Default::default())
             .await
             .unwrap();
 
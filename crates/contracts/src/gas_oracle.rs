@@ -0,0 +1,161 @@
+use std::{str::FromStr, sync::Arc};
+
+use aa_bundler_primitives::UserOperation;
+use anyhow::format_err;
+use ethers::{
+    contract::abigen,
+    providers::Middleware,
+    types::{Address, BlockNumber, U256},
+};
+
+use crate::utils::handle_ops_call_data;
+
+abigen!(
+    OptimismGasPriceOracle,
+    r#"[
+        function getL1Fee(bytes memory _data) external view returns (uint256)
+    ]"#
+);
+abigen!(
+    ArbitrumNodeInterface,
+    r#"[
+        function gasEstimateL1Component(address to, bool contractCreation, bytes calldata data) external payable returns (uint64 gasEstimateForL1, uint256 baseFee, uint256 l1BaseFeeEstimate)
+    ]"#
+);
+
+// https://github.com/ethereum-optimism/optimism/blob/develop/specs/predeploys.md#gaspriceoracle
+pub const OPTIMISM_GAS_PRICE_ORACLE: &str = "0x420000000000000000000000000000000000000F";
+// https://github.com/OffchainLabs/nitro/blob/master/nodeInterface/NodeInterface.go
+pub const ARBITRUM_NODE_INTERFACE: &str = "0x00000000000000000000000000000000000000C8";
+
+// Optimism, Optimism Goerli, Optimism Sepolia, Base, Base Goerli, Base Sepolia
+pub const OPTIMISM_CHAIN_IDS: [u64; 6] = [10, 420, 11155420, 8453, 84531, 84532];
+// Arbitrum One, Arbitrum Nova, Arbitrum Goerli, Arbitrum Sepolia
+pub const ARBITRUM_CHAIN_IDS: [u64; 4] = [42161, 42170, 421613, 421614];
+
+// Estimates the gas needed to pay for posting the user operation data to L1 (on rollups).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum L1GasOracle {
+    #[default]
+    None,
+    Optimism,
+    Arbitrum,
+}
+
+impl FromStr for L1GasOracle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "optimism" => Ok(Self::Optimism),
+            "arbitrum" => Ok(Self::Arbitrum),
+            _ => Err(format!(
+                "{s} is not a valid L1 gas oracle (none, optimism, arbitrum)"
+            )),
+        }
+    }
+}
+
+impl L1GasOracle {
+    pub fn from_chain_id(chain_id: U256) -> Self {
+        let chain_id = chain_id.as_u64();
+        if OPTIMISM_CHAIN_IDS.contains(&chain_id) {
+            Self::Optimism
+        } else if ARBITRUM_CHAIN_IDS.contains(&chain_id) {
+            Self::Arbitrum
+        } else {
+            Self::None
+        }
+    }
+
+    // L1 data cost of including the user operation in a bundle, expressed in L2 gas
+    pub async fn l1_gas<M: Middleware + 'static>(
+        &self,
+        eth_provider: Arc<M>,
+        entry_point: Address,
+        user_operation: &UserOperation,
+    ) -> anyhow::Result<U256> {
+        // bundle with a single user operation, sender is used as beneficiary placeholder
+        let call_data =
+            || handle_ops_call_data(vec![user_operation.clone()], user_operation.sender);
+
+        match self {
+            Self::None => Ok(U256::zero()),
+            Self::Optimism => {
+                let oracle = OptimismGasPriceOracle::new(
+                    OPTIMISM_GAS_PRICE_ORACLE.parse::<Address>()?,
+                    eth_provider.clone(),
+                );
+                let l1_fee = oracle
+                    .get_l1_fee(call_data())
+                    .call()
+                    .await
+                    .map_err(|error| format_err!("Failed to get L1 fee: {error:?}"))?;
+
+                // L1 fee is paid in wei, so it is converted to gas with the gas price of the user operation
+                let base_fee = eth_provider
+                    .get_block(BlockNumber::Latest)
+                    .await
+                    .map_err(|error| format_err!("Failed to get latest block: {error:?}"))?
+                    .and_then(|block| block.base_fee_per_gas)
+                    .unwrap_or_default();
+                let mut gas_price = user_operation
+                    .max_fee_per_gas
+                    .min(base_fee.saturating_add(user_operation.max_priority_fee_per_gas));
+                if gas_price.is_zero() {
+                    gas_price = eth_provider
+                        .get_gas_price()
+                        .await
+                        .map_err(|error| format_err!("Failed to get gas price: {error:?}"))?;
+                }
+                if gas_price.is_zero() {
+                    return Ok(U256::zero());
+                }
+
+                Ok((l1_fee + gas_price - 1) / gas_price)
+            }
+            Self::Arbitrum => {
+                let node_interface = ArbitrumNodeInterface::new(
+                    ARBITRUM_NODE_INTERFACE.parse::<Address>()?,
+                    eth_provider,
+                );
+                let (gas_estimate_for_l1, _, _) = node_interface
+                    .gas_estimate_l1_component(entry_point, false, call_data())
+                    .call()
+                    .await
+                    .map_err(|error| format_err!("Failed to estimate L1 gas: {error:?}"))?;
+
+                Ok(U256::from(gas_estimate_for_l1))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn l1_gas_oracle_from_chain_id() {
+        assert_eq!(L1GasOracle::from_chain_id(U256::from(1)), L1GasOracle::None);
+        assert_eq!(
+            L1GasOracle::from_chain_id(U256::from(10)),
+            L1GasOracle::Optimism
+        );
+        assert_eq!(
+            L1GasOracle::from_chain_id(U256::from(8453)),
+            L1GasOracle::Optimism
+        );
+        assert_eq!(
+            L1GasOracle::from_chain_id(U256::from(42161)),
+            L1GasOracle::Arbitrum
+        );
+        assert_eq!(
+            L1GasOracle::from_chain_id(U256::from(421614)),
+            L1GasOracle::Arbitrum
+        );
+        assert_eq!("arbitrum".parse(), Ok(L1GasOracle::Arbitrum));
+        assert!("polygon".parse::<L1GasOracle>().is_err());
+    }
+}
@@ -1,14 +1,25 @@
 #![allow(dead_code)]
 
 mod entry_point;
+mod gas_oracle;
 mod gen;
 mod tracer;
 mod utils;
 
 pub use entry_point::{EntryPoint, EntryPointErr, SimulateValidationResult};
+pub use gas_oracle::{
+    L1GasOracle, ARBITRUM_CHAIN_IDS, ARBITRUM_NODE_INTERFACE, OPTIMISM_CHAIN_IDS,
+    OPTIMISM_GAS_PRICE_ORACLE,
+};
 pub use gen::{
     EntryPointAPI, EntryPointAPIEvents, UserOperationEventFilter, ValidatePaymasterUserOpReturn,
     CONTRACTS_FUNCTIONS,
 };
-pub use tracer::{Call, CallEntry, JsTracerFrame, JS_TRACER};
-pub use utils::parse_from_input_data;
+pub use tracer::{
+    BundlerCollectorFrame, Call, CallEntry, CallTracerFrame, JsTracerFrame, Level, Log,
+    ReadsAndWrites, ValidationTracer, BUNDLER_COLLECTOR_TRACER, JS_TRACER,
+};
+pub use utils::{
+    decode_revert_reason, handle_ops_call_data, parse_from_input_data, user_operation_logs,
+    user_operation_revert_reason,
+};
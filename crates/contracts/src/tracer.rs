@@ -1,8 +1,49 @@
 use anyhow::format_err;
-use ethers::types::{Address, Bytes, GethTrace, U256};
+use ethers::types::{Address, Bytes, GethDebugTracerType, GethTrace, U256};
 use serde::Deserialize;
+use serde_json::Value;
 use std::collections::HashMap;
 
+// Name of the native tracer shipped by ERC-4337 enabled geth forks
+pub const BUNDLER_COLLECTOR_TRACER: &str = "bundlerCollectorTracer";
+
+// selectors of the calls from the entry point that start the validation of each entity (v0.6)
+// createSender(bytes)
+const CREATE_SENDER_SELECTOR: [u8; 4] = [0x57, 0x0e, 0x1a, 0x36];
+// validateUserOp(UserOperation,bytes32,uint256)
+const VALIDATE_USER_OP_SELECTOR: [u8; 4] = [0x3a, 0x87, 0x1c, 0xdd];
+// validatePaymasterUserOp(UserOperation,bytes32,uint256)
+const VALIDATE_PAYMASTER_USER_OP_SELECTOR: [u8; 4] = [0xf4, 0x65, 0xc7, 0x7e];
+
+// Tracer used with debug_traceCall to trace the validation of user operations
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationTracer {
+    // JS_TRACER, executed by the goja interpreter of geth
+    #[default]
+    Js,
+    // native bundlerCollectorTracer, output is normalized into JsTracerFrame
+    BundlerCollector,
+}
+
+impl ValidationTracer {
+    pub fn tracer_type(&self) -> GethDebugTracerType {
+        match self {
+            // geth looks up native tracers by name before evaluating the tracer as JS code
+            Self::Js => GethDebugTracerType::JsTracer(JS_TRACER.to_string()),
+            Self::BundlerCollector => {
+                GethDebugTracerType::JsTracer(BUNDLER_COLLECTOR_TRACER.to_string())
+            }
+        }
+    }
+
+    pub fn frame(&self, trace: GethTrace) -> anyhow::Result<JsTracerFrame> {
+        match self {
+            Self::Js => JsTracerFrame::try_from(trace),
+            Self::BundlerCollector => BundlerCollectorFrame::try_from(trace).map(Into::into),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
 pub struct JsTracerFrame {
     #[serde(rename = "numberLevels")]
@@ -30,6 +71,9 @@ pub struct Level {
     pub opcodes: HashMap<String, u64>,
     #[serde(rename = "contractSize")]
     pub contract_size: HashMap<Address, u64>,
+    // set if an opcode ran out of gas (only present in the trace if true)
+    #[serde(default)]
+    pub oog: bool,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
@@ -58,6 +102,138 @@ pub struct Call {
     pub value: Option<U256>,
 }
 
+// Output of the native bundlerCollectorTracer, where the validation is split into the top-level
+// calls from the entry point instead of the levels separated by the NUMBER opcode
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundlerCollectorFrame {
+    pub calls_from_entry_point: Vec<EntryPointCallInfo>,
+    #[serde(default)]
+    pub keccak: Vec<Bytes>,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+    #[serde(default)]
+    pub calls: Vec<Call>,
+    #[serde(default)]
+    pub debug: Vec<String>,
+}
+
+impl TryFrom<GethTrace> for BundlerCollectorFrame {
+    type Error = anyhow::Error;
+    fn try_from(value: GethTrace) -> Result<Self, Self::Error> {
+        match value {
+            GethTrace::Known(value) => Err(format_err!("invalid geth trace: {value:?}")),
+            GethTrace::Unknown(value) => serde_json::from_value(value)
+                .map_err(|error| format_err!("failed to parse geth trace: {error}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryPointCallInfo {
+    pub top_level_method_sig: Bytes,
+    pub top_level_target_address: Option<Address>,
+    #[serde(default)]
+    pub access: HashMap<Address, NativeAccessInfo>,
+    #[serde(default)]
+    pub opcodes: HashMap<String, u64>,
+    #[serde(default)]
+    pub contract_size: HashMap<Address, NativeContractSize>,
+    #[serde(default)]
+    pub oog: bool,
+}
+
+// the native tracer reports the read values (or the counts in older versions) of padded slots
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct NativeAccessInfo {
+    #[serde(default)]
+    pub reads: HashMap<String, Value>,
+    #[serde(default)]
+    pub writes: HashMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum NativeContractSize {
+    Size(u64),
+    #[serde(rename_all = "camelCase")]
+    WithOpcode {
+        contract_size: u64,
+        opcode: String,
+    },
+}
+
+impl NativeContractSize {
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Size(size) => *size,
+            Self::WithOpcode { contract_size, .. } => *contract_size,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<&str> {
+        match self {
+            Self::Size(_) => None,
+            Self::WithOpcode { opcode, .. } => Some(opcode),
+        }
+    }
+}
+
+// slots are hex numbers without padding and prefix in the JS tracer
+fn normalize_slot(slot: &str) -> String {
+    match U256::from_str_radix(slot.trim_start_matches("0x"), 16) {
+        Ok(slot) => format!("{slot:x}"),
+        Err(_) => slot.to_string(),
+    }
+}
+
+impl From<BundlerCollectorFrame> for JsTracerFrame {
+    fn from(frame: BundlerCollectorFrame) -> Self {
+        // factory, account, paymaster (same as the levels of the JS tracer)
+        let mut number_levels = vec![Level::default(); 3];
+        for call in frame.calls_from_entry_point {
+            let index = match call.top_level_method_sig.as_ref() {
+                sig if sig == CREATE_SENDER_SELECTOR => 0,
+                sig if sig == VALIDATE_USER_OP_SELECTOR => 1,
+                sig if sig == VALIDATE_PAYMASTER_USER_OP_SELECTOR => 2,
+                _ => continue,
+            };
+
+            let level = &mut number_levels[index];
+            for (address, access) in call.access {
+                let entry = level.access.entry(address).or_default();
+                for (slot, read) in access.reads {
+                    *entry.reads.entry(normalize_slot(&slot)).or_insert(0) +=
+                        read.as_u64().unwrap_or(1);
+                }
+                for (slot, count) in access.writes {
+                    *entry.writes.entry(normalize_slot(&slot)).or_insert(0) += count;
+                }
+            }
+            for (opcode, count) in call.opcodes {
+                *level.opcodes.entry(opcode).or_insert(0) += count;
+            }
+            for (address, contract_size) in call.contract_size {
+                // the address argument of CREATE2 isn't an accessed contract (as in the JS tracer)
+                if contract_size.opcode() == Some("CREATE2") {
+                    continue;
+                }
+                level.contract_size.insert(address, contract_size.size());
+            }
+            level.oog |= call.oog;
+        }
+
+        Self {
+            number_levels,
+            keccak: frame.keccak,
+            logs: frame.logs,
+            calls: frame.calls,
+            debug: frame.debug,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
 pub struct CallEntry {
     pub typ: String,
@@ -67,6 +243,33 @@ pub struct CallEntry {
     pub ret: Option<Bytes>,
     pub rev: Option<Bytes>,
     pub value: Option<U256>,
+    // addresses of the enclosing calls, outermost first
+    #[serde(default)]
+    pub path: Vec<Address>,
+}
+
+// Call frame of the geth built-in callTracer (only the fields needed to find the calls to a contract)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct CallTracerFrame {
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub input: Bytes,
+    #[serde(default)]
+    pub calls: Vec<CallTracerFrame>,
+}
+
+impl CallTracerFrame {
+    // Input data of all (nested) calls to the address, in the order of execution
+    pub fn inputs_of_calls_to(&self, address: Address) -> Vec<Bytes> {
+        let mut inputs = vec![];
+        if self.to == Some(address) {
+            inputs.push(self.input.clone());
+        }
+        for call in self.calls.iter() {
+            inputs.extend(call.inputs_of_calls_to(address));
+        }
+        inputs
+    }
 }
 
 // https://github.com/eth-infinitism/bundler/blob/main/packages/bundler/src/BundlerCollectorTracer.ts
@@ -160,7 +363,8 @@ pub const JS_TRACER: &str = r#"
                 this.countSlot(this.currentLevel.opcodes, opcode);
             }
         }
-        if (opcode.match(/^(EXT.*|CALL|CALLCODE|DELEGATECALL|STATICCALL|CREATE2)$/) != null) {
+        // CREATE2 isn't included, peek(1) is the init code offset and not an address
+        if (opcode.match(/^(EXT.*|CALL|CALLCODE|DELEGATECALL|STATICCALL)$/) != null) {
             // this.debug.push('op= opcode + ' last= this.lastOp + ' stacksize= log.stack.length())
             const idx = opcode.startsWith('EXT') ? 0 : 1;
             const addr = toAddress(log.stack.peek(idx).toString(16));
@@ -211,3 +415,92 @@ pub const JS_TRACER: &str = r#"
     }
 }
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_tracer_frame_inputs() {
+        let entry_point = Address::random();
+        let frame: CallTracerFrame = serde_json::from_value(serde_json::json!({
+            "type": "CALL",
+            "from": Address::random(),
+            "to": Address::random(),
+            "input": "0x01",
+            "calls": [
+                {"type": "CALL", "to": entry_point, "input": "0x02"},
+                {
+                    "type": "DELEGATECALL",
+                    "to": Address::random(),
+                    "input": "0x03",
+                    "calls": [{"type": "CALL", "to": entry_point, "input": "0x04"}]
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            frame.inputs_of_calls_to(entry_point),
+            vec![Bytes::from(vec![2]), Bytes::from(vec![4])]
+        );
+    }
+
+    #[test]
+    fn bundler_collector_frame_normalized() {
+        assert_eq!(
+            ethers::utils::id("createSender(bytes)"),
+            CREATE_SENDER_SELECTOR
+        );
+
+        let account = Address::random();
+        let paymaster = Address::random();
+        let salt = Address::random();
+        let frame: BundlerCollectorFrame = serde_json::from_value(serde_json::json!({
+            "callsFromEntryPoint": [
+                {
+                    "topLevelMethodSig": "0x3a871cdd",
+                    "topLevelTargetAddress": account,
+                    "access": {
+                        (format!("{account:?}")): {
+                            "reads": {
+                                "0x0000000000000000000000000000000000000000000000000000000000000001": "0x05"
+                            },
+                            "writes": {}
+                        }
+                    },
+                    "opcodes": {"SLOAD": 1, "CALL": 1},
+                    "contractSize": {
+                        (format!("{paymaster:?}")): {"contractSize": 100, "opcode": "CALL"},
+                        (format!("{salt:?}")): {"contractSize": 0, "opcode": "CREATE2"}
+                    }
+                },
+                {
+                    "topLevelMethodSig": "0xf465c77e",
+                    "topLevelTargetAddress": paymaster,
+                    "access": {},
+                    "opcodes": {"TIMESTAMP": 1},
+                    "contractSize": {},
+                    "oog": true
+                }
+            ],
+            "keccak": ["0x1234"],
+            "logs": [],
+            "calls": [{"type": "CALL", "from": account, "to": paymaster, "method": "0x12345678", "gas": 100, "value": "0x0"}]
+        }))
+        .unwrap();
+
+        let frame = JsTracerFrame::from(frame);
+        assert_eq!(frame.number_levels.len(), 3);
+        assert!(frame.number_levels[0].opcodes.is_empty());
+        assert_eq!(frame.number_levels[1].access[&account].reads["1"], 1);
+        assert_eq!(frame.number_levels[1].opcodes["CALL"], 1);
+        assert_eq!(frame.number_levels[1].contract_size[&paymaster], 100);
+        assert!(!frame.number_levels[1].contract_size.contains_key(&salt));
+        assert_eq!(frame.number_levels[2].opcodes["TIMESTAMP"], 1);
+        assert!(!frame.number_levels[1].oog);
+        assert!(frame.number_levels[2].oog);
+        assert_eq!(frame.keccak, vec![Bytes::from(vec![0x12, 0x34])]);
+        assert_eq!(frame.calls[0].to, Some(paymaster));
+    }
+}
@@ -1,7 +1,14 @@
 use aa_bundler_primitives::UserOperation;
-use ethers::{abi::AbiDecode, types::Bytes};
+use ethers::{
+    abi::{AbiDecode, AbiEncode, RawLog},
+    contract::{EthEvent, EthLogDecode},
+    types::{Address, Bytes, Log, H256, U256},
+};
 
-use crate::gen::entry_point_api::{self, EntryPointAPICalls};
+use crate::gen::entry_point_api::{
+    self, BeforeExecutionFilter, EntryPointAPICalls, HandleAggregatedOpsCall, HandleOpsCall,
+    UserOperationEventFilter, UserOperationRevertReasonFilter, UserOpsPerAggregator,
+};
 
 impl From<UserOperation> for entry_point_api::UserOperation {
     fn from(user_operation: UserOperation) -> Self {
@@ -39,6 +46,7 @@ impl From<entry_point_api::UserOperation> for UserOperation {
     }
 }
 
+// User operations of a handleOps or handleAggregatedOps call to the entry point
 pub fn parse_from_input_data(data: Bytes) -> Option<Vec<UserOperation>> {
     EntryPointAPICalls::decode(data)
         .ok()
@@ -46,13 +54,113 @@ pub fn parse_from_input_data(data: Bytes) -> Option<Vec<UserOperation>> {
             EntryPointAPICalls::HandleOps(ops) => {
                 Some(ops.ops.into_iter().map(|op| op.into()).collect())
             }
+            EntryPointAPICalls::HandleAggregatedOps(ops) => Some(
+                ops.ops_per_aggregator
+                    .into_iter()
+                    .flat_map(|ops| ops.user_ops)
+                    .map(|op| op.into())
+                    .collect(),
+            ),
             _ => None,
         })
 }
 
+// https://docs.soliditylang.org/en/latest/control-structures.html#panic-via-assert-and-error-via-require
+fn panic_reason(code: U256) -> String {
+    let description = match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic underflow or overflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to zero-initialized function",
+        _ => "unknown panic",
+    };
+    format!("Panic({code:#x}): {description}")
+}
+
+// Error(string) and Panic(uint256) revert data is decoded, anything else is returned as hex
+pub fn decode_revert_reason(data: &Bytes) -> String {
+    const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+    const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+    if data.len() >= 4 && data[..4] == ERROR_SELECTOR {
+        if let Ok(reason) = String::decode(&data[4..]) {
+            return reason;
+        }
+    }
+    if data.len() >= 4 && data[..4] == PANIC_SELECTOR {
+        if let Ok(code) = U256::decode(&data[4..]) {
+            return panic_reason(code);
+        }
+    }
+    format!("{data}")
+}
+
+// Logs of the user operation in the bundle transaction receipt: the logs after the previous
+// UserOperationEvent (or BeforeExecution), up to its own UserOperationEvent (excluded).
+pub fn user_operation_logs(
+    logs: &[Log],
+    entry_point: Address,
+    user_operation_hash: H256,
+) -> Vec<Log> {
+    let mut start = 0;
+    for (index, log) in logs.iter().enumerate() {
+        if log.address != entry_point {
+            continue;
+        }
+        match log.topics.first() {
+            Some(topic) if *topic == BeforeExecutionFilter::signature() => start = index + 1,
+            Some(topic) if *topic == UserOperationEventFilter::signature() => {
+                if log.topics.get(1) == Some(&user_operation_hash) {
+                    return logs[start..index].to_vec();
+                }
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    vec![]
+}
+
+// Decoded revert reason of the UserOperationRevertReason event, if the execution reverted. Only the
+// entry point emits the event, contracts called by the user operation could fake it.
+pub fn user_operation_revert_reason(
+    logs: &[Log],
+    entry_point: Address,
+    user_operation_hash: H256,
+) -> Option<String> {
+    logs.iter()
+        .filter(|log| {
+            log.address == entry_point
+                && log.topics.first() == Some(&UserOperationRevertReasonFilter::signature())
+                && log.topics.get(1) == Some(&user_operation_hash)
+        })
+        .find_map(|log| {
+            UserOperationRevertReasonFilter::decode_log(&RawLog {
+                topics: log.topics.clone(),
+                data: log.data.to_vec(),
+            })
+            .ok()
+        })
+        .map(|event| decode_revert_reason(&event.revert_reason))
+}
+
+pub fn handle_ops_call_data(user_operations: Vec<UserOperation>, beneficiary: Address) -> Bytes {
+    HandleOpsCall {
+        ops: user_operations.into_iter().map(Into::into).collect(),
+        beneficiary,
+    }
+    .encode()
+    .into()
+}
+
 #[cfg(test)]
 mod tests {
-    use ethers::types::Bytes;
+    use ethers::{abi::Token, types::Bytes};
     use std::str::FromStr;
 
     use super::*;
@@ -64,4 +172,129 @@ mod tests {
         let res = parse_from_input_data(data);
         assert!(matches!(res, Some(..)), "No user operation found")
     }
+
+    #[test]
+    fn parse_aggregated_input_data() {
+        let user_operation = |nonce: u64| UserOperation {
+            nonce: U256::from(nonce),
+            ..UserOperation::random()
+        };
+        let ops_per_aggregator = |user_operations: Vec<UserOperation>| UserOpsPerAggregator {
+            user_ops: user_operations.into_iter().map(Into::into).collect(),
+            aggregator: Address::random(),
+            signature: Bytes::default(),
+        };
+        let user_operations = vec![user_operation(1), user_operation(2), user_operation(3)];
+
+        let data: Bytes = HandleAggregatedOpsCall {
+            ops_per_aggregator: vec![
+                ops_per_aggregator(user_operations[..2].to_vec()),
+                ops_per_aggregator(user_operations[2..].to_vec()),
+            ],
+            beneficiary: Address::random(),
+        }
+        .encode()
+        .into();
+        assert_eq!(parse_from_input_data(data), Some(user_operations));
+
+        assert_eq!(
+            parse_from_input_data(Bytes::from_str("0xdeadbeef").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn decode_revert_reasons() {
+        let data = Bytes::from_str("0x08c379a0000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000067061757365640000000000000000000000000000000000000000000000000000").unwrap();
+        assert_eq!(decode_revert_reason(&data), "paused");
+        let data = Bytes::from_str(
+            "0x4e487b710000000000000000000000000000000000000000000000000000000000000011",
+        )
+        .unwrap();
+        assert_eq!(
+            decode_revert_reason(&data),
+            "Panic(0x11): arithmetic underflow or overflow"
+        );
+        assert_eq!(
+            decode_revert_reason(&Bytes::from_str("0xdeadbeef").unwrap()),
+            "0xdeadbeef"
+        );
+    }
+
+    #[test]
+    fn logs_of_user_operation() {
+        let entry_point = Address::random();
+        let user_operation_hash = H256::random();
+        let log = |address: Address, topics: Vec<H256>| Log {
+            address,
+            topics,
+            ..Default::default()
+        };
+        let user_operation_event = |hash: H256| {
+            log(
+                entry_point,
+                vec![UserOperationEventFilter::signature(), hash],
+            )
+        };
+
+        let logs = vec![
+            log(entry_point, vec![BeforeExecutionFilter::signature()]),
+            log(Address::random(), vec![]),
+            user_operation_event(H256::random()),
+            log(Address::random(), vec![H256::random()]),
+            log(Address::random(), vec![]),
+            user_operation_event(user_operation_hash),
+            user_operation_event(H256::random()),
+        ];
+
+        assert_eq!(
+            user_operation_logs(&logs, entry_point, user_operation_hash),
+            logs[3..5].to_vec()
+        );
+        assert!(user_operation_logs(&logs, entry_point, H256::random()).is_empty());
+        assert_eq!(
+            user_operation_revert_reason(&logs, entry_point, user_operation_hash),
+            None
+        );
+    }
+
+    #[test]
+    fn revert_reason_of_user_operation() {
+        let entry_point = Address::random();
+        let user_operation_hash = H256::random();
+        let revert_reason_event = |address: Address, reason: &str| Log {
+            address,
+            topics: vec![
+                UserOperationRevertReasonFilter::signature(),
+                user_operation_hash,
+                H256::from(Address::random()),
+            ],
+            data: ethers::abi::encode(&[
+                Token::Uint(U256::zero()),
+                Token::Bytes(
+                    [
+                        [0x08, 0xc3, 0x79, 0xa0].as_slice(),
+                        &ethers::abi::encode(&[Token::String(reason.to_string())]),
+                    ]
+                    .concat(),
+                ),
+            ])
+            .into(),
+            ..Default::default()
+        };
+
+        // an event emitted by a contract called by the user operation is ignored
+        let logs = vec![
+            revert_reason_event(Address::random(), "fake"),
+            revert_reason_event(entry_point, "paused"),
+        ];
+        assert_eq!(
+            user_operation_revert_reason(&logs, entry_point, user_operation_hash),
+            Some("paused".to_string())
+        );
+        assert_eq!(
+            user_operation_revert_reason(&logs[..1], entry_point, user_operation_hash),
+            None
+        );
+    }
 }
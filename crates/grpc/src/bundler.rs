@@ -1,10 +1,19 @@
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use aa_bundler_bundler::Bundler as BundlerCore;
-use aa_bundler_primitives::{parse_address, parse_u256, UserOperation, Wallet};
+use aa_bundler_bundler::{
+    Bundler as BundlerCore, ConditionalSubmitter, EthClientSubmitter, FlashbotsSubmitter,
+    PrivateRpcSubmitter, SendBundleMode, SharedSubmitter, DEFAULT_MAX_BLOCKS,
+};
+use aa_bundler_primitives::{parse_address, parse_u256, SharedSigner, StorageMap, UserOperation};
+use anyhow::format_err;
 use async_trait::async_trait;
 use clap::Parser;
-use ethers::types::{Address, H256, U256};
+use ethers::{
+    prelude::rand,
+    providers::{Http, Provider},
+    signers::LocalWallet,
+    types::{Address, H256, U256},
+};
 use parking_lot::Mutex;
 use tonic::Response;
 use tracing::{error, info, warn};
@@ -31,6 +40,61 @@ pub struct BundlerServiceOpts {
 
     #[clap(long, default_value = "10")]
     pub bundle_interval: u64,
+
+    // how bundle transactions are submitted: eth-client, private-rpc, flashbots or conditional
+    #[clap(long, default_value = "eth-client")]
+    pub send_bundle_mode: SendBundleMode,
+
+    #[clap(long)]
+    pub private_rpc_url: Option<String>,
+
+    #[clap(long)]
+    pub flashbots_relay_url: Option<String>,
+
+    // key used to sign requests to the relay, a random one is used if not provided
+    #[clap(long)]
+    pub flashbots_auth_key: Option<String>,
+
+    // number of blocks the bundle is (re)submitted for before giving up
+    #[clap(long, default_value_t = DEFAULT_MAX_BLOCKS)]
+    pub max_blocks: u64,
+}
+
+impl BundlerServiceOpts {
+    pub fn submitter(&self, eth_client_address: &str) -> anyhow::Result<SharedSubmitter> {
+        let provider = Provider::<Http>::try_from(eth_client_address)?;
+
+        Ok(match self.send_bundle_mode {
+            SendBundleMode::EthClient => Arc::new(EthClientSubmitter::new(provider)),
+            SendBundleMode::PrivateRpc => {
+                let private_rpc_url = self
+                    .private_rpc_url
+                    .as_ref()
+                    .ok_or_else(|| format_err!("Private rpc mode requires --private-rpc-url"))?;
+                Arc::new(
+                    PrivateRpcSubmitter::new(private_rpc_url, provider)?
+                        .max_blocks(self.max_blocks),
+                )
+            }
+            SendBundleMode::Conditional => {
+                Arc::new(ConditionalSubmitter::new(provider).max_blocks(self.max_blocks))
+            }
+            SendBundleMode::Flashbots => {
+                let relay_url = self
+                    .flashbots_relay_url
+                    .as_ref()
+                    .ok_or_else(|| format_err!("Flashbots mode requires --flashbots-relay-url"))?;
+                let auth_signer = match self.flashbots_auth_key.as_ref() {
+                    Some(key) => key.trim().parse::<LocalWallet>()?,
+                    None => LocalWallet::new(&mut rand::thread_rng()),
+                };
+                Arc::new(
+                    FlashbotsSubmitter::new(relay_url, auth_signer, provider)
+                        .max_blocks(self.max_blocks),
+                )
+            }
+        })
+    }
 }
 
 pub struct BundlerService {
@@ -46,22 +110,24 @@ fn is_running(running: Arc<Mutex<bool>>) -> bool {
 
 impl BundlerService {
     pub fn new(
-        wallet: Wallet,
+        signer: SharedSigner,
         beneficiary: Address,
         uopool_grpc_client: UoPoolClient<tonic::transport::Channel>,
         entry_points: Vec<Address>,
         chain_id: U256,
         eth_client_address: String,
+        submitter: SharedSubmitter,
     ) -> Self {
         let bundlers: Vec<BundlerCore> = entry_points
             .iter()
             .map(|entry_point| {
                 BundlerCore::new(
-                    wallet.clone(),
+                    signer.clone(),
                     beneficiary,
                     *entry_point,
                     chain_id,
                     eth_client_address.clone(),
+                    submitter.clone(),
                 )
             })
             .collect();
@@ -76,7 +142,7 @@ impl BundlerService {
     async fn create_bundle(
         uopool_grpc_client: &UoPoolClient<tonic::transport::Channel>,
         entry_point: &Address,
-    ) -> anyhow::Result<Vec<UserOperation>> {
+    ) -> anyhow::Result<(Vec<UserOperation>, StorageMap)> {
         let request = tonic::Request::new(GetSortedRequest {
             entry_point: Some((*entry_point).into()),
         });
@@ -84,13 +150,14 @@ impl BundlerService {
             .clone()
             .get_sorted_user_operations(request)
             .await?;
+        let response = response.into_inner();
+        let storage_map = response.storage_map();
         let user_operations: Vec<UserOperation> = response
-            .into_inner()
             .user_operations
             .into_iter()
             .map(|u| u.into())
             .collect();
-        Ok(user_operations)
+        Ok((user_operations, storage_map))
     }
 
     pub async fn send_bundles_now(&self) -> anyhow::Result<H256> {
@@ -99,9 +166,9 @@ impl BundlerService {
         for bundler in self.bundlers.iter() {
             info!("Sending bundle for entry point: {:?}", bundler.entry_point);
 
-            let bundle =
+            let (bundle, storage_map) =
                 Self::create_bundle(&self.uopool_grpc_client, &bundler.entry_point).await?;
-            let tx_hash = bundler.send_next_bundle(&bundle).await?;
+            let tx_hash = bundler.send_next_bundle(&bundle, &storage_map).await?;
 
             Self::handle_past_events(&self.uopool_grpc_client, &bundler.entry_point).await?;
 
@@ -169,8 +236,10 @@ impl BundlerService {
                         match Self::create_bundle(&uopool_grpc_client, &bundler_own.entry_point)
                             .await
                         {
-                            Ok(bundle) => {
-                                if let Err(e) = bundler_own.send_next_bundle(&bundle).await {
+                            Ok((bundle, storage_map)) => {
+                                if let Err(e) =
+                                    bundler_own.send_next_bundle(&bundle, &storage_map).await
+                                {
                                     error!("Error while sending bundle: {e:?}");
                                 }
                                 if let Err(e) = Self::handle_past_events(
@@ -288,8 +357,40 @@ mod tests {
                     3002
                 ),
                 bundle_interval: 10,
+                send_bundle_mode: SendBundleMode::EthClient,
+                private_rpc_url: None,
+                flashbots_relay_url: None,
+                flashbots_auth_key: None,
+                max_blocks: DEFAULT_MAX_BLOCKS,
             },
             BundlerServiceOpts::try_parse_from(args).unwrap()
         );
     }
+
+    #[test]
+    fn bundler_opts_flashbots() {
+        let args = vec![
+            "bundleropts",
+            "--beneficiary",
+            "0x690B9A9E9aa1C9dB991C7721a92d351Db4FaC990",
+            "--min-balance",
+            "1",
+            "--send-bundle-mode",
+            "flashbots",
+            "--flashbots-relay-url",
+            "https://relay.flashbots.net",
+            "--max-blocks",
+            "5",
+        ];
+        let opts = BundlerServiceOpts::try_parse_from(args).unwrap();
+        assert_eq!(opts.send_bundle_mode, SendBundleMode::Flashbots);
+        assert_eq!(opts.max_blocks, 5);
+        assert!(opts.submitter("http://127.0.0.1:8545").is_ok());
+
+        let opts = BundlerServiceOpts {
+            send_bundle_mode: SendBundleMode::PrivateRpc,
+            ..opts
+        };
+        assert!(opts.submitter("http://127.0.0.1:8545").is_err());
+    }
 }
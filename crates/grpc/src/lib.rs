@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 mod bundler;
+mod p2p;
 mod proto;
 mod uopool;
 
@@ -0,0 +1,274 @@
+use aa_bundler_contracts::EntryPointErr;
+use aa_bundler_p2p::{
+    MessageAcceptance, MessageId, Network, NetworkEvent, PeerId, PooledUserOpHashesRequest,
+    PooledUserOpHashesResponse, UserOperationsWithEntryPoint, MAX_OPS_PER_REQUEST,
+};
+use aa_bundler_primitives::{UserOperation, ENTITY_BANNED_ERROR_CODE};
+use aa_bundler_uopool::mempool_id;
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockNumber, H256, U256},
+};
+use tokio::sync::mpsc::{
+    self, error::TrySendError, Receiver, Sender, UnboundedReceiver, UnboundedSender,
+};
+use tracing::{debug, trace, warn};
+
+use crate::{proto::uopool::AddResult, uopool::UoPoolService};
+
+// received gossip messages waiting for verification, further ones are ignored
+const VERIFICATION_QUEUE_SIZE: usize = 1024;
+
+// Gossip message with user operations received from a peer
+struct ReceivedUserOperations {
+    peer_id: PeerId,
+    message_id: MessageId,
+    entry_point: Address,
+    user_operations: Vec<UserOperation>,
+}
+
+type ValidationResult = (MessageId, PeerId, MessageAcceptance);
+
+// Gossips the user operations added through RPC to the shared mempool and adds the user operations
+// received from peers (after verification) to the local mempools.
+pub async fn p2p_service_run<M: Middleware + 'static>(
+    mut network: Network,
+    uopool_service: UoPoolService<M>,
+    mut receiver: UnboundedReceiver<(Address, UserOperation)>,
+) where
+    EntryPointErr: From<<M as Middleware>::Error>,
+{
+    // received user operations are verified in a separate task, so the swarm keeps being polled.
+    // Gossip messages are only forwarded to other peers after the verification.
+    let (verification_sender, verification_receiver) = mpsc::channel(VERIFICATION_QUEUE_SIZE);
+    let (validation_result_sender, mut validation_result_receiver) = mpsc::unbounded_channel();
+    tokio::spawn(verify_user_operations(
+        uopool_service.clone(),
+        verification_receiver,
+        validation_result_sender,
+    ));
+
+    loop {
+        tokio::select! {
+            Some((entry_point, user_operation)) = receiver.recv() => {
+                publish_user_operation(&mut network, &uopool_service, entry_point, user_operation).await;
+            }
+            Some((message_id, peer_id, acceptance)) = validation_result_receiver.recv() => {
+                network.report_validation_result(&message_id, &peer_id, acceptance);
+            }
+            event = network.next_event() => {
+                handle_network_event(&mut network, &uopool_service, &verification_sender, event);
+            }
+        }
+    }
+}
+
+async fn verify_user_operations<M: Middleware + 'static>(
+    uopool_service: UoPoolService<M>,
+    mut receiver: Receiver<ReceivedUserOperations>,
+    validation_result_sender: UnboundedSender<ValidationResult>,
+) where
+    EntryPointErr: From<<M as Middleware>::Error>,
+{
+    while let Some(received) = receiver.recv().await {
+        let acceptance = verify_message(&uopool_service, &received).await;
+        if validation_result_sender
+            .send((received.message_id, received.peer_id, acceptance))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+// A message is rejected (and its peer penalized) if one of its user operations is invalid, and
+// accepted if a user operation is added to the mempool. Messages with only known user operations
+// or entities banned by the local reputation are ignored.
+async fn verify_message<M: Middleware + 'static>(
+    uopool_service: &UoPoolService<M>,
+    received: &ReceivedUserOperations,
+) -> MessageAcceptance
+where
+    EntryPointErr: From<<M as Middleware>::Error>,
+{
+    let peer_id = received.peer_id;
+    let entry_point = received.entry_point;
+    // user operations gossiped on the canonical topic only go to the canonical mempool
+    let mempool_id = mempool_id(&entry_point, &uopool_service.chain_id);
+
+    let mut acceptance = MessageAcceptance::Ignore;
+    for user_operation in received.user_operations.iter() {
+        let known = uopool_service
+            .mempools
+            .get(&mempool_id)
+            .map_or(false, |uopool| {
+                let user_operation_hash =
+                    user_operation.hash(&entry_point, &uopool_service.chain_id);
+                matches!(uopool.mempool.get(&user_operation_hash), Ok(Some(_)))
+            });
+        if known {
+            continue;
+        }
+
+        match uopool_service
+            .add_to_mempool(user_operation.clone(), entry_point, mempool_id)
+            .await
+        {
+            Ok(res) if res.result() == AddResult::Added => {
+                debug!("Added user operation {} from {peer_id}", res.data);
+                acceptance = MessageAcceptance::Accept;
+            }
+            Ok(res) => {
+                debug!("User operation from {peer_id} not added: {}", res.data);
+                let code = serde_json::from_str::<serde_json::Value>(&res.data)
+                    .ok()
+                    .and_then(|error| error["code"].as_i64());
+                if code != Some(i64::from(ENTITY_BANNED_ERROR_CODE)) {
+                    return MessageAcceptance::Reject;
+                }
+            }
+            Err(status) => {
+                warn!("Adding user operation from {peer_id} error: {status:?}");
+            }
+        }
+    }
+    acceptance
+}
+
+async fn publish_user_operation<M: Middleware + 'static>(
+    network: &mut Network,
+    uopool_service: &UoPoolService<M>,
+    entry_point: Address,
+    user_operation: UserOperation,
+) {
+    let verified_at_block_hash = match uopool_service
+        .eth_provider
+        .get_block(BlockNumber::Latest)
+        .await
+    {
+        Ok(block) => block.and_then(|block| block.hash).unwrap_or_default(),
+        Err(error) => {
+            warn!("Getting the latest block error: {error:?}");
+            H256::zero()
+        }
+    };
+
+    let user_operations = UserOperationsWithEntryPoint::new(
+        entry_point,
+        verified_at_block_hash,
+        uopool_service.chain_id,
+        vec![user_operation],
+    );
+    let mempool_id = mempool_id(&entry_point, &uopool_service.chain_id);
+    if let Err(error) = network.publish(&mempool_id, &user_operations) {
+        // publishing fails if there are no peers subscribed to the mempool
+        debug!("Gossiping user operation to mempool {mempool_id:?} error: {error:?}");
+    }
+}
+
+fn handle_network_event<M: Middleware + 'static>(
+    network: &mut Network,
+    uopool_service: &UoPoolService<M>,
+    verification_sender: &Sender<ReceivedUserOperations>,
+    event: NetworkEvent,
+) {
+    match event {
+        NetworkEvent::UserOperations {
+            peer_id,
+            message_id,
+            mempool_id: id,
+            user_operations,
+        } => {
+            let entry_point = user_operations.entry_point_contract;
+            if user_operations.chain_id != uopool_service.chain_id
+                || mempool_id(&entry_point, &uopool_service.chain_id) != id
+            {
+                warn!("User operations from {peer_id} don't belong to mempool {id:?}");
+                network.report_validation_result(&message_id, &peer_id, MessageAcceptance::Reject);
+                return;
+            }
+
+            let received = ReceivedUserOperations {
+                peer_id,
+                message_id,
+                entry_point,
+                user_operations: user_operations.user_operations(),
+            };
+            match verification_sender.try_send(received) {
+                Ok(()) => {}
+                Err(TrySendError::Full(received)) => {
+                    debug!("Verification queue is full, ignoring user operations from {peer_id}");
+                    network.report_validation_result(
+                        &received.message_id,
+                        &peer_id,
+                        MessageAcceptance::Ignore,
+                    );
+                }
+                Err(TrySendError::Closed(_)) => {
+                    warn!("Verification of received user operations stopped");
+                }
+            }
+        }
+        NetworkEvent::PooledUserOpHashesRequest {
+            peer_id,
+            request,
+            channel,
+        } => {
+            trace!("Pooled user operation hashes request from {peer_id}: {request:?}");
+            let response = pooled_user_op_hashes(uopool_service, &request);
+            if let Err(error) = network.respond_pooled_user_op_hashes(channel, response) {
+                warn!("{error:?}");
+            }
+        }
+        NetworkEvent::PooledUserOpHashesResponse {
+            peer_id, response, ..
+        } => {
+            trace!("Pooled user operation hashes response from {peer_id}: {response:?}");
+        }
+        event => trace!("P2P network event: {event:?}"),
+    }
+}
+
+// The cursor is the offset into the sorted hashes of all canonical mempools
+fn pooled_user_op_hashes<M: Middleware + 'static>(
+    uopool_service: &UoPoolService<M>,
+    request: &PooledUserOpHashesRequest,
+) -> PooledUserOpHashesResponse {
+    let mut hashes: Vec<H256> = uopool_service
+        .mempools
+        .iter()
+        // the alternative mempools aren't shared
+        .filter(|uopool| {
+            mempool_id(&uopool.entry_point.address(), &uopool_service.chain_id) == *uopool.key()
+        })
+        .flat_map(|uopool| {
+            let entry_point = uopool.entry_point.address();
+            uopool
+                .mempool
+                .get_all()
+                .into_iter()
+                .map(|user_operation| {
+                    user_operation
+                        .hash(&entry_point, &uopool_service.chain_id)
+                        .into()
+                })
+                .collect::<Vec<H256>>()
+        })
+        .collect();
+    hashes.sort();
+
+    let start = U256::from_big_endian(request.cursor.as_bytes())
+        .min(U256::from(hashes.len()))
+        .as_usize();
+    let end = (start + MAX_OPS_PER_REQUEST).min(hashes.len());
+    let next_cursor = if end < hashes.len() {
+        H256::from_low_u64_be(end as u64)
+    } else {
+        H256::zero()
+    };
+
+    PooledUserOpHashesResponse {
+        next_cursor,
+        hashes: hashes[start..end].to_vec(),
+    }
+}
@@ -290,7 +290,83 @@ pub mod types {
 }
 
 pub mod uopool {
+    use aa_bundler_primitives::{
+        StorageMap, UserOperationGasPrice, UserOperationGasPriceSuggestion,
+    };
+
     tonic::include_proto!("uopool");
+
+    impl From<UserOperationGasPrice> for GasPrice {
+        fn from(value: UserOperationGasPrice) -> Self {
+            Self {
+                max_fee_per_gas: Some(value.max_fee_per_gas.into()),
+                max_priority_fee_per_gas: Some(value.max_priority_fee_per_gas.into()),
+            }
+        }
+    }
+
+    impl From<GasPrice> for UserOperationGasPrice {
+        fn from(value: GasPrice) -> Self {
+            Self {
+                max_fee_per_gas: value.max_fee_per_gas.map(Into::into).unwrap_or_default(),
+                max_priority_fee_per_gas: value
+                    .max_priority_fee_per_gas
+                    .map(Into::into)
+                    .unwrap_or_default(),
+            }
+        }
+    }
+
+    impl From<UserOperationGasPriceSuggestion> for GetUserOperationGasPriceResponse {
+        fn from(value: UserOperationGasPriceSuggestion) -> Self {
+            Self {
+                slow: Some(value.slow.into()),
+                standard: Some(value.standard.into()),
+                fast: Some(value.fast.into()),
+            }
+        }
+    }
+
+    impl From<GetUserOperationGasPriceResponse> for UserOperationGasPriceSuggestion {
+        fn from(value: GetUserOperationGasPriceResponse) -> Self {
+            Self {
+                slow: value.slow.map(Into::into).unwrap_or_default(),
+                standard: value.standard.map(Into::into).unwrap_or_default(),
+                fast: value.fast.map(Into::into).unwrap_or_default(),
+            }
+        }
+    }
+
+    impl GetSortedResponse {
+        pub fn storage_map(&self) -> StorageMap {
+            self.storage_map
+                .iter()
+                .filter_map(|storage_slots| {
+                    storage_slots.address.clone().map(|address| {
+                        (
+                            address.into(),
+                            storage_slots
+                                .slots
+                                .iter()
+                                .cloned()
+                                .map(Into::into)
+                                .collect(),
+                        )
+                    })
+                })
+                .collect()
+        }
+    }
+
+    pub fn storage_map_to_proto(storage_map: StorageMap) -> Vec<StorageSlots> {
+        storage_map
+            .into_iter()
+            .map(|(address, slots)| StorageSlots {
+                address: Some(address.into()),
+                slots: slots.into_iter().map(Into::into).collect(),
+            })
+            .collect()
+    }
 }
 
 pub mod bundler {
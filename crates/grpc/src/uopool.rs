@@ -1,41 +1,50 @@
 use std::{
     collections::{HashMap, HashSet},
     net::SocketAddr,
-    sync::Arc,
+    path::PathBuf,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
 use aa_bundler_contracts::{
-    parse_from_input_data, EntryPoint, EntryPointAPIEvents, EntryPointErr,
-    SimulateValidationResult, UserOperationEventFilter,
+    user_operation_logs, user_operation_revert_reason, EntryPoint, EntryPointAPIEvents,
+    EntryPointErr, L1GasOracle, SimulateValidationResult, UserOperationEventFilter,
 };
+use aa_bundler_p2p::{Config, Multiaddr, Network};
 use aa_bundler_primitives::{
-    get_addr, parse_u256, ReputationStatus, SimulationError, UserOperation,
-    UserOperationGasEstimation, BAN_SLACK, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLED_MAX_INCLUDE,
-    THROTTLING_SLACK,
+    get_addr, parse_u256, ReputationStatus, SanityCheckError, SimulationError, StorageMap,
+    UserOperation, UserOperationGasEstimation, UserOperationHash, BAN_SLACK,
+    MIN_INCLUSION_RATE_DENOMINATOR, THROTTLED_MAX_INCLUDE, THROTTLING_SLACK,
 };
 use aa_bundler_uopool::{
-    canonical::simulation::SimulateValidationError, mempool_id, MemoryMempool, MemoryReputation,
-    MempoolId, Overhead, Reputation, UoPool as UserOperationPool,
+    canonical::simulation::SimulateValidationError, mempool_id, DatabaseUserOperationIndex,
+    MemoryMempool, MemoryReputation, MempoolId, NoWriteMap, Overhead, Reputation, RuleSet,
+    RuleSetRegistry, Simulator, UoPool as UserOperationPool, UserOperationLocation,
+    DEFAULT_VERIFICATION_GAS_MARGIN,
 };
 use anyhow::Result;
 use async_trait::async_trait;
 use clap::Parser;
 use dashmap::DashMap;
+use dirs::home_dir;
 use ethers::{
+    abi::RawLog,
+    contract::EthLogDecode,
     prelude::LogMeta,
-    providers::{Http, Middleware, Provider},
+    providers::{call_raw::spoof, Http, Middleware, Provider},
     types::{Address, H256, U256, U64},
 };
+use tokio::sync::mpsc::{self, UnboundedSender};
 use tonic::Response;
 use tracing::{debug, info, trace, warn};
 
 const LATEST_SCAN_DEPTH: u64 = 1000;
 
+use crate::p2p::p2p_service_run;
 use crate::proto::types::{GetChainIdResponse, GetSupportedEntryPointsResponse};
 use crate::proto::uopool::*;
 
-#[derive(Clone, Copy, Debug, Parser, PartialEq)]
+#[derive(Clone, Debug, Parser, PartialEq)]
 pub struct UoPoolServiceOpts {
     #[clap(long, default_value = "127.0.0.1:3001")]
     pub uopool_grpc_listen_address: SocketAddr,
@@ -48,12 +57,124 @@ pub struct UoPoolServiceOpts {
 
     #[clap(long, value_parser=parse_u256, default_value = "0")]
     pub min_priority_fee_per_gas: U256,
+
+    // L1 data cost oracle (none, optimism, arbitrum), detected from chain id if not set
+    #[clap(long)]
+    pub l1_gas_oracle: Option<L1GasOracle>,
+
+    // safety margin (in percent) added to the estimated verification gas limit
+    #[clap(long, default_value_t = DEFAULT_VERIFICATION_GAS_MARGIN)]
+    pub verification_gas_margin: u64,
+
+    // how the validation is traced (js-tracer and native-tracer: debug_traceCall with the JS or the
+    // native bundlerCollectorTracer, revm: in-process execution)
+    #[clap(long, default_value = "js-tracer")]
+    pub simulator: Simulator,
+
+    // JSON file with the entry points and validation rules of alternative mempools by name
+    #[clap(long)]
+    pub validation_rules: Option<PathBuf>,
+
+    // JSON file with pre-verification gas overhead presets by chain id
+    #[clap(long)]
+    pub overhead_config: Option<PathBuf>,
+
+    // overrides of single overhead parameters (applied on top of the preset)
+    #[clap(long, value_parser=parse_u256)]
+    pub overhead_fixed: Option<U256>,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub overhead_per_user_op: Option<U256>,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub overhead_per_user_op_word: Option<U256>,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub overhead_zero_byte: Option<U256>,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub overhead_non_zero_byte: Option<U256>,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub overhead_bundle_size: Option<U256>,
+
+    #[clap(long, value_parser=parse_u256)]
+    pub overhead_sig_size: Option<U256>,
+
+    // directory of the user operation index database (defaults to ~/.aa-bundler/db)
+    #[clap(long)]
+    pub datadir: Option<PathBuf>,
+
+    // joins the shared mempool p2p network if set (e.g. /ip4/0.0.0.0/tcp/4337)
+    #[clap(long)]
+    pub p2p_listen_address: Option<Multiaddr>,
+
+    #[clap(long, value_delimiter = ',')]
+    pub p2p_bootnodes: Vec<Multiaddr>,
+}
+
+impl UoPoolServiceOpts {
+    pub fn overhead(&self, chain_id: U256) -> Result<Overhead> {
+        let mut overhead = match self.overhead_config.as_ref() {
+            Some(path) => Overhead::from_config_file(path, chain_id)?,
+            None => Overhead::from_chain_id(chain_id),
+        };
+
+        for (value, parameter) in [
+            (self.overhead_fixed, &mut overhead.fixed),
+            (self.overhead_per_user_op, &mut overhead.per_user_op),
+            (
+                self.overhead_per_user_op_word,
+                &mut overhead.per_user_op_word,
+            ),
+            (self.overhead_zero_byte, &mut overhead.zero_byte),
+            (self.overhead_non_zero_byte, &mut overhead.non_zero_byte),
+            (self.overhead_bundle_size, &mut overhead.bundle_size),
+            (self.overhead_sig_size, &mut overhead.sig_size),
+        ] {
+            if let Some(value) = value {
+                *parameter = value;
+            }
+        }
+
+        if overhead.bundle_size.is_zero() {
+            return Err(anyhow::format_err!(
+                "Overhead bundle size must be greater than 0"
+            ));
+        }
+
+        Ok(overhead)
+    }
+
+    pub fn datadir(&self) -> Result<PathBuf> {
+        match &self.datadir {
+            Some(datadir) => Ok(datadir.clone()),
+            None => home_dir()
+                .map(|home| home.join(".aa-bundler").join("db"))
+                .ok_or_else(|| anyhow::format_err!("Get Home directory error")),
+        }
+    }
 }
 
 pub struct UoPoolService<M: Middleware> {
     pub mempools: Arc<DashMap<MempoolId, UserOperationPool<M>>>,
     pub eth_provider: Arc<M>,
     pub chain_id: U256,
+    pub user_operation_index: Arc<DatabaseUserOperationIndex<NoWriteMap>>,
+    // user operations added through RPC are gossiped to the p2p network
+    pub p2p_sender: Option<UnboundedSender<(Address, UserOperation)>>,
+}
+
+impl<M: Middleware> Clone for UoPoolService<M> {
+    fn clone(&self) -> Self {
+        Self {
+            mempools: self.mempools.clone(),
+            eth_provider: self.eth_provider.clone(),
+            chain_id: self.chain_id,
+            user_operation_index: self.user_operation_index.clone(),
+            p2p_sender: self.p2p_sender.clone(),
+        }
+    }
 }
 
 impl<M: Middleware + 'static> UoPoolService<M> {
@@ -61,30 +182,240 @@ impl<M: Middleware + 'static> UoPoolService<M> {
         mempools: Arc<DashMap<MempoolId, UserOperationPool<M>>>,
         eth_provider: Arc<M>,
         chain_id: U256,
+        user_operation_index: Arc<DatabaseUserOperationIndex<NoWriteMap>>,
+        p2p_sender: Option<UnboundedSender<(Address, UserOperation)>>,
     ) -> Self {
         Self {
             mempools,
             eth_provider,
             chain_id,
+            user_operation_index,
+            p2p_sender,
         }
     }
 
+    // The canonical mempool of the entry point first, then its alternative mempools
+    fn mempool_ids(&self, entry_point: &Address) -> Vec<MempoolId> {
+        let canonical_id = mempool_id(entry_point, &self.chain_id);
+        let mut ids: Vec<MempoolId> = self
+            .mempools
+            .iter()
+            .filter(|uopool| {
+                uopool.entry_point.address() == *entry_point && *uopool.key() != canonical_id
+            })
+            .map(|uopool| *uopool.key())
+            .collect();
+        ids.sort();
+        ids.insert(0, canonical_id);
+        ids
+    }
+
+    // Adds the user operation to the canonical mempool of the entry point, or to the first
+    // alternative mempool which relaxes the violated validation rule. Returns the id of the mempool.
+    pub async fn add_user_operation(
+        &self,
+        user_operation: UserOperation,
+        entry_point: Address,
+    ) -> Result<(AddResponse, MempoolId), tonic::Status> {
+        let canonical_id = mempool_id(&entry_point, &self.chain_id);
+        let (res, violation) = self
+            .verify_and_add(user_operation.clone(), entry_point, canonical_id)
+            .await?;
+
+        // other rejections (sanity check, signature, reputation, ...) apply to all mempools
+        if let Some(violation) = violation {
+            for mempool_id in self.mempool_ids(&entry_point).into_iter().skip(1) {
+                let relaxes = self
+                    .mempools
+                    .get(&mempool_id)
+                    .map_or(false, |uopool| uopool.rules.relaxes(&violation));
+                if !relaxes {
+                    continue;
+                }
+
+                let (res, _) = self
+                    .verify_and_add(user_operation.clone(), entry_point, mempool_id)
+                    .await?;
+                if res.result() == AddResult::Added {
+                    return Ok((res, mempool_id));
+                }
+            }
+        }
+
+        // the rejection by the canonical rules is reported
+        Ok((res, canonical_id))
+    }
+
+    // Verifies the user operation and adds it to the mempool
+    pub async fn add_to_mempool(
+        &self,
+        user_operation: UserOperation,
+        entry_point: Address,
+        mempool_id: MempoolId,
+    ) -> Result<AddResponse, tonic::Status> {
+        self.verify_and_add(user_operation, entry_point, mempool_id)
+            .await
+            .map(|(res, _)| res)
+    }
+
+    // The violated validation rule is returned if the simulation rejected the user operation
+    async fn verify_and_add(
+        &self,
+        user_operation: UserOperation,
+        entry_point: Address,
+        mempool_id: MempoolId,
+    ) -> Result<(AddResponse, Option<SimulateValidationError>), tonic::Status> {
+        let mut res = AddResponse::default();
+
+        let verification_result = {
+            let uopool = self
+                .mempools
+                .get(&mempool_id)
+                .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
+            match uopool.validate_user_operation(&user_operation).await {
+                Ok(sanity_check_result) => uopool
+                    .simulate_user_operation(&user_operation, &Default::default())
+                    .await
+                    .map(|simulation_result| (sanity_check_result, simulation_result))
+                    .map_err(|error| {
+                        let violation = error.rule_id().map(|_| error.clone());
+                        (SimulationError::from(error), violation)
+                    }),
+                Err(error) => Err((SanityCheckError::from(error), None)),
+            }
+        };
+
+        match verification_result {
+            Ok((sanity_check_result, simulation_result)) => {
+                let mut uopool = self
+                    .mempools
+                    .get_mut(&mempool_id)
+                    .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
+
+                if let Some(user_operation_hash) = sanity_check_result.user_operation_hash {
+                    uopool
+                        .remove_user_operation(&user_operation_hash)
+                        .unwrap_or_else(|| {
+                            trace!(
+                                "Unable to remove user operation {:?} from mempool {:?}",
+                                user_operation_hash,
+                                mempool_id
+                            )
+                        });
+                }
+
+                match uopool.mempool.add_with_code_hashes(
+                    user_operation.clone(),
+                    &entry_point,
+                    &self.chain_id,
+                    &simulation_result.code_hashes,
+                ) {
+                    Ok(user_operation_hash) => {
+                        // TODO: update reputation
+
+                        res.set_result(AddResult::Added);
+                        res.data = serde_json::to_string(&user_operation_hash)
+                            .map_err(|_| tonic::Status::internal("error adding user operation"))?;
+                    }
+                    Err(error) => {
+                        res.set_result(AddResult::NotAdded);
+                        res.data = serde_json::to_string(&error.to_string())
+                            .map_err(|_| tonic::Status::internal("error adding user operation"))?;
+                    }
+                }
+            }
+            Err((error, violation)) => {
+                res.set_result(AddResult::NotAdded);
+                res.data = serde_json::to_string(&error)
+                    .map_err(|_| tonic::Status::internal("error adding user operation"))?;
+                return Ok((res, violation));
+            }
+        }
+
+        Ok((res, None))
+    }
+
+    // The event is looked up in the receipt of the indexed transaction. If the user operation isn't
+    // indexed (or the block was reorged), the logs of recent blocks are queried.
     pub async fn find_user_operation_event(
         &self,
         user_operation_hash: H256,
     ) -> anyhow::Result<Option<(UserOperationEventFilter, LogMeta)>> {
+        if let Some(location) = self
+            .user_operation_index
+            .get(&user_operation_hash.into())
+            .map_err(|e| anyhow::format_err!("Getting user operation location error: {e:?}"))?
+        {
+            if let Some(event) = self.get_indexed_user_operation_event(location).await? {
+                return Ok(Some(event));
+            }
+        }
+
+        let latest_block = self.eth_provider.get_block_number().await?;
+        let from_block = latest_block
+            .checked_sub(U64::from(LATEST_SCAN_DEPTH))
+            .unwrap_or(U64::from(0));
+
         let mut event: Option<(UserOperationEventFilter, LogMeta)> = None;
         for pool in self.mempools.iter() {
             if let Some(res) = pool
-                .get_user_operation_event_meta(user_operation_hash)
+                .get_user_operation_event_meta(user_operation_hash, from_block)
                 .await?
             {
                 event = Some(res);
                 break;
             }
         }
+
+        if let Some((_, log_meta)) = &event {
+            self.index_user_operation(&user_operation_hash.into(), log_meta);
+        }
         Ok(event)
     }
+
+    async fn get_indexed_user_operation_event(
+        &self,
+        location: UserOperationLocation,
+    ) -> anyhow::Result<Option<(UserOperationEventFilter, LogMeta)>> {
+        let transaction_receipt = self
+            .eth_provider
+            .get_transaction_receipt(location.transaction_hash)
+            .await?;
+
+        Ok(transaction_receipt
+            .filter(|receipt| receipt.block_hash == Some(location.block_hash))
+            .and_then(|receipt| {
+                let log = receipt
+                    .logs
+                    .into_iter()
+                    .find(|log| log.log_index == Some(location.log_index))?;
+                let event = UserOperationEventFilter::decode_log(&RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.to_vec(),
+                })
+                .ok()?;
+                Some((
+                    event,
+                    LogMeta {
+                        address: log.address,
+                        block_number: location.block_number.into(),
+                        block_hash: location.block_hash,
+                        transaction_hash: location.transaction_hash,
+                        transaction_index: receipt.transaction_index,
+                        log_index: location.log_index,
+                    },
+                ))
+            }))
+    }
+
+    fn index_user_operation(&self, user_operation_hash: &UserOperationHash, log_meta: &LogMeta) {
+        if let Err(e) = self
+            .user_operation_index
+            .insert(user_operation_hash, log_meta.into())
+        {
+            warn!("Failed to index user operation {user_operation_hash:?}: {e:?}");
+        }
+    }
 }
 
 #[async_trait]
@@ -97,7 +428,6 @@ where
         request: tonic::Request<AddRequest>,
     ) -> Result<Response<AddResponse>, tonic::Status> {
         let req = request.into_inner();
-        let mut res = AddResponse::default();
 
         if let AddRequest {
             uo: Some(user_operation),
@@ -112,69 +442,16 @@ where
                 .try_into()
                 .map_err(|_| tonic::Status::invalid_argument("invalid entry point"))?;
 
-            let mempool_id = mempool_id(&entry_point, &self.chain_id);
-
-            let verification_result = {
-                let uopool = self
-                    .mempools
-                    .get(&mempool_id)
-                    .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
-                uopool.verify_user_operation(&user_operation).await
-            };
-
-            match verification_result {
-                Ok(verification_result) => {
-                    let mut uopool = self.mempools.get_mut(&mempool_id).ok_or_else(|| {
-                        tonic::Status::invalid_argument("entry point not supported")
-                    })?;
+            let (res, id) = self
+                .add_user_operation(user_operation.clone(), entry_point)
+                .await?;
 
-                    if let Some(user_operation_hash) =
-                        verification_result.sanity_check_result.user_operation_hash
-                    {
-                        uopool
-                            .remove_user_operation(&user_operation_hash)
-                            .unwrap_or_else(|| {
-                                trace!(
-                                    "Unable to remove user operation {:?} from mempool {:?}",
-                                    user_operation_hash,
-                                    mempool_id
-                                )
-                            });
-                    }
-
-                    match uopool
-                        .mempool
-                        .add(user_operation.clone(), &entry_point, &self.chain_id)
-                    {
-                        Ok(_) => {
-                            // TODO: find better way to atomically store user operation and code hashes
-                            match uopool.mempool.set_code_hashes(
-                                &user_operation.hash(&entry_point, &self.chain_id),
-                                &verification_result.simulation_result.code_hashes,
-                            ) {
-                                Ok(()) | Err(_) => {}
-                            }
-
-                            // TODO: update reputation
-
-                            res.set_result(AddResult::Added);
-                            res.data = serde_json::to_string(
-                                &user_operation.hash(&entry_point, &self.chain_id),
-                            )
-                            .map_err(|_| tonic::Status::internal("error adding user operation"))?;
-                        }
-                        Err(error) => {
-                            res.set_result(AddResult::NotAdded);
-                            res.data = serde_json::to_string(&error.to_string()).map_err(|_| {
-                                tonic::Status::internal("error adding user operation")
-                            })?;
-                        }
-                    }
-                }
-                Err(error) => {
-                    res.set_result(AddResult::NotAdded);
-                    res.data = serde_json::to_string(&error)
-                        .map_err(|_| tonic::Status::internal("error adding user operation"))?;
+            // only the user operations of the canonical mempool are gossiped
+            if res.result() == AddResult::Added && id == mempool_id(&entry_point, &self.chain_id) {
+                if let Some(p2p_sender) = &self.p2p_sender {
+                    p2p_sender
+                        .send((entry_point, user_operation))
+                        .unwrap_or_else(|e| warn!("Failed to gossip user operation: {e:?}"));
                 }
             }
 
@@ -198,25 +475,28 @@ where
             let entry_point = entry_point
                 .try_into()
                 .map_err(|_| tonic::Status::invalid_argument("invalid entry point"))?;
-            let mempool_id = mempool_id(&entry_point, &self.chain_id);
-
-            let mut uopool = self
-                .mempools
-                .get_mut(&mempool_id)
-                .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
+            let mempool_ids = self.mempool_ids(&entry_point);
+            if !self.mempools.contains_key(&mempool_ids[0]) {
+                return Err(tonic::Status::invalid_argument("entry point not supported"));
+            }
 
             for hash in hashes {
                 let hash: H256 = hash
                     .try_into()
                     .map_err(|_| tonic::Status::invalid_argument("invalid user operation hash"))?;
 
-                match uopool.mempool.remove(&hash.into()) {
-                    Ok(_) => {}
-                    Err(_) => {
-                        return Ok(tonic::Response::new(RemoveResponse {
-                            result: RemoveResult::NotRemoved as i32,
-                        }));
-                    }
+                // the user operation is in one of the mempools of the entry point
+                let removed = mempool_ids.iter().any(|mempool_id| {
+                    self.mempools
+                        .get_mut(mempool_id)
+                        .map_or(false, |mut uopool| {
+                            uopool.mempool.remove(&hash.into()).is_ok()
+                        })
+                });
+                if !removed {
+                    return Ok(tonic::Response::new(RemoveResponse {
+                        result: RemoveResult::NotRemoved as i32,
+                    }));
                 }
             }
 
@@ -247,6 +527,10 @@ where
             eps: self
                 .mempools
                 .iter()
+                // alternative mempools share the entry point of a canonical mempool
+                .filter(|mempool| {
+                    mempool_id(&mempool.entry_point.address(), &self.chain_id) == *mempool.key()
+                })
                 .map(|mempool| mempool.value().entry_point.address().into())
                 .collect(),
         }))
@@ -262,6 +546,7 @@ where
         if let EstimateUserOperationGasRequest {
             uo: Some(user_operation),
             ep: Some(entry_point),
+            state_override,
         } = req
         {
             let user_operation: UserOperation = user_operation
@@ -270,6 +555,12 @@ where
             let entry_point: Address = entry_point
                 .try_into()
                 .map_err(|_| tonic::Status::invalid_argument("invalid entry point"))?;
+            let state_override: spoof::State = if state_override.is_empty() {
+                spoof::State::default()
+            } else {
+                serde_json::from_str(&state_override)
+                    .map_err(|_| tonic::Status::invalid_argument("invalid state override"))?
+            };
 
             let mempool_id = mempool_id(&entry_point, &self.chain_id);
 
@@ -278,24 +569,51 @@ where
                 .get(&mempool_id)
                 .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
 
-            match uopool.simulate_user_operation(&user_operation).await {
-                Ok(simulation_result) => {
+            match uopool
+                .simulate_user_operation(&user_operation, &state_override)
+                .await
+            {
+                Ok(_) => {
                     let pre_verification_gas =
-                        Overhead::default().calculate_pre_verification_gas(&user_operation);
+                        match uopool.calculate_pre_verification_gas(&user_operation).await {
+                            Ok(pre_verification_gas) => pre_verification_gas,
+                            Err(error) => {
+                                res.set_result(EstimateUserOperationGasResult::NotEstimated);
+                                res.data = serde_json::to_string(&SimulationError::from(
+                                    SimulateValidationError::UnknownError {
+                                        error: error.to_string(),
+                                    },
+                                ))
+                                .map_err(|_| {
+                                    tonic::Status::internal("error estimating user operation gas")
+                                })?;
+                                return Ok(tonic::Response::new(res));
+                            }
+                        };
 
-                    let verification_gas_limit = match simulation_result.simulate_validation_result
+                    let verification_gas_limit = match uopool
+                        .estimate_verification_gas_limit(&user_operation, &state_override)
+                        .await
                     {
-                        SimulateValidationResult::ValidationResult(validation_result) => {
-                            validation_result.return_info.0
+                        Ok(verification_gas_limit) => verification_gas_limit,
+                        Err(error) => {
+                            res.set_result(EstimateUserOperationGasResult::NotEstimated);
+                            res.data = serde_json::to_string(&SimulationError::from(error))
+                                .map_err(|_| {
+                                    tonic::Status::internal("error estimating user operation gas")
+                                })?;
+                            return Ok(tonic::Response::new(res));
                         }
-                        SimulateValidationResult::ValidationResultWithAggregation(
-                            validation_result_with_aggregation,
-                        ) => validation_result_with_aggregation.return_info.0,
                     };
 
                     match uopool
-                        .entry_point
-                        .estimate_call_gas(user_operation.clone())
+                        .estimate_call_gas_limit(
+                            &UserOperation {
+                                verification_gas_limit,
+                                ..user_operation.clone()
+                            },
+                            &state_override,
+                        )
                         .await
                     {
                         Ok(call_gas_limit) => {
@@ -311,19 +629,10 @@ where
                         }
                         Err(error) => {
                             res.set_result(EstimateUserOperationGasResult::NotEstimated);
-                            res.data = serde_json::to_string(&SimulationError::from(match error {
-                                EntryPointErr::JsonRpcError(err) => {
-                                    SimulateValidationError::UserOperationExecution {
-                                        message: err.message,
-                                    }
-                                }
-                                _ => SimulateValidationError::UnknownError {
-                                    error: format!("{error:?}"),
-                                },
-                            }))
-                            .map_err(|_| {
-                                tonic::Status::internal("error estimating user operation gas")
-                            })?;
+                            res.data = serde_json::to_string(&SimulationError::from(error))
+                                .map_err(|_| {
+                                    tonic::Status::internal("error estimating user operation gas")
+                                })?;
                         }
                     }
                 }
@@ -355,38 +664,49 @@ where
                 .try_into()
                 .map_err(|_| tonic::Status::invalid_argument("invalid entry point"))?;
 
-            let mempool_id = mempool_id(&entry_point, &self.chain_id);
-
-            let uos = {
+            // the user operations of the alternative mempools are bundled as well
+            let mut uos: Vec<(MempoolId, UserOperation)> = vec![];
+            for mempool_id in self.mempool_ids(&entry_point) {
                 let uopool = self
                     .mempools
                     .get(&mempool_id)
                     .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
-                uopool.mempool.get_sorted().map_err(|e| {
-                    tonic::Status::internal(format!("Get sorted uos internal error: {e:?}"))
-                })?
-            };
-
-            let remove_user_op = |uo: &UserOperation| -> Result<(), tonic::Status> {
-                let user_op_hash = uo.hash(&entry_point, &self.chain_id);
-                let mut uopool = self
-                    .mempools
-                    .get_mut(&mempool_id)
-                    .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
-                uopool.mempool.remove(&user_op_hash).map_err(|e| {
-                    tonic::Status::unknown(format!(
-                        "remove a banned user operation {user_op_hash:x?} failed with {e:?}."
-                    ))
-                })?;
-                Ok(())
-            };
+                uos.extend(
+                    uopool
+                        .mempool
+                        .get_sorted()
+                        .map_err(|e| {
+                            tonic::Status::internal(format!("Get sorted uos internal error: {e:?}"))
+                        })?
+                        .into_iter()
+                        .map(|uo| (mempool_id, uo)),
+                );
+            }
+            uos.sort_by(|(_, a), (_, b)| {
+                b.max_priority_fee_per_gas.cmp(&a.max_priority_fee_per_gas)
+            });
+
+            let remove_user_op =
+                |mempool_id: &MempoolId, uo: &UserOperation| -> Result<(), tonic::Status> {
+                    let user_op_hash = uo.hash(&entry_point, &self.chain_id);
+                    let mut uopool = self.mempools.get_mut(mempool_id).ok_or_else(|| {
+                        tonic::Status::invalid_argument("entry point not supported")
+                    })?;
+                    uopool.mempool.remove(&user_op_hash).map_err(|e| {
+                        tonic::Status::unknown(format!(
+                            "remove a banned user operation {user_op_hash:x?} failed with {e:?}."
+                        ))
+                    })?;
+                    Ok(())
+                };
 
             let mut valid_user_operations = vec![];
             let mut senders: HashSet<Address> = HashSet::new();
             let mut total_gas = U256::zero();
             let mut paymaster_deposit: HashMap<Address, U256> = HashMap::new();
             let mut staked_entity_count: HashMap<Address, u64> = HashMap::new();
-            for uo in uos.iter() {
+            let mut storage_map = StorageMap::new();
+            for (mempool_id, uo) in uos.iter() {
                 if senders.contains(&uo.sender) {
                     continue;
                 }
@@ -394,7 +714,7 @@ where
                 let paymaster_opt = get_addr(uo.paymaster_and_data.0.as_ref());
                 let factory_opt = get_addr(uo.init_code.0.as_ref());
                 let (paymaster_status, factory_status) = {
-                    let uopool = self.mempools.get(&mempool_id).ok_or_else(|| {
+                    let uopool = self.mempools.get(mempool_id).ok_or_else(|| {
                         tonic::Status::invalid_argument("entry point not supported")
                     })?;
 
@@ -413,7 +733,7 @@ where
 
                 match (paymaster_status, factory_status) {
                     (ReputationStatus::BANNED, _) | (_, ReputationStatus::BANNED) => {
-                        remove_user_op(uo)?;
+                        remove_user_op(mempool_id, uo)?;
                         continue;
                     }
                     (ReputationStatus::THROTTLED, _) if paymaster_count > THROTTLED_MAX_INCLUDE => {
@@ -428,16 +748,18 @@ where
                 };
 
                 let (simulation_result, max_verification_gas) = {
-                    let uopool = self.mempools.get(&mempool_id).ok_or_else(|| {
+                    let uopool = self.mempools.get(mempool_id).ok_or_else(|| {
                         tonic::Status::invalid_argument("entry point not supported")
                     })?;
                     (
-                        uopool.simulate_user_operation(uo).await,
+                        uopool
+                            .simulate_user_operation(uo, &Default::default())
+                            .await,
                         uopool.max_verification_gas,
                     )
                 };
 
-                match simulation_result {
+                let user_operation_storage_map = match simulation_result {
                     Ok(simulation_result) => {
                         if simulation_result.valid_after.is_some() {
                             continue;
@@ -458,10 +780,8 @@ where
                                     let balance = match paymaster_deposit.get(&paymaster) {
                                         Some(n) => Ok(n.to_owned()),
                                         None => {
-                                            let uopool = self
-                                                .mempools
-                                                .get(&mempool_id)
-                                                .ok_or_else(|| {
+                                            let uopool =
+                                                self.mempools.get(mempool_id).ok_or_else(|| {
                                                     tonic::Status::invalid_argument(
                                                         "entry point not supported",
                                                     )
@@ -501,16 +821,21 @@ where
                                 todo!("Aggregation is not supported now.")
                             }
                         }
+
+                        simulation_result.storage_map
                     }
                     Err(e) => {
                         debug!("Failed in 2nd simulation: {e:?} ");
-                        remove_user_op(uo)?;
+                        remove_user_op(mempool_id, uo)?;
                         continue;
                     }
-                }
+                };
 
                 valid_user_operations.push(uo.to_owned());
                 senders.insert(uo.sender);
+                for (address, slots) in user_operation_storage_map {
+                    storage_map.entry(address).or_default().extend(slots);
+                }
             }
 
             let response = GetSortedResponse {
@@ -518,6 +843,7 @@ where
                     .into_iter()
                     .map(|u| u.into())
                     .collect(),
+                storage_map: storage_map_to_proto(storage_map),
             };
             return Ok(tonic::Response::new(response));
         } else {
@@ -550,9 +876,10 @@ where
                 .unwrap_or(U64::from(0))
                 .as_u64(),
         );
-        let entry_point =
-            entry_point_opt.ok_or(tonic::Status::invalid_argument("entry point is missing"))?;
-        let mempool_id = mempool_id(&entry_point.into(), &self.chain_id);
+        let entry_point: Address = entry_point_opt
+            .ok_or(tonic::Status::invalid_argument("entry point is missing"))?
+            .into();
+        let mempool_id = mempool_id(&entry_point, &self.chain_id);
 
         let mut uopool = self
             .mempools
@@ -560,12 +887,14 @@ where
             .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
 
         let events_filter = uopool.entry_point.events().from_block(last_block);
-        let events = events_filter.query().await.map_err(|e| {
+        let events = events_filter.query_with_meta().await.map_err(|e| {
             tonic::Status::internal(format!("Getting event logs with error: {e:?}"))
         })?;
-        for event in events {
+        let mut included_user_operations = vec![];
+        for (event, log_meta) in events {
             match event {
                 EntryPointAPIEvents::UserOperationEventFilter(user_operation_event) => {
+                    self.index_user_operation(&user_operation_event.user_op_hash.into(), &log_meta);
                     uopool
                         .remove_user_operation(&user_operation_event.user_op_hash.into())
                         .unwrap_or_else(|| {
@@ -576,6 +905,7 @@ where
                                 mempool_id
                             )
                         });
+                    included_user_operations.push(user_operation_event.user_op_hash.into());
                     uopool.include_address(user_operation_event.sender);
                     uopool.include_address(user_operation_event.paymaster);
                     // TODO: include event aggregator
@@ -589,6 +919,16 @@ where
                 _ => (),
             }
         }
+        drop(uopool);
+
+        // included user operations of the alternative mempools
+        for mempool_id in self.mempool_ids(&entry_point).iter().skip(1) {
+            if let Some(mut uopool) = self.mempools.get_mut(mempool_id) {
+                for user_operation_hash in included_user_operations.iter() {
+                    uopool.remove_user_operation(user_operation_hash);
+                }
+            }
+        }
 
         Ok(Response::new(()))
     }
@@ -615,23 +955,26 @@ where
 
         match event {
             Some((event, log_meta)) => {
-                if let Some((user_operation, entry_point)) = self
-                    .eth_provider
-                    .get_transaction(log_meta.transaction_hash)
+                // the entry point is the address of the event log
+                let entry_point = log_meta.address;
+                let uopool = self
+                    .mempools
+                    .iter()
+                    .find(|uopool| uopool.entry_point.address() == entry_point)
+                    .ok_or(tonic::Status::not_found("User operation not found"))?;
+                let user_operations = uopool
+                    .entry_point
+                    .get_user_operations(log_meta.transaction_hash)
                     .await
                     .map_err(|e| {
                         tonic::Status::internal(format!(
-                            "Getting transaction by hash with error: {e:?}"
+                            "Getting user operations of transaction with error: {e:?}"
                         ))
-                    })?
-                    .and_then(|tx| {
-                        let user_ops = parse_from_input_data(tx.input)?;
-                        let entry_point = tx.to?;
-                        user_ops
-                            .iter()
-                            .find(|uo| uo.sender == event.sender && uo.nonce == event.nonce)
-                            .map(|uo| (uo.clone(), entry_point))
-                    })
+                    })?;
+
+                if let Some(user_operation) = user_operations
+                    .into_iter()
+                    .find(|uo| uo.sender == event.sender && uo.nonce == event.nonce)
                 {
                     let response = Response::new(GetUserOperationByHashResponse {
                         user_operation: Some(user_operation.into()),
@@ -645,7 +988,31 @@ where
                     Err(tonic::Status::not_found("User operation not found"))
                 }
             }
-            None => Err(tonic::Status::not_found("User operation not found")),
+            None => {
+                // pending user operation in one of the mempools
+                for mempool in self.mempools.iter() {
+                    let user_operation =
+                        mempool
+                            .mempool
+                            .get(&user_operation_hash.into())
+                            .map_err(|e| {
+                                tonic::Status::internal(format!(
+                                    "Getting user operation from mempool with error: {e:?}"
+                                ))
+                            })?;
+                    if let Some(user_operation) = user_operation {
+                        return Ok(Response::new(GetUserOperationByHashResponse {
+                            user_operation: Some(user_operation.into()),
+                            entry_point: Some(mempool.entry_point.address().into()),
+                            transaction_hash: None,
+                            block_hash: None,
+                            block_number: 0,
+                        }));
+                    }
+                }
+
+                Err(tonic::Status::not_found("User operation not found"))
+            }
         }
     }
 
@@ -687,6 +1054,15 @@ where
                         .get_user_operation_by_hash(tonic::Request::new(req))
                         .await?;
 
+                    let logs = user_operation_logs(
+                        &transaction_receipt.logs,
+                        log_meta.address,
+                        user_operation_hash,
+                    );
+                    let reason =
+                        user_operation_revert_reason(&logs, log_meta.address, user_operation_hash)
+                            .unwrap_or_default();
+
                     let response = Response::new(GetUserOperationReceiptResponse {
                         user_operation_hash: Some(user_operation_hash.into()),
                         sender: Some(event.sender.into()),
@@ -694,12 +1070,9 @@ where
                         actual_gas_cost: Some(event.actual_gas_cost.into()),
                         actual_gas_used: Some(event.actual_gas_used.into()),
                         success: event.success,
-                        transaction_receipt: Some(transaction_receipt.clone().into()),
-                        logs: transaction_receipt
-                            .logs
-                            .into_iter()
-                            .map(|l| l.into())
-                            .collect(),
+                        transaction_receipt: Some(transaction_receipt.into()),
+                        logs: logs.into_iter().map(|l| l.into()).collect(),
+                        reason,
                         paymaster: user_operation
                             .into_inner()
                             .user_operation
@@ -715,6 +1088,36 @@ where
         }
     }
 
+    async fn get_user_operation_gas_price(
+        &self,
+        request: tonic::Request<GetUserOperationGasPriceRequest>,
+    ) -> Result<Response<GetUserOperationGasPriceResponse>, tonic::Status> {
+        let req = request.into_inner();
+
+        if let Some(entry_point) = req.ep {
+            let entry_point: Address = entry_point
+                .try_into()
+                .map_err(|_| tonic::Status::invalid_argument("invalid entry point"))?;
+
+            let mempool_id = mempool_id(&entry_point, &self.chain_id);
+
+            let uopool = self
+                .mempools
+                .get(&mempool_id)
+                .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
+
+            let gas_price = uopool
+                .suggest_gas_price()
+                .await
+                .map_err(|error| tonic::Status::internal(error.to_string()))?;
+            trace!("Suggested user operation gas price: {gas_price:?}");
+
+            return Ok(tonic::Response::new(gas_price.into()));
+        }
+
+        Err(tonic::Status::invalid_argument("missing entry point"))
+    }
+
     async fn get_all(
         &self,
         request: tonic::Request<GetAllRequest>,
@@ -826,6 +1229,45 @@ where
 
         Err(tonic::Status::invalid_argument("missing entry point"))
     }
+
+    async fn get_validation_report(
+        &self,
+        request: tonic::Request<GetValidationReportRequest>,
+    ) -> Result<Response<GetValidationReportResponse>, tonic::Status> {
+        let req = request.into_inner();
+
+        if let GetValidationReportRequest {
+            uo: Some(user_operation),
+            ep: Some(entry_point),
+        } = req
+        {
+            let user_operation: UserOperation = user_operation
+                .try_into()
+                .map_err(|_| tonic::Status::invalid_argument("invalid user operation"))?;
+            let entry_point: Address = entry_point
+                .try_into()
+                .map_err(|_| tonic::Status::invalid_argument("invalid entry point"))?;
+
+            let mempool_id = mempool_id(&entry_point, &self.chain_id);
+
+            let uopool = self
+                .mempools
+                .get(&mempool_id)
+                .ok_or_else(|| tonic::Status::invalid_argument("entry point not supported"))?;
+
+            let report = uopool.validation_report(&user_operation).await;
+            trace!("Validation report of user operation {user_operation:?}: {report:?}");
+
+            return Ok(tonic::Response::new(GetValidationReportResponse {
+                report: serde_json::to_string(&report)
+                    .map_err(|_| tonic::Status::internal("error encoding validation report"))?,
+            }));
+        }
+
+        Err(tonic::Status::invalid_argument(
+            "missing user operation or entry point",
+        ))
+    }
 }
 
 pub async fn uopool_service_run(
@@ -835,48 +1277,140 @@ pub async fn uopool_service_run(
     max_verification_gas: U256,
 ) -> Result<()> {
     let chain_id = eth_provider.get_chainid().await?;
+    let gas_overhead = opts.overhead(chain_id)?;
+    info!("Pre-verification gas overhead: {gas_overhead:?}");
+    let rule_sets = match opts.validation_rules.as_ref() {
+        Some(path) => RuleSetRegistry::from_config_file(path, &chain_id)?,
+        None => RuleSetRegistry::default(),
+    };
+
+    let datadir = opts.datadir()?;
+    std::fs::create_dir_all(&datadir)?;
+    let user_operation_index = DatabaseUserOperationIndex::new(datadir.clone())?;
+    user_operation_index.create_tables()?;
+    let user_operation_index = Arc::new(user_operation_index);
+    info!("User operation index database at {datadir:?}");
+
+    let p2p = match opts.p2p_listen_address.clone() {
+        Some(listen_address) => {
+            let network = Network::new(
+                Config {
+                    listen_address,
+                    bootnodes: opts.p2p_bootnodes.clone(),
+                },
+                entry_points
+                    .iter()
+                    .map(|entry_point| mempool_id(entry_point, &chain_id))
+                    .collect(),
+            )?;
+            info!(
+                "P2P network started with peer id {}",
+                network.local_peer_id()
+            );
+            let (p2p_sender, p2p_receiver) = mpsc::unbounded_channel();
+            Some((network, p2p_sender, p2p_receiver))
+        }
+        None => None,
+    };
 
     tokio::spawn(async move {
         let mut builder = tonic::transport::Server::builder();
 
         let mempools_map = Arc::new(DashMap::<MempoolId, UserOperationPool<Provider<Http>>>::new());
 
-        for entry_point in entry_points {
-            let id = mempool_id(&entry_point, &chain_id);
-
-            let mut reputation = Box::<MemoryReputation>::default();
-            reputation.init(
-                MIN_INCLUSION_RATE_DENOMINATOR,
-                THROTTLING_SLACK,
-                BAN_SLACK,
-                opts.min_stake,
-                opts.min_unstake_delay,
+        // the canonical mempools of the entry points, then the alternative mempools
+        let mempools = entry_points
+            .iter()
+            .map(|entry_point| {
+                (
+                    mempool_id(entry_point, &chain_id),
+                    *entry_point,
+                    RuleSet::default(),
+                )
+            })
+            .chain(
+                rule_sets
+                    .iter()
+                    .map(|(id, entry_point, rule_set)| (*id, *entry_point, rule_set.clone())),
             );
+        // the mempools of an entry point share the reputation, so entities banned or throttled in
+        // the canonical mempool are in the alternative mempools as well
+        let mut reputations = HashMap::<Address, Arc<Mutex<MemoryReputation>>>::new();
+        for (id, entry_point, rule_set) in mempools {
+            if !entry_points.contains(&entry_point) {
+                warn!("Alternative mempool {id:?} of unsupported entry point {entry_point:?}");
+                continue;
+            }
 
-            mempools_map.insert(
-                id,
-                UserOperationPool::<Provider<Http>>::new(
-                    EntryPoint::<Provider<Http>>::new(eth_provider.clone(), entry_point),
-                    Box::<MemoryMempool>::default(),
-                    reputation,
-                    eth_provider.clone(),
-                    max_verification_gas,
-                    opts.min_priority_fee_per_gas,
-                    chain_id,
-                ),
+            let reputation = reputations
+                .entry(entry_point)
+                .or_insert_with(|| {
+                    let mut reputation = Arc::new(Mutex::new(MemoryReputation::default()));
+                    reputation.init(
+                        MIN_INCLUSION_RATE_DENOMINATOR,
+                        THROTTLING_SLACK,
+                        BAN_SLACK,
+                        opts.min_stake,
+                        opts.min_unstake_delay,
+                    );
+                    reputation
+                })
+                .clone();
+
+            let mut uopool = UserOperationPool::<Provider<Http>>::new(
+                EntryPoint::<Provider<Http>>::new(eth_provider.clone(), entry_point),
+                Box::<MemoryMempool>::default(),
+                Box::new(reputation),
+                eth_provider.clone(),
+                max_verification_gas,
+                opts.min_priority_fee_per_gas,
+                chain_id,
+            );
+            if let Some(l1_gas_oracle) = opts.l1_gas_oracle {
+                uopool.l1_gas_oracle = l1_gas_oracle;
+            }
+            uopool.gas_overhead = gas_overhead;
+            uopool.verification_gas_margin = opts.verification_gas_margin;
+            uopool.simulator = opts.simulator;
+            uopool.rules = rule_set;
+            info!(
+                "Mempool {id:?} of entry point {entry_point:?} with {} validation rules",
+                if rule_sets.get(&id).is_some() {
+                    "alternative"
+                } else {
+                    "canonical"
+                }
             );
+
+            mempools_map.insert(id, uopool);
         }
 
-        let svc = uo_pool_server::UoPoolServer::new(UoPoolService::new(
+        let uopool_service = UoPoolService::new(
             mempools_map.clone(),
             eth_provider.clone(),
             chain_id,
-        ));
+            user_operation_index,
+            p2p.as_ref().map(|(_, p2p_sender, _)| p2p_sender.clone()),
+        );
+
+        if let Some((network, _, p2p_receiver)) = p2p {
+            tokio::spawn(p2p_service_run(
+                network,
+                uopool_service.clone(),
+                p2p_receiver,
+            ));
+        }
+
+        let svc = uo_pool_server::UoPoolServer::new(uopool_service);
 
         tokio::spawn(async move {
             loop {
+                // once per shared reputation
                 mempools_map
                     .iter_mut()
+                    .filter(|mempool| {
+                        mempool_id(&mempool.entry_point.address(), &chain_id) == *mempool.key()
+                    })
                     .for_each(|mut mempool| mempool.value_mut().reputation.update_hourly());
                 tokio::time::sleep(Duration::from_secs(60 * 60)).await;
             }
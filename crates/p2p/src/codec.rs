@@ -0,0 +1,175 @@
+use std::io::{self, Read, Write};
+
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::{core::upgrade::ProtocolName, request_response::Codec};
+use ssz::{Decode, Encode};
+
+use crate::types::{PooledUserOpHashesRequest, PooledUserOpHashesResponse};
+
+pub const POOLED_USER_OP_HASHES_PROTOCOL: &str =
+    "/account_abstraction/req/pooled_user_op_hashes/1/ssz_snappy";
+// request and response chunks are limited to the max gossip size
+const MAX_CHUNK_SIZE: usize = 1_048_576;
+const RESPONSE_CODE_SUCCESS: u8 = 0;
+
+#[derive(Clone, Debug, Default)]
+pub struct PooledUserOpHashesProtocol;
+
+impl ProtocolName for PooledUserOpHashesProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        POOLED_USER_OP_HASHES_PROTOCOL.as_bytes()
+    }
+}
+
+// ssz_snappy encoding: unsigned varint length of the SSZ bytes, followed by the snappy (frame
+// format) compressed SSZ bytes. Responses are prefixed with the response code.
+#[derive(Clone, Debug, Default)]
+pub struct PooledUserOpHashesCodec;
+
+fn invalid_data<E: std::fmt::Debug>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{error:?}"))
+}
+
+fn encode_ssz_snappy(ssz_bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+
+    let mut length = ssz_bytes.len();
+    while length >= 0x80 {
+        data.push((length as u8) | 0x80);
+        length >>= 7;
+    }
+    data.push(length as u8);
+
+    let mut encoder = snap::write::FrameEncoder::new(data);
+    encoder.write_all(ssz_bytes)?;
+    encoder.into_inner().map_err(invalid_data)
+}
+
+fn decode_ssz_snappy(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut length = 0usize;
+    let mut offset = 0;
+    loop {
+        let byte = *data
+            .get(offset)
+            .ok_or_else(|| invalid_data("missing length prefix"))?;
+        if offset >= 9 {
+            return Err(invalid_data("length prefix too long"));
+        }
+        length |= ((byte & 0x7f) as usize) << (7 * offset);
+        offset += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    if length > MAX_CHUNK_SIZE {
+        return Err(invalid_data(format!("chunk too large: {length} bytes")));
+    }
+
+    let mut ssz_bytes = vec![];
+    snap::read::FrameDecoder::new(&data[offset..])
+        .take(length as u64)
+        .read_to_end(&mut ssz_bytes)?;
+    if ssz_bytes.len() != length {
+        return Err(invalid_data("chunk length mismatch"));
+    }
+    Ok(ssz_bytes)
+}
+
+async fn read_chunk<T: AsyncRead + Unpin + Send>(io: &mut T) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    io.take(MAX_CHUNK_SIZE as u64)
+        .read_to_end(&mut data)
+        .await?;
+    Ok(data)
+}
+
+#[async_trait]
+impl Codec for PooledUserOpHashesCodec {
+    type Protocol = PooledUserOpHashesProtocol;
+    type Request = PooledUserOpHashesRequest;
+    type Response = PooledUserOpHashesResponse;
+
+    async fn read_request<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_chunk(io).await?;
+        PooledUserOpHashesRequest::from_ssz_bytes(&decode_ssz_snappy(&data)?).map_err(invalid_data)
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_chunk(io).await?;
+        match data.split_first() {
+            Some((&RESPONSE_CODE_SUCCESS, data)) => {
+                PooledUserOpHashesResponse::from_ssz_bytes(&decode_ssz_snappy(data)?)
+                    .map_err(invalid_data)
+            }
+            Some((code, _)) => Err(invalid_data(format!("error response code {code}"))),
+            None => Err(invalid_data("empty response")),
+        }
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&encode_ssz_snappy(&request.as_ssz_bytes())?)
+            .await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&[RESPONSE_CODE_SUCCESS]).await?;
+        io.write_all(&encode_ssz_snappy(&response.as_ssz_bytes())?)
+            .await?;
+        io.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::H256;
+
+    #[test]
+    fn ssz_snappy_chunk() {
+        let response = PooledUserOpHashesResponse {
+            next_cursor: H256::from_low_u64_be(1),
+            hashes: (0..200).map(|_| H256::random()).collect(),
+        };
+        let ssz_bytes = response.as_ssz_bytes();
+
+        // length prefix is a 2 bytes varint
+        let data = encode_ssz_snappy(&ssz_bytes).unwrap();
+        assert_eq!(data[..2], [0xa4, 0x32]);
+        assert_eq!(decode_ssz_snappy(&data).unwrap(), ssz_bytes);
+
+        assert!(decode_ssz_snappy(&[]).is_err());
+        assert!(decode_ssz_snappy(&data[..10]).is_err());
+    }
+}
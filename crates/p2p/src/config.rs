@@ -0,0 +1,22 @@
+use libp2p::Multiaddr;
+
+// Default port of the ERC-4337 shared mempool network
+pub const DEFAULT_P2P_PORT: u16 = 4337;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub listen_address: Multiaddr,
+    // peers that are dialed at startup
+    pub bootnodes: Vec<Multiaddr>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_address: format!("/ip4/0.0.0.0/tcp/{DEFAULT_P2P_PORT}")
+                .parse()
+                .expect("valid multiaddr"),
+            bootnodes: vec![],
+        }
+    }
+}
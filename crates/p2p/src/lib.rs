@@ -0,0 +1,19 @@
+#![allow(dead_code)]
+
+mod codec;
+mod config;
+mod network;
+mod types;
+
+pub use codec::{PooledUserOpHashesCodec, POOLED_USER_OP_HASHES_PROTOCOL};
+pub use config::Config;
+pub use libp2p::{
+    gossipsub::{MessageAcceptance, MessageId},
+    request_response::ResponseChannel,
+    Multiaddr, PeerId,
+};
+pub use network::{topic, Network, NetworkEvent};
+pub use types::{
+    PooledUserOpHashesRequest, PooledUserOpHashesResponse, UserOperationSsz,
+    UserOperationsWithEntryPoint, MAX_OPS_PER_REQUEST,
+};
@@ -0,0 +1,431 @@
+use std::{collections::HashMap, iter};
+
+use anyhow::format_err;
+use ethers::types::H256;
+use futures::StreamExt;
+use libp2p::{
+    core::upgrade::Version,
+    gossipsub::{
+        self, IdentTopic, MessageAcceptance, MessageAuthenticity, MessageId, PeerScoreParams,
+        PeerScoreThresholds, TopicHash, TopicScoreParams, ValidationMode,
+    },
+    identity::Keypair,
+    noise,
+    request_response::{self, ProtocolSupport, RequestId, ResponseChannel},
+    swarm::{NetworkBehaviour, Swarm, SwarmBuilder, SwarmEvent},
+    tcp, yamux, Multiaddr, PeerId, Transport,
+};
+use tracing::{debug, trace, warn};
+
+use crate::{
+    codec::{PooledUserOpHashesCodec, PooledUserOpHashesProtocol},
+    config::Config,
+    types::{
+        message_id, PooledUserOpHashesRequest, PooledUserOpHashesResponse,
+        UserOperationsWithEntryPoint, MAX_GOSSIP_SIZE,
+    },
+};
+
+// Score penalty of a peer per invalid message (squared and decayed), peers below the graylist
+// threshold of -80 are ignored
+const INVALID_MESSAGE_DELIVERIES_WEIGHT: f64 = -10.0;
+
+// Gossip topic of the mempool, the mempool id is hex encoded without the 0x prefix
+pub fn topic(mempool_id: &H256) -> IdentTopic {
+    IdentTopic::new(format!(
+        "/account_abstraction/{mempool_id:x}/user_operations_with_entrypoint/ssz_snappy"
+    ))
+}
+
+// Peers are only scored by the invalid user operations they send, mempools may be too quiet for
+// the delivery rate penalties
+fn topic_score_params() -> TopicScoreParams {
+    TopicScoreParams {
+        topic_weight: 1.0,
+        time_in_mesh_weight: 0.0,
+        first_message_deliveries_weight: 0.0,
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        invalid_message_deliveries_weight: INVALID_MESSAGE_DELIVERIES_WEIGHT,
+        ..Default::default()
+    }
+}
+
+#[derive(NetworkBehaviour)]
+pub struct Behaviour {
+    gossipsub: gossipsub::Behaviour,
+    pooled_user_op_hashes: request_response::Behaviour<PooledUserOpHashesCodec>,
+}
+
+#[derive(Debug)]
+pub enum NetworkEvent {
+    NewListenAddr(Multiaddr),
+    PeerConnected(PeerId),
+    PeerDisconnected(PeerId),
+    Subscribed {
+        peer_id: PeerId,
+        mempool_id: H256,
+    },
+    // the message has to be validated with `report_validation_result` before it's forwarded
+    UserOperations {
+        peer_id: PeerId,
+        message_id: MessageId,
+        mempool_id: H256,
+        user_operations: UserOperationsWithEntryPoint,
+    },
+    PooledUserOpHashesRequest {
+        peer_id: PeerId,
+        request: PooledUserOpHashesRequest,
+        channel: ResponseChannel<PooledUserOpHashesResponse>,
+    },
+    PooledUserOpHashesResponse {
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: PooledUserOpHashesResponse,
+    },
+}
+
+pub struct Network {
+    swarm: Swarm<Behaviour>,
+    // gossip topics of the mempools the node participates in
+    mempool_ids: HashMap<TopicHash, H256>,
+}
+
+impl Network {
+    pub fn new(config: Config, mempool_ids: Vec<H256>) -> anyhow::Result<Self> {
+        let keypair = Keypair::generate_secp256k1();
+        let peer_id = PeerId::from(keypair.public());
+
+        let transport = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
+            .upgrade(Version::V1)
+            .authenticate(noise::NoiseAuthenticated::xx(&keypair)?)
+            .multiplex(yamux::YamuxConfig::default())
+            .boxed();
+
+        // messages aren't signed (StrictNoSign in the spec), so they are deduplicated by content.
+        // They are only forwarded after the user operations are verified.
+        let gossipsub_config = gossipsub::ConfigBuilder::default()
+            .validation_mode(ValidationMode::Anonymous)
+            .validate_messages()
+            .max_transmit_size(MAX_GOSSIP_SIZE)
+            .message_id_fn(|message: &gossipsub::Message| {
+                MessageId::from(message_id(&message.data))
+            })
+            .build()
+            .map_err(|e| format_err!("Gossipsub config error: {e:?}"))?;
+        let mut gossipsub =
+            gossipsub::Behaviour::new(MessageAuthenticity::Anonymous, gossipsub_config)
+                .map_err(|e| format_err!("Gossipsub behaviour error: {e:?}"))?;
+
+        let mut topics = HashMap::new();
+        let mut peer_score_params = PeerScoreParams::default();
+        for mempool_id in mempool_ids {
+            let topic = topic(&mempool_id);
+            gossipsub
+                .subscribe(&topic)
+                .map_err(|e| format_err!("Subscribing to topic {topic} error: {e:?}"))?;
+            peer_score_params
+                .topics
+                .insert(topic.hash(), topic_score_params());
+            topics.insert(topic.hash(), mempool_id);
+        }
+        gossipsub
+            .with_peer_score(peer_score_params, PeerScoreThresholds::default())
+            .map_err(|e| format_err!("Gossipsub peer score error: {e}"))?;
+
+        let pooled_user_op_hashes = request_response::Behaviour::new(
+            PooledUserOpHashesCodec,
+            iter::once((PooledUserOpHashesProtocol, ProtocolSupport::Full)),
+            request_response::Config::default(),
+        );
+
+        let mut swarm = SwarmBuilder::with_tokio_executor(
+            transport,
+            Behaviour {
+                gossipsub,
+                pooled_user_op_hashes,
+            },
+            peer_id,
+        )
+        .build();
+
+        swarm.listen_on(config.listen_address)?;
+        for bootnode in config.bootnodes {
+            debug!("Dialing bootnode {bootnode}");
+            swarm.dial(bootnode)?;
+        }
+
+        Ok(Self {
+            swarm,
+            mempool_ids: topics,
+        })
+    }
+
+    pub fn local_peer_id(&self) -> &PeerId {
+        self.swarm.local_peer_id()
+    }
+
+    pub fn publish(
+        &mut self,
+        mempool_id: &H256,
+        user_operations: &UserOperationsWithEntryPoint,
+    ) -> anyhow::Result<()> {
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(topic(mempool_id), user_operations.encode_gossip()?)
+            .map_err(|e| format_err!("Publishing user operations error: {e:?}"))?;
+        Ok(())
+    }
+
+    // Accepted messages are forwarded to other peers, rejected ones lower the score of the peer
+    pub fn report_validation_result(
+        &mut self,
+        message_id: &MessageId,
+        peer_id: &PeerId,
+        acceptance: MessageAcceptance,
+    ) {
+        if let Err(error) = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .report_message_validation_result(message_id, peer_id, acceptance)
+        {
+            debug!("Reporting validation result of message {message_id} error: {error:?}");
+        }
+    }
+
+    pub fn request_pooled_user_op_hashes(
+        &mut self,
+        peer_id: &PeerId,
+        request: PooledUserOpHashesRequest,
+    ) -> RequestId {
+        self.swarm
+            .behaviour_mut()
+            .pooled_user_op_hashes
+            .send_request(peer_id, request)
+    }
+
+    pub fn respond_pooled_user_op_hashes(
+        &mut self,
+        channel: ResponseChannel<PooledUserOpHashesResponse>,
+        response: PooledUserOpHashesResponse,
+    ) -> anyhow::Result<()> {
+        self.swarm
+            .behaviour_mut()
+            .pooled_user_op_hashes
+            .send_response(channel, response)
+            .map_err(|_| format_err!("Sending pooled user operation hashes response error"))
+    }
+
+    // Drives the swarm until the next event relevant to the mempool (cancel safe)
+    pub async fn next_event(&mut self) -> NetworkEvent {
+        loop {
+            let event = self.swarm.select_next_some().await;
+            if let Some(event) = self.handle_swarm_event(event) {
+                return event;
+            }
+        }
+    }
+
+    fn handle_swarm_event<E>(
+        &mut self,
+        event: SwarmEvent<BehaviourEvent, E>,
+    ) -> Option<NetworkEvent> {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => Some(NetworkEvent::NewListenAddr(address)),
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                Some(NetworkEvent::PeerConnected(peer_id))
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                ..
+            } => Some(NetworkEvent::PeerDisconnected(peer_id)),
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(event)) => {
+                self.handle_gossipsub_event(event)
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::PooledUserOpHashes(event)) => {
+                self.handle_pooled_user_op_hashes_event(event)
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_gossipsub_event(&mut self, event: gossipsub::Event) -> Option<NetworkEvent> {
+        match event {
+            gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            } => {
+                let Some(mempool_id) = self.mempool_ids.get(&message.topic).copied() else {
+                    self.report_validation_result(
+                        &message_id,
+                        &propagation_source,
+                        MessageAcceptance::Ignore,
+                    );
+                    return None;
+                };
+                match UserOperationsWithEntryPoint::decode_gossip(&message.data) {
+                    Ok(user_operations) => Some(NetworkEvent::UserOperations {
+                        peer_id: propagation_source,
+                        message_id,
+                        mempool_id,
+                        user_operations,
+                    }),
+                    Err(error) => {
+                        warn!("Invalid gossip message from {propagation_source}: {error:?}");
+                        self.report_validation_result(
+                            &message_id,
+                            &propagation_source,
+                            MessageAcceptance::Reject,
+                        );
+                        None
+                    }
+                }
+            }
+            gossipsub::Event::Subscribed { peer_id, topic } => {
+                let mempool_id = *self.mempool_ids.get(&topic)?;
+                Some(NetworkEvent::Subscribed {
+                    peer_id,
+                    mempool_id,
+                })
+            }
+            event => {
+                trace!("Gossipsub event: {event:?}");
+                None
+            }
+        }
+    }
+
+    fn handle_pooled_user_op_hashes_event(
+        &mut self,
+        event: request_response::Event<PooledUserOpHashesRequest, PooledUserOpHashesResponse>,
+    ) -> Option<NetworkEvent> {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request {
+                    request, channel, ..
+                } => Some(NetworkEvent::PooledUserOpHashesRequest {
+                    peer_id: peer,
+                    request,
+                    channel,
+                }),
+                request_response::Message::Response {
+                    request_id,
+                    response,
+                } => Some(NetworkEvent::PooledUserOpHashesResponse {
+                    peer_id: peer,
+                    request_id,
+                    response,
+                }),
+            },
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                warn!("Pooled user operation hashes request to {peer} failed: {error:?}");
+                None
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                warn!("Pooled user operation hashes request from {peer} failed: {error:?}");
+                None
+            }
+            request_response::Event::ResponseSent { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aa_bundler_primitives::UserOperation;
+    use ethers::types::{Address, U256};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn local_config(bootnodes: Vec<Multiaddr>) -> Config {
+        Config {
+            listen_address: "/ip4/127.0.0.1/tcp/0".parse().unwrap(),
+            bootnodes,
+        }
+    }
+
+    #[tokio::test]
+    async fn two_local_nodes() {
+        let mempool_id = H256::random();
+        let mut node1 = Network::new(local_config(vec![]), vec![mempool_id]).unwrap();
+        let listen_address = loop {
+            if let NetworkEvent::NewListenAddr(address) = node1.next_event().await {
+                break address;
+            }
+        };
+        let mut node2 = Network::new(local_config(vec![listen_address]), vec![mempool_id]).unwrap();
+        let (peer_id1, peer_id2) = (*node1.local_peer_id(), *node2.local_peer_id());
+
+        // both nodes should see the subscription of the other node
+        timeout(Duration::from_secs(10), async {
+            let (mut subscribed1, mut subscribed2) = (false, false);
+            while !(subscribed1 && subscribed2) {
+                tokio::select! {
+                    event = node1.next_event() => if let NetworkEvent::Subscribed { peer_id, .. } = event {
+                        subscribed1 = peer_id == peer_id2;
+                    },
+                    event = node2.next_event() => if let NetworkEvent::Subscribed { peer_id, .. } = event {
+                        subscribed2 = peer_id == peer_id1;
+                    },
+                }
+            }
+        })
+        .await
+        .expect("nodes should subscribe to the mempool topic");
+
+        let user_operations = UserOperationsWithEntryPoint::new(
+            Address::random(),
+            H256::random(),
+            U256::from(1337),
+            vec![UserOperation::random()],
+        );
+        node1.publish(&mempool_id, &user_operations).unwrap();
+
+        let received = timeout(Duration::from_secs(10), async {
+            loop {
+                tokio::select! {
+                    _ = node1.next_event() => {},
+                    event = node2.next_event() => if let NetworkEvent::UserOperations { peer_id, message_id, mempool_id: id, user_operations } = event {
+                        assert_eq!((peer_id, id), (peer_id1, mempool_id));
+                        node2.report_validation_result(&message_id, &peer_id, MessageAcceptance::Accept);
+                        break user_operations;
+                    },
+                }
+            }
+        })
+        .await
+        .expect("user operations should be gossiped");
+        assert_eq!(received, user_operations);
+
+        let hashes = vec![H256::random(), H256::random()];
+        node2.request_pooled_user_op_hashes(
+            &peer_id1,
+            PooledUserOpHashesRequest {
+                cursor: H256::zero(),
+            },
+        );
+        let response = timeout(Duration::from_secs(10), async {
+            loop {
+                tokio::select! {
+                    event = node1.next_event() => if let NetworkEvent::PooledUserOpHashesRequest { channel, .. } = event {
+                        node1.respond_pooled_user_op_hashes(channel, PooledUserOpHashesResponse {
+                            next_cursor: H256::zero(),
+                            hashes: hashes.clone(),
+                        }).unwrap();
+                    },
+                    event = node2.next_event() => if let NetworkEvent::PooledUserOpHashesResponse { response, .. } = event {
+                        break response;
+                    },
+                }
+            }
+        })
+        .await
+        .expect("pooled user operation hashes should be returned");
+        assert_eq!(response.hashes, hashes);
+    }
+}
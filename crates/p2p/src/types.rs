@@ -0,0 +1,189 @@
+use aa_bundler_primitives::UserOperation;
+use anyhow::format_err;
+use ethers::types::{Address, H256, U256};
+use sha2::{Digest, Sha256};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode, Encode};
+
+// https://github.com/eth-infinitism/bundler-spec/blob/main/p2p-specs/p2p-interface.md
+pub const MAX_OPS_PER_REQUEST: usize = 4096;
+pub const MAX_GOSSIP_SIZE: usize = 1_048_576;
+const MESSAGE_DOMAIN_INVALID_SNAPPY: [u8; 4] = [0x00, 0x00, 0x00, 0x00];
+const MESSAGE_DOMAIN_VALID_SNAPPY: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct UserOperationSsz {
+    pub sender: Address,
+    pub nonce: U256,
+    pub init_code: Vec<u8>,
+    pub call_data: Vec<u8>,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub paymaster_and_data: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl From<UserOperation> for UserOperationSsz {
+    fn from(user_operation: UserOperation) -> Self {
+        Self {
+            sender: user_operation.sender,
+            nonce: user_operation.nonce,
+            init_code: user_operation.init_code.to_vec(),
+            call_data: user_operation.call_data.to_vec(),
+            call_gas_limit: user_operation.call_gas_limit,
+            verification_gas_limit: user_operation.verification_gas_limit,
+            pre_verification_gas: user_operation.pre_verification_gas,
+            max_fee_per_gas: user_operation.max_fee_per_gas,
+            max_priority_fee_per_gas: user_operation.max_priority_fee_per_gas,
+            paymaster_and_data: user_operation.paymaster_and_data.to_vec(),
+            signature: user_operation.signature.to_vec(),
+        }
+    }
+}
+
+impl From<UserOperationSsz> for UserOperation {
+    fn from(value: UserOperationSsz) -> Self {
+        Self {
+            sender: value.sender,
+            nonce: value.nonce,
+            init_code: value.init_code.into(),
+            call_data: value.call_data.into(),
+            call_gas_limit: value.call_gas_limit,
+            verification_gas_limit: value.verification_gas_limit,
+            pre_verification_gas: value.pre_verification_gas,
+            max_fee_per_gas: value.max_fee_per_gas,
+            max_priority_fee_per_gas: value.max_priority_fee_per_gas,
+            paymaster_and_data: value.paymaster_and_data.into(),
+            signature: value.signature.into(),
+        }
+    }
+}
+
+// Message of the user_operations_with_entrypoint gossip topic
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct UserOperationsWithEntryPoint {
+    pub entry_point_contract: Address,
+    pub verified_at_block_hash: U256,
+    pub chain_id: U256,
+    pub user_operations: Vec<UserOperationSsz>,
+}
+
+impl UserOperationsWithEntryPoint {
+    pub fn new(
+        entry_point: Address,
+        verified_at_block_hash: H256,
+        chain_id: U256,
+        user_operations: Vec<UserOperation>,
+    ) -> Self {
+        Self {
+            entry_point_contract: entry_point,
+            verified_at_block_hash: U256::from_big_endian(verified_at_block_hash.as_bytes()),
+            chain_id,
+            user_operations: user_operations.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn user_operations(&self) -> Vec<UserOperation> {
+        self.user_operations
+            .iter()
+            .cloned()
+            .map(Into::into)
+            .collect()
+    }
+
+    // Gossip messages are SSZ encoded and snappy (block format) compressed
+    pub fn encode_gossip(&self) -> anyhow::Result<Vec<u8>> {
+        snap::raw::Encoder::new()
+            .compress_vec(&self.as_ssz_bytes())
+            .map_err(|e| format_err!("Snappy compression error: {e:?}"))
+    }
+
+    pub fn decode_gossip(data: &[u8]) -> anyhow::Result<Self> {
+        let decompressed_length = snap::raw::decompress_len(data)
+            .map_err(|e| format_err!("Snappy decompression error: {e:?}"))?;
+        if decompressed_length > MAX_GOSSIP_SIZE {
+            return Err(format_err!(
+                "Gossip message too large: {decompressed_length} bytes"
+            ));
+        }
+        let ssz_bytes = snap::raw::Decoder::new()
+            .decompress_vec(data)
+            .map_err(|e| format_err!("Snappy decompression error: {e:?}"))?;
+        let message = Self::from_ssz_bytes(&ssz_bytes)
+            .map_err(|e| format_err!("SSZ decoding error: {e:?}"))?;
+        if message.user_operations.len() > MAX_OPS_PER_REQUEST {
+            return Err(format_err!(
+                "Too many user operations in gossip message: {}",
+                message.user_operations.len()
+            ));
+        }
+        Ok(message)
+    }
+}
+
+// SHA256(MESSAGE_DOMAIN + snappy decompressed data)[:20], the raw data is used if it can't be
+// decompressed
+pub fn message_id(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    match snap::raw::Decoder::new().decompress_vec(data) {
+        Ok(decompressed) => {
+            hasher.update(MESSAGE_DOMAIN_VALID_SNAPPY);
+            hasher.update(decompressed);
+        }
+        Err(_) => {
+            hasher.update(MESSAGE_DOMAIN_INVALID_SNAPPY);
+            hasher.update(data);
+        }
+    }
+    hasher.finalize()[..20].to_vec()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct PooledUserOpHashesRequest {
+    pub cursor: H256,
+}
+
+// The next cursor is zero if there are no more hashes
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct PooledUserOpHashesResponse {
+    pub next_cursor: H256,
+    pub hashes: Vec<H256>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Bytes;
+
+    fn user_operation(nonce: u64) -> UserOperation {
+        UserOperation {
+            nonce: U256::from(nonce),
+            call_data: Bytes::from(vec![1, 2, 3]),
+            signature: Bytes::from(vec![4; 65]),
+            ..UserOperation::random()
+        }
+    }
+
+    #[test]
+    fn user_operations_with_entry_point_gossip() {
+        let user_operations = vec![user_operation(0), user_operation(1)];
+        let message = UserOperationsWithEntryPoint::new(
+            Address::random(),
+            H256::random(),
+            U256::from(5),
+            user_operations.clone(),
+        );
+
+        let data = message.encode_gossip().unwrap();
+        let decoded = UserOperationsWithEntryPoint::decode_gossip(&data).unwrap();
+        assert_eq!(decoded, message);
+        assert_eq!(decoded.user_operations(), user_operations);
+
+        assert!(UserOperationsWithEntryPoint::decode_gossip(&[1, 2, 3]).is_err());
+        assert_eq!(message_id(&data).len(), 20);
+        assert_ne!(message_id(&data), message_id(&[1, 2, 3]));
+    }
+}
@@ -1,4 +1,6 @@
+use ethers::types::{Address, H256};
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Deserialize)]
 pub enum Mode {
@@ -9,3 +11,6 @@ pub enum Mode {
 }
 
 pub const DEFAULT_INTERVAL: u64 = 10;
+
+// Storage slots read during validation of user operations, by contract address
+pub type StorageMap = HashMap<Address, HashSet<H256>>;
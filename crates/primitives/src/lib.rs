@@ -4,22 +4,27 @@ mod bundler;
 mod error_codes;
 mod reputation;
 mod sanity_check;
+mod signer;
 mod simulation;
 mod user_operation;
 mod utils;
 mod wallet;
 
-pub use bundler::{Mode, DEFAULT_INTERVAL};
+pub use bundler::{Mode, StorageMap, DEFAULT_INTERVAL};
 pub use error_codes::*;
 pub use reputation::{
     BadReputationError, ReputationEntry, ReputationStatus, StakeInfo, BAN_SLACK,
     MIN_INCLUSION_RATE_DENOMINATOR, THROTTLED_MAX_INCLUDE, THROTTLING_SLACK,
 };
 pub use sanity_check::SanityCheckError;
-pub use simulation::{CodeHash, SimulationError, EXPIRATION_TIMESTAMP_DIFF};
+pub use signer::{BundlerSigner, RemoteSigner, SharedSigner};
+pub use simulation::{
+    CodeHash, CodeHashChange, CodeHashChangeKind, EntityValidationReport, RuleViolation,
+    SimulationError, ValidationReport, EXPIRATION_TIMESTAMP_DIFF,
+};
 pub use user_operation::{
-    UserOperation, UserOperationByHash, UserOperationGasEstimation, UserOperationHash,
-    UserOperationPartial, UserOperationReceipt,
+    UserOperation, UserOperationByHash, UserOperationGasEstimation, UserOperationGasPrice,
+    UserOperationGasPriceSuggestion, UserOperationHash, UserOperationPartial, UserOperationReceipt,
 };
 pub use utils::{get_addr, parse_address, parse_u256};
-pub use wallet::Wallet;
+pub use wallet::{read_keystore_password, read_private_key, Wallet};
@@ -0,0 +1,249 @@
+use anyhow::format_err;
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, U256},
+    utils::rlp::Rlp,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::wallet::Wallet;
+
+pub type SharedSigner = Arc<dyn BundlerSigner>;
+
+// Signer used by the bundler to sign bundle (handleOps) transactions.
+#[async_trait]
+pub trait BundlerSigner: Send + Sync {
+    fn address(&self) -> Address;
+    fn chain_id(&self) -> u64;
+    // Returns RLP encoded signed transaction, ready for eth_sendRawTransaction
+    async fn sign_transaction(&self, transaction: &TypedTransaction) -> anyhow::Result<Bytes>;
+}
+
+#[async_trait]
+impl BundlerSigner for Wallet {
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.signer.chain_id()
+    }
+
+    async fn sign_transaction(&self, transaction: &TypedTransaction) -> anyhow::Result<Bytes> {
+        let signature = self.signer.sign_transaction(transaction).await?;
+        Ok(transaction.rlp_signed(&signature))
+    }
+}
+
+// Web3Signer returns raw transaction, Geth/Clef return an object with raw and decoded transaction
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SignTransactionResponse {
+    Raw(Bytes),
+    Object { raw: Bytes },
+}
+
+// External signer that signs transactions over JSON-RPC (eth_signTransaction).
+#[derive(Clone, Debug)]
+pub struct RemoteSigner {
+    provider: Provider<Http>,
+    address: Address,
+    chain_id: u64,
+}
+
+impl RemoteSigner {
+    pub async fn new(url: &str, address: Option<Address>, chain_id: U256) -> anyhow::Result<Self> {
+        let provider = Provider::<Http>::try_from(url)?;
+
+        let address = match address {
+            Some(address) => address,
+            None => provider
+                .get_accounts()
+                .await?
+                .first()
+                .copied()
+                .ok_or_else(|| format_err!("Remote signer at {url} has no accounts"))?,
+        };
+
+        Ok(Self {
+            provider,
+            address,
+            chain_id: chain_id.as_u64(),
+        })
+    }
+}
+
+#[async_trait]
+impl BundlerSigner for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    async fn sign_transaction(&self, transaction: &TypedTransaction) -> anyhow::Result<Bytes> {
+        let mut transaction = transaction.clone();
+        transaction.set_from(self.address);
+        if transaction.chain_id().is_none() {
+            transaction.set_chain_id(self.chain_id);
+        }
+
+        // chain id is skipped during transaction serialization, so it has to be added explicitly
+        let mut request = serde_json::to_value(&transaction)?;
+        if let Some(request) = request.as_object_mut() {
+            request.insert(
+                "chainId".to_string(),
+                serde_json::to_value(transaction.chain_id())?,
+            );
+        }
+
+        let raw = match self
+            .provider
+            .request::<_, SignTransactionResponse>("eth_signTransaction", [request])
+            .await?
+        {
+            SignTransactionResponse::Raw(raw) => raw,
+            SignTransactionResponse::Object { raw } => raw,
+        };
+
+        // make sure the remote signer signed what we asked for with the expected key
+        let (signed_transaction, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw))
+            .map_err(|error| format_err!("Remote signer returned invalid transaction: {error}"))?;
+        let sighash = signed_transaction.sighash();
+        if sighash != transaction.sighash() {
+            return Err(format_err!(
+                "Remote signer signed {signed_transaction:?} instead of {transaction:?}"
+            ));
+        }
+        let signer = signature.recover(sighash)?;
+        if signer != self.address {
+            return Err(format_err!(
+                "Remote signer signed with {signer:?} instead of {:?}",
+                self.address
+            ));
+        }
+
+        Ok(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{signers::LocalWallet, types::TransactionRequest};
+    use jsonrpsee::{server::ServerBuilder, RpcModule};
+
+    const KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    // stand-in for an external signer (e.g. Web3Signer), which may change the nonce before signing
+    async fn start_signer(wallet: LocalWallet, change_nonce: bool) -> String {
+        let server = ServerBuilder::default().build("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap();
+
+        let mut module = RpcModule::new((wallet, change_nonce));
+        module
+            .register_method("eth_accounts", |_, (wallet, _)| Ok(vec![wallet.address()]))
+            .unwrap();
+        module
+            .register_method("eth_signTransaction", |params, (wallet, change_nonce)| {
+                let mut transaction: TypedTransaction = params.one()?;
+                if *change_nonce {
+                    transaction.set_nonce(transaction.nonce().copied().unwrap_or_default() + 1);
+                }
+                let signature = wallet
+                    .sign_transaction_sync(&transaction)
+                    .map_err(|error| jsonrpsee::core::Error::Custom(error.to_string()))?;
+                Ok(transaction.rlp_signed(&signature))
+            })
+            .unwrap();
+
+        let handle = server.start(module).unwrap();
+        tokio::spawn(handle.stopped());
+
+        format!("http://{address}")
+    }
+
+    #[tokio::test]
+    async fn remote_signer() {
+        let wallet = KEY.parse::<LocalWallet>().unwrap();
+        let url = start_signer(wallet.clone(), false).await;
+
+        let signer = RemoteSigner::new(&url, None, U256::from(1337))
+            .await
+            .unwrap();
+        assert_eq!(BundlerSigner::address(&signer), wallet.address());
+
+        let transaction: TypedTransaction = TransactionRequest::new()
+            .to(Address::random())
+            .nonce(1)
+            .gas(100000)
+            .gas_price(1000000000)
+            .data(Bytes::from(vec![1, 2, 3]))
+            .into();
+        let raw = BundlerSigner::sign_transaction(&signer, &transaction)
+            .await
+            .unwrap();
+
+        let (signed_transaction, signature) =
+            TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+        assert_eq!(
+            signature.recover(signed_transaction.sighash()).unwrap(),
+            wallet.address()
+        );
+        assert_eq!(signed_transaction.chain_id(), Some(1337.into()));
+
+        // local key is the default implementation and must produce the same result
+        let local = Wallet {
+            signer: wallet.with_chain_id(1337_u64),
+        };
+        let mut transaction = transaction;
+        transaction
+            .set_from(wallet.address())
+            .set_chain_id(1337_u64);
+        assert_eq!(
+            BundlerSigner::sign_transaction(&local, &transaction)
+                .await
+                .unwrap(),
+            raw
+        );
+    }
+
+    #[tokio::test]
+    async fn remote_signer_wrong_key() {
+        let url = start_signer(KEY.parse::<LocalWallet>().unwrap(), false).await;
+
+        let signer = RemoteSigner::new(&url, Some(Address::random()), U256::from(1))
+            .await
+            .unwrap();
+        let transaction: TypedTransaction = TransactionRequest::new()
+            .to(Address::random())
+            .nonce(0)
+            .gas(21000)
+            .gas_price(1)
+            .into();
+        assert!(BundlerSigner::sign_transaction(&signer, &transaction)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn remote_signer_changed_transaction() {
+        let url = start_signer(KEY.parse::<LocalWallet>().unwrap(), true).await;
+
+        let signer = RemoteSigner::new(&url, None, U256::from(1)).await.unwrap();
+        let transaction: TypedTransaction = TransactionRequest::new()
+            .to(Address::random())
+            .nonce(0)
+            .gas(21000)
+            .gas_price(1)
+            .into();
+        assert!(BundlerSigner::sign_transaction(&signer, &transaction)
+            .await
+            .is_err());
+    }
+}
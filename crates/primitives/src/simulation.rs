@@ -4,6 +4,9 @@ use ethers::{
 };
 use jsonrpsee::types::ErrorObject;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::StakeInfo;
 
 pub type SimulationError = ErrorObject<'static>;
 
@@ -26,3 +29,71 @@ pub struct CodeHash {
     pub address: Address,
     pub hash: H256,
 }
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeHashChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for CodeHashChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added => write!(f, "added"),
+            Self::Removed => write!(f, "removed"),
+            Self::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+// Code hash of a contract accessed during validation that differs from the previous simulation
+// (none if the contract wasn't accessed in that simulation)
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CodeHashChange {
+    pub address: Address,
+    pub change: CodeHashChangeKind,
+    pub previous: Option<H256>,
+    pub current: Option<H256>,
+}
+
+// Violated validation rule (signature, timestamps, opcodes, storage_access, call_stack, ...)
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleViolation {
+    pub rule: String,
+    // ERC-7562 rule (e.g. STO-021 or OP-011)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<Address>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opcode: Option<String>,
+    // addresses of the calls leading to the violation, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub call_path: Vec<Address>,
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityValidationReport {
+    // factory, account or paymaster
+    pub entity: String,
+    pub stake_info: StakeInfo,
+    pub staked: bool,
+    pub violations: Vec<RuleViolation>,
+}
+
+// Result of all validation rules of a user operation (without stopping at the first violation)
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub entities: Vec<EntityValidationReport>,
+    // violations that aren't caused by a single entity
+    pub violations: Vec<RuleViolation>,
+}
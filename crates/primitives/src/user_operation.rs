@@ -189,9 +189,10 @@ pub struct UserOperationByHash {
     pub user_operation: UserOperation,
     #[serde(serialize_with = "as_checksum")]
     pub entry_point: Address,
-    pub block_number: BlockNumber,
-    pub block_hash: H256,
-    pub transaction_hash: H256,
+    // block fields are null for user operations that are still in the mempool
+    pub block_number: Option<BlockNumber>,
+    pub block_hash: Option<H256>,
+    pub transaction_hash: Option<H256>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -291,6 +292,20 @@ pub struct UserOperationGasEstimation {
     pub call_gas_limit: U256,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationGasPrice {
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserOperationGasPriceSuggestion {
+    pub slow: UserOperationGasPrice,
+    pub standard: UserOperationGasPrice,
+    pub fast: UserOperationGasPrice,
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -1,10 +1,12 @@
+use anyhow::format_err;
 use ethers::{
     prelude::{k256::ecdsa::SigningKey, rand},
-    signers::{coins_bip39::English, MnemonicBuilder, Signer},
+    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer},
     types::U256,
+    utils::to_checksum,
 };
 use expanded_pathbuf::ExpandedPathBuf;
-use std::fs;
+use std::{env, fs};
 
 #[derive(Clone)]
 pub struct Wallet {
@@ -26,6 +28,29 @@ impl Wallet {
         })
     }
 
+    // Create a random wallet and store it as an encrypted JSON keystore.
+    // The keystore file is named after the checksummed address of the wallet.
+    pub fn new_keystore(
+        output_path: ExpandedPathBuf,
+        password: &str,
+        chain_id: U256,
+    ) -> anyhow::Result<Self> {
+        let mut rng = rand::thread_rng();
+
+        fs::create_dir_all(&output_path)?;
+
+        let (wallet, uuid) =
+            LocalWallet::new_keystore(output_path.to_path_buf(), &mut rng, password, None)?;
+        fs::rename(
+            output_path.join(uuid),
+            output_path.join(format!("{}.json", to_checksum(&wallet.address(), None))),
+        )?;
+
+        Ok(Self {
+            signer: wallet.with_chain_id(chain_id.as_u64()),
+        })
+    }
+
     pub fn from_file(input_path: ExpandedPathBuf, chain_id: U256) -> anyhow::Result<Self> {
         let wallet = MnemonicBuilder::<English>::default()
             .phrase(input_path.to_path_buf())
@@ -35,4 +60,126 @@ impl Wallet {
             signer: wallet.with_chain_id(chain_id.as_u64()),
         })
     }
+
+    pub fn from_keystore(
+        input_path: ExpandedPathBuf,
+        password: &str,
+        chain_id: U256,
+    ) -> anyhow::Result<Self> {
+        let wallet = LocalWallet::decrypt_keystore(input_path.to_path_buf(), password)?;
+
+        Ok(Self {
+            signer: wallet.with_chain_id(chain_id.as_u64()),
+        })
+    }
+
+    pub fn from_private_key(private_key: &str, chain_id: U256) -> anyhow::Result<Self> {
+        let wallet = private_key.trim().parse::<LocalWallet>()?;
+
+        Ok(Self {
+            signer: wallet.with_chain_id(chain_id.as_u64()),
+        })
+    }
+}
+
+// Read a secret from the environment variable with the given name, or from a file (trailing
+// newline is ignored), so it doesn't show up in the command line.
+fn read_secret(
+    title: &str,
+    secret_env: Option<&str>,
+    secret_file: Option<&ExpandedPathBuf>,
+) -> anyhow::Result<String> {
+    match (secret_env, secret_file) {
+        (Some(name), _) => {
+            env::var(name).map_err(|_| format_err!("Environment variable {name} is not set"))
+        }
+        (None, Some(path)) => Ok(fs::read_to_string(path)?
+            .trim_end_matches(['\r', '\n'])
+            .to_string()),
+        (None, None) => Err(format_err!(
+            "{title} is missing (use environment variable or file)"
+        )),
+    }
+}
+
+pub fn read_keystore_password(
+    password_env: Option<&str>,
+    password_file: Option<&ExpandedPathBuf>,
+) -> anyhow::Result<String> {
+    read_secret("Keystore password", password_env, password_file)
+}
+
+pub fn read_private_key(
+    private_key_env: Option<&str>,
+    private_key_file: Option<&ExpandedPathBuf>,
+) -> anyhow::Result<String> {
+    read_secret("Private key", private_key_env, private_key_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn wallet_keystore() {
+        let dir = TempDir::new("test-wallet-keystore").unwrap();
+        let path = ExpandedPathBuf(dir.path().to_path_buf());
+
+        let wallet = Wallet::new_keystore(path.clone(), "password", U256::from(5)).unwrap();
+        let keystore_path = ExpandedPathBuf(path.join(format!(
+            "{}.json",
+            to_checksum(&wallet.signer.address(), None)
+        )));
+
+        let wallet_decrypted =
+            Wallet::from_keystore(keystore_path.clone(), "password", U256::from(5)).unwrap();
+        assert_eq!(wallet.signer.address(), wallet_decrypted.signer.address());
+        assert_eq!(wallet_decrypted.signer.chain_id(), 5);
+
+        assert!(Wallet::from_keystore(keystore_path, "wrong", U256::from(5)).is_err());
+    }
+
+    #[test]
+    fn wallet_private_key() {
+        let wallet = Wallet::from_private_key(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80\n",
+            U256::from(1337),
+        )
+        .unwrap();
+        assert_eq!(
+            to_checksum(&wallet.signer.address(), None),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+        );
+        assert_eq!(wallet.signer.chain_id(), 1337);
+
+        assert!(Wallet::from_private_key("0x1234", U256::from(1)).is_err());
+    }
+
+    #[test]
+    fn keystore_password() {
+        let dir = TempDir::new("test-wallet-password").unwrap();
+        let path = ExpandedPathBuf(dir.path().join("password"));
+        fs::write(&path, "secret\n").unwrap();
+
+        assert_eq!(read_keystore_password(None, Some(&path)).unwrap(), "secret");
+        assert!(read_keystore_password(None, None).is_err());
+    }
+
+    #[test]
+    fn private_key() {
+        let dir = TempDir::new("test-wallet-private-key").unwrap();
+        let path = ExpandedPathBuf(dir.path().join("private-key"));
+        let key = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+        fs::write(&path, format!("{key}\n")).unwrap();
+
+        assert_eq!(read_private_key(None, Some(&path)).unwrap(), key);
+        env::set_var("AA_BUNDLER_TEST_PRIVATE_KEY", key);
+        assert_eq!(
+            read_private_key(Some("AA_BUNDLER_TEST_PRIVATE_KEY"), None).unwrap(),
+            key
+        );
+        assert!(read_private_key(Some("AA_BUNDLER_TEST_MISSING_KEY"), None).is_err());
+        assert!(read_private_key(None, None).is_err());
+    }
 }
@@ -0,0 +1,36 @@
+use aa_bundler_grpc::{uo_pool_client::UoPoolClient, GetUserOperationGasPriceRequest};
+use aa_bundler_primitives::UserOperationGasPriceSuggestion;
+use anyhow::format_err;
+use async_trait::async_trait;
+use ethers::types::Address;
+use jsonrpsee::core::RpcResult;
+use tracing::trace;
+
+use crate::bundler_api::BundlerApiServer;
+
+pub struct BundlerApiServerImpl {
+    pub uopool_grpc_client: UoPoolClient<tonic::transport::Channel>,
+}
+
+#[async_trait]
+impl BundlerApiServer for BundlerApiServerImpl {
+    async fn get_user_operation_gas_price(
+        &self,
+        entry_point: Address,
+    ) -> RpcResult<UserOperationGasPriceSuggestion> {
+        let mut uopool_grpc_client = self.uopool_grpc_client.clone();
+
+        let request = tonic::Request::new(GetUserOperationGasPriceRequest {
+            ep: Some(entry_point.into()),
+        });
+
+        let response = uopool_grpc_client
+            .get_user_operation_gas_price(request)
+            .await
+            .map_err(|status| format_err!("GRPC error (uopool): {}", status.message()))?
+            .into_inner();
+        trace!("Get user operation gas price response: {response:?}");
+
+        Ok(response.into())
+    }
+}
@@ -0,0 +1,12 @@
+use aa_bundler_primitives::UserOperationGasPriceSuggestion;
+use ethers::types::Address;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+
+#[rpc(server, namespace = "bundler")]
+pub trait BundlerApi {
+    #[method(name = "getUserOperationGasPrice")]
+    async fn get_user_operation_gas_price(
+        &self,
+        entry_point: Address,
+    ) -> RpcResult<UserOperationGasPriceSuggestion>;
+}
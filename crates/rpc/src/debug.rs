@@ -1,9 +1,12 @@
 use aa_bundler_grpc::{
     bundler_client::BundlerClient, uo_pool_client::UoPoolClient, ClearResult,
-    GetAllReputationRequest, GetAllReputationResult, GetAllRequest, GetAllResult, Mode as GrpcMode,
-    SetModeRequest, SetReputationRequest, SetReputationResult,
+    GetAllReputationRequest, GetAllReputationResult, GetAllRequest, GetAllResult,
+    GetValidationReportRequest, Mode as GrpcMode, SetModeRequest, SetReputationRequest,
+    SetReputationResult,
+};
+use aa_bundler_primitives::{
+    Mode, ReputationEntry, UserOperation, ValidationReport, DEFAULT_INTERVAL,
 };
-use aa_bundler_primitives::{Mode, ReputationEntry, UserOperation, DEFAULT_INTERVAL};
 use anyhow::format_err;
 use async_trait::async_trait;
 use ethers::types::{Address, H256};
@@ -143,4 +146,26 @@ impl DebugApiServer for DebugApiServerImpl {
             ))),
         }
     }
+
+    async fn validation_report(
+        &self,
+        user_operation: UserOperation,
+        entry_point: Address,
+    ) -> RpcResult<ValidationReport> {
+        let mut uopool_grpc_client = self.uopool_grpc_client.clone();
+
+        let request = tonic::Request::new(GetValidationReportRequest {
+            uo: Some(user_operation.into()),
+            ep: Some(entry_point.into()),
+        });
+
+        let response = uopool_grpc_client
+            .get_validation_report(request)
+            .await
+            .map_err(|status| format_err!("GRPC error (uopool): {}", status.message()))?
+            .into_inner();
+
+        Ok(serde_json::from_str::<ValidationReport>(&response.report)
+            .map_err(|error| format_err!("Error parsing validation report: {error:?}"))?)
+    }
 }
@@ -1,4 +1,4 @@
-use aa_bundler_primitives::{Mode, ReputationEntry, UserOperation};
+use aa_bundler_primitives::{Mode, ReputationEntry, UserOperation, ValidationReport};
 use ethers::types::{Address, H256};
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
 
@@ -25,4 +25,11 @@ pub trait DebugApi {
 
     #[method(name = "sendBundleNow")]
     async fn send_bundle_now(&self) -> RpcResult<H256>;
+
+    #[method(name = "validationReport")]
+    async fn validation_report(
+        &self,
+        user_operation: UserOperation,
+        entry_point: Address,
+    ) -> RpcResult<ValidationReport>;
 }
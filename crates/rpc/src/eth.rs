@@ -11,6 +11,7 @@ use aa_bundler_primitives::{
 use anyhow::format_err;
 use async_trait::async_trait;
 use ethers::{
+    providers::call_raw::spoof,
     types::{Address, U64},
     utils::to_checksum,
 };
@@ -92,12 +93,20 @@ impl EthApiServer for EthApiServerImpl {
         &self,
         user_operation: UserOperationPartial,
         entry_point: Address,
+        state_override: Option<spoof::State>,
     ) -> RpcResult<UserOperationGasEstimation> {
         let mut uopool_grpc_client = self.uopool_grpc_client.clone();
 
+        let state_override = match state_override {
+            Some(state_override) => serde_json::to_string(&state_override)
+                .map_err(|err| format_err!("error serializing state override: {}", err))?,
+            None => String::new(),
+        };
+
         let request = tonic::Request::new(EstimateUserOperationGasRequest {
             uo: Some(UserOperation::from(user_operation).into()),
             ep: Some(entry_point.into()),
+            state_override,
         });
 
         let response = uopool_grpc_client
@@ -148,7 +157,7 @@ impl EthApiServer for EthApiServerImpl {
                                 actual_gas_cost: result.actual_gas_cost?.into(),
                                 actual_gas_used: result.actual_gas_used?.into(),
                                 success: result.success,
-                                reason: String::new(),
+                                reason: result.reason,
                                 logs: result.logs.into_iter().map(|l| l.into()).collect(),
                                 receipt: result.transaction_receipt?.into(),
                             })
@@ -202,12 +211,13 @@ impl EthApiServer for EthApiServerImpl {
                         let uo: Option<UserOperationByHash> =
                             result.user_operation.and_then(|user_operation| {
                                 let entry_point = result.entry_point?.into();
-                                let block_hash = result.block_hash?.into();
-                                let transaction_hash = result.transaction_hash?.into();
+                                // pending user operations don't have a block hash
+                                let block_hash = result.block_hash.map(Into::into);
+                                let transaction_hash = result.transaction_hash.map(Into::into);
                                 Some(UserOperationByHash {
                                     user_operation: user_operation.into(),
                                     entry_point,
-                                    block_number: result.block_number.into(),
+                                    block_number: block_hash.map(|_| result.block_number.into()),
                                     block_hash,
                                     transaction_hash,
                                 })
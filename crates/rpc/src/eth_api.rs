@@ -2,7 +2,10 @@ use aa_bundler_primitives::{
     UserOperation, UserOperationByHash, UserOperationGasEstimation, UserOperationHash,
     UserOperationPartial, UserOperationReceipt,
 };
-use ethers::types::{Address, U64};
+use ethers::{
+    providers::call_raw::spoof,
+    types::{Address, U64},
+};
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
 
 #[rpc(server, namespace = "eth")]
@@ -25,6 +28,7 @@ pub trait EthApi {
         &self,
         user_operation: UserOperationPartial,
         entry_point: Address,
+        state_override: Option<spoof::State>,
     ) -> RpcResult<UserOperationGasEstimation>;
 
     #[method(name = "getUserOperationReceipt")]
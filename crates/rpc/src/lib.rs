@@ -1,10 +1,14 @@
 #![allow(dead_code)]
 
+mod bundler;
+mod bundler_api;
 mod debug;
 mod debug_api;
 mod eth;
 mod eth_api;
 
+pub use bundler::BundlerApiServerImpl;
+pub use bundler_api::BundlerApiServer;
 pub use debug::DebugApiServerImpl;
 pub use debug_api::DebugApiServer;
 pub use eth::EthApiServerImpl;
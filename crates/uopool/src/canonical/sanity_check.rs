@@ -8,12 +8,9 @@ use ethers::{
 };
 use jsonrpsee::types::error::ErrorCode;
 
-use crate::{
-    utils::{calculate_valid_gas, Overhead},
-    UoPool,
-};
+use crate::{canonical::simulation::SimulateValidationError, utils::calculate_valid_gas, UoPool};
 
-const MAX_UOS_PER_UNSTAKED_SENDER: usize = 4;
+pub(crate) const MAX_UOS_PER_UNSTAKED_SENDER: usize = 4;
 const GAS_INCREASE_PERC: u64 = 10;
 
 #[derive(Debug)]
@@ -202,7 +199,7 @@ impl<M: Middleware + 'static> UoPool<M> {
         Ok(())
     }
 
-    fn verification_gas(
+    async fn verification_gas(
         &self,
         user_operation: &UserOperation,
     ) -> Result<(), BadUserOperationError<M>> {
@@ -213,8 +210,12 @@ impl<M: Middleware + 'static> UoPool<M> {
             });
         }
 
-        let calculated_pre_verification_gas =
-            Overhead::default().calculate_pre_verification_gas(user_operation);
+        let calculated_pre_verification_gas = self
+            .calculate_pre_verification_gas(user_operation)
+            .await
+            .map_err(|error| BadUserOperationError::UnknownError {
+                error: error.to_string(),
+            })?;
         if user_operation.pre_verification_gas < calculated_pre_verification_gas {
             return Err(BadUserOperationError::LowPreVerificationGas {
                 pre_verification_gas: user_operation.pre_verification_gas,
@@ -274,12 +275,17 @@ impl<M: Middleware + 'static> UoPool<M> {
         &self,
         user_operation: &UserOperation,
     ) -> Result<(), BadUserOperationError<M>> {
+        // same estimation as eth_estimateUserOperationGas, without state override
         let call_gas_estimation = self
-            .entry_point
-            .estimate_call_gas(user_operation.clone())
+            .estimate_call_gas_limit(user_operation, &Default::default())
             .await
-            .map_err(|error| BadUserOperationError::UnknownError {
-                error: format!("{error:?}"),
+            .map_err(|error| match error {
+                SimulateValidationError::UserOperationExecution { message } => {
+                    BadUserOperationError::UserOperationExecution { message }
+                }
+                _ => BadUserOperationError::UnknownError {
+                    error: format!("{error:?}"),
+                },
             })?;
 
         if user_operation.call_gas_limit >= call_gas_estimation {
@@ -419,7 +425,7 @@ impl<M: Middleware + 'static> UoPool<M> {
         self.sender_or_init_code(user_operation).await?;
 
         // The verificationGasLimit is sufficiently low (<= MAX_VERIFICATION_GAS) and the preVerificationGas is sufficiently high (enough to pay for the calldata gas cost of serializing the UserOperation plus PRE_VERIFICATION_OVERHEAD_GAS)
-        self.verification_gas(user_operation)?;
+        self.verification_gas(user_operation).await?;
 
         // The paymasterAndData is either empty, or start with the paymaster address, which is a contract that (i) currently has nonempty code on chain, (ii) has a sufficient deposit to pay for the UserOperation, and (iii) is not currently banned. During simulation, the paymaster's stake is also checked, depending on its storage usage - see reputation, throttling and banning section for details.
         self.verify_paymaster(user_operation).await?;
@@ -1,15 +1,17 @@
 use aa_bundler_contracts::{
     Call, CallEntry, EntryPointErr, JsTracerFrame, SimulateValidationResult,
-    ValidatePaymasterUserOpReturn, CONTRACTS_FUNCTIONS,
+    ValidatePaymasterUserOpReturn, ValidationTracer, CONTRACTS_FUNCTIONS,
 };
 use aa_bundler_primitives::{
-    CodeHash, SimulationError, StakeInfo, UserOperation, EXECUTION_ERROR_CODE,
-    EXPIRATION_TIMESTAMP_DIFF, OPCODE_VALIDATION_ERROR_CODE, SIGNATURE_FAILED_ERROR_CODE,
-    SIMULATE_VALIDATION_ERROR_CODE, TIMESTAMP_VALIDATION_ERROR_CODE,
+    CodeHash, CodeHashChange, EntityValidationReport, ReputationError, ReputationStatus,
+    RuleViolation, SimulationError, StakeInfo, StorageMap, UserOperation, ValidationReport,
+    ENTITY_BANNED_ERROR_CODE, EXECUTION_ERROR_CODE, EXPIRATION_TIMESTAMP_DIFF,
+    OPCODE_VALIDATION_ERROR_CODE, SIGNATURE_FAILED_ERROR_CODE, SIMULATE_VALIDATION_ERROR_CODE,
+    TIMESTAMP_VALIDATION_ERROR_CODE,
 };
 use ethers::{
     abi::AbiDecode,
-    providers::Middleware,
+    providers::{call_raw::spoof, Middleware},
     types::{Address, Bytes, GethTrace, H256, U256},
     utils::keccak256,
 };
@@ -23,12 +25,18 @@ use std::{
 use tokio::task::JoinSet;
 use tracing::trace;
 
-use crate::{utils::equal_code_hashes, UoPool};
+use crate::{
+    canonical::sanity_check::MAX_UOS_PER_UNSTAKED_SENDER,
+    rules::{ValidationContext, ValidationRule},
+    simulator::trace_call,
+    utils::code_hashes_diff,
+    UoPool,
+};
 
 // https://github.com/eth-infinitism/account-abstraction/blob/develop/contracts/core/EntryPoint.sol#L514
 // 0 - factory, 1 - sender/account, 2 - paymaster
 // opcode NUMBER is marker between levels
-const NUMBER_LEVELS: usize = 3;
+pub(crate) const NUMBER_LEVELS: usize = 3;
 const LEVEL_TO_ENTITY: [&str; NUMBER_LEVELS] = ["factory", "account", "paymaster"];
 
 lazy_static! {
@@ -57,9 +65,128 @@ lazy_static! {
     static ref REVERT_OPCODE: String = "REVERT".to_string();
     static ref CREATE_OPCODE: String = "CREATE".to_string();
     static ref PAYMASTER_VALIDATION_FUNCTION: String = "validatePaymasterUserOp".to_string();
+    static ref DEPOSIT_TO_FUNCTION: String = "depositTo".to_string();
 }
 
-#[derive(Debug)]
+// entity whose validation made the call (the first entity in the call path)
+fn call_entity(
+    call: &CallEntry,
+    stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
+) -> Option<String> {
+    call.path.iter().chain(call.to.iter()).find_map(|address| {
+        stake_info_by_entity
+            .iter()
+            .position(|stake_info| !stake_info.address.is_zero() && stake_info.address == *address)
+            .map(|index| LEVEL_TO_ENTITY[index].to_string())
+    })
+}
+
+fn call_path(call: &CallEntry) -> Vec<Address> {
+    call.path.iter().chain(call.to.iter()).copied().collect()
+}
+
+// precompiled contracts (ecrecover to the Cancun KZG point evaluation) don't have code
+fn is_precompile(address: &Address) -> bool {
+    *address >= Address::from_low_u64_be(1) && *address <= Address::from_low_u64_be(0x0a)
+}
+
+// ERC-7562 rule violated by the opcode, none if the entity may use it
+fn opcode_rule_violation(opcode: &str, staked: bool) -> Option<&'static str> {
+    match opcode {
+        // balances may only be read by staked entities
+        "BALANCE" | "SELFBALANCE" if staked => None,
+        "BALANCE" | "SELFBALANCE" => Some("OP-080"),
+        // the tracer only collects GAS that isn't followed by a call
+        "GAS" => Some("OP-012"),
+        _ if FORBIDDEN_OPCODES.contains(opcode) => Some("OP-011"),
+        _ => None,
+    }
+}
+
+// Accessed slot of a contract other than the sender (which storage is always allowed, STO-010)
+// and the entry point
+struct SlotAccess<'a> {
+    // index of the entity whose validation accessed the slot
+    index: usize,
+    address: &'a Address,
+    write: bool,
+    associated_with_sender: bool,
+    associated_with_entity: bool,
+}
+
+// ERC-7562 storage rule violated by the access (rule id, allowed if the entity is staked, reason),
+// none if the access is allowed
+fn storage_rule_violation(
+    access: &SlotAccess,
+    stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
+    staked: &[bool; NUMBER_LEVELS],
+) -> Option<(&'static str, bool, String)> {
+    let entity_staked = staked[access.index];
+
+    if access.associated_with_sender {
+        // STO-021: the account already exists, STO-022: the account is deployed by a staked factory
+        let deploying_sender = !stake_info_by_entity[0].address.is_zero();
+        if !deploying_sender || staked[0] {
+            return None;
+        }
+        return Some((
+            "STO-022",
+            access.index == 0,
+            "slot is associated with the sender, which is being deployed by an unstaked factory"
+                .to_string(),
+        ));
+    }
+
+    // STO-031: staked entities may access their own storage
+    if *access.address == stake_info_by_entity[access.index].address {
+        if entity_staked {
+            return None;
+        }
+        return Some((
+            "STO-031",
+            true,
+            "storage of the entity and the entity isn't staked".to_string(),
+        ));
+    }
+
+    // the relaxations of staked entities only apply to non-entity contracts
+    if stake_info_by_entity
+        .iter()
+        .any(|stake_info| !stake_info.address.is_zero() && stake_info.address == *access.address)
+    {
+        return Some(("STO-033", false, "storage of another entity".to_string()));
+    }
+
+    // STO-032: staked entities may access slots associated with them in non-entity contracts
+    if access.associated_with_entity {
+        if entity_staked {
+            return None;
+        }
+        return Some((
+            "STO-032",
+            true,
+            "slot is associated with the entity and the entity isn't staked".to_string(),
+        ));
+    }
+
+    // STO-033: staked entities may read any slot of non-entity contracts
+    match (access.write, entity_staked) {
+        (false, true) => None,
+        (false, false) => Some((
+            "STO-033",
+            true,
+            "slot isn't associated with the sender or the entity and the entity isn't staked"
+                .to_string(),
+        )),
+        (true, _) => Some((
+            "STO-033",
+            false,
+            "write to a slot that isn't associated with the sender or the entity".to_string(),
+        )),
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum SimulateValidationError {
     SignatureValidation {},
     ExpirationValidation {
@@ -73,18 +200,45 @@ pub enum SimulateValidationError {
     OpcodeValidation {
         entity: String,
         opcode: String,
+        rule_id: String,
     },
     UserOperationExecution {
         message: String,
     },
     StorageAccessValidation {
+        entity: String,
+        address: Address,
         slot: String,
+        // read or write
+        access: String,
+        // true if the access would be allowed with a staked entity
+        allowed_if_staked: bool,
+        reason: String,
+        rule_id: String,
     },
     CallStackValidation {
+        entity: Option<String>,
+        call_path: Vec<Address>,
         message: String,
+        rule_id: String,
     },
     CodeHashesValidation {
         message: String,
+        changes: Vec<CodeHashChange>,
+    },
+    OutOfGas {
+        entity: String,
+    },
+    UndeployedContractAccess {
+        entity: String,
+        address: Address,
+    },
+    // banned entity, or unstaked entity which needs a stake
+    StakeValidation {
+        entity: String,
+        address: Address,
+        code: i32,
+        message: String,
     },
     UnknownError {
         error: String,
@@ -121,47 +275,745 @@ impl From<SimulateValidationError> for SimulationError {
             SimulateValidationError::UserOperationRejected { message } => {
                 SimulationError::owned(SIMULATE_VALIDATION_ERROR_CODE, message, None::<bool>)
             }
-            SimulateValidationError::OpcodeValidation { entity, opcode } => SimulationError::owned(
+            SimulateValidationError::OpcodeValidation {
+                entity,
+                opcode,
+                rule_id,
+            } => SimulationError::owned(
                 OPCODE_VALIDATION_ERROR_CODE,
                 format!("{entity} uses banned opcode: {opcode}"),
-                None::<bool>,
+                Some(json!({ "entity": entity, "opcode": opcode, "rule_id": rule_id })),
             ),
             SimulateValidationError::UserOperationExecution { message } => {
                 SimulationError::owned(EXECUTION_ERROR_CODE, message, None::<bool>)
             }
-            SimulateValidationError::StorageAccessValidation { slot } => SimulationError::owned(
+            SimulateValidationError::StorageAccessValidation {
+                entity,
+                address,
+                slot,
+                access,
+                allowed_if_staked,
+                reason,
+                rule_id,
+            } => SimulationError::owned(
                 OPCODE_VALIDATION_ERROR_CODE,
-                format!("Storage access validation failed for slot: {slot}"),
-                None::<bool>,
+                format!("Storage access validation failed for slot: {slot} ({entity} {access} of {address:?}: {reason})"),
+                Some(json!({
+                    "entity": entity, "address": address, "slot": slot, "access": access,
+                    "allowed_if_staked": allowed_if_staked, "reason": reason, "rule_id": rule_id,
+                })),
             ),
-            SimulateValidationError::CallStackValidation { message } => {
-                SimulationError::owned(OPCODE_VALIDATION_ERROR_CODE, message, None::<bool>)
+            SimulateValidationError::CallStackValidation {
+                message, rule_id, ..
+            } => SimulationError::owned(
+                OPCODE_VALIDATION_ERROR_CODE,
+                message,
+                Some(json!({ "rule_id": rule_id })),
+            ),
+            SimulateValidationError::CodeHashesValidation { message, changes } => {
+                SimulationError::owned(
+                    OPCODE_VALIDATION_ERROR_CODE,
+                    format!(
+                        "{message}: {}",
+                        changes
+                            .iter()
+                            .map(|change| format!("{:?} {}", change.address, change.change))
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                    Some(json!({ "rule_id": "COD-010", "changes": changes })),
+                )
             }
-            SimulateValidationError::CodeHashesValidation { message } => {
-                SimulationError::owned(OPCODE_VALIDATION_ERROR_CODE, message, None::<bool>)
+            SimulateValidationError::OutOfGas { entity } => SimulationError::owned(
+                OPCODE_VALIDATION_ERROR_CODE,
+                format!("{entity} internally reverts on out of gas"),
+                Some(json!({ "entity": entity, "rule_id": "OP-020" })),
+            ),
+            SimulateValidationError::UndeployedContractAccess { entity, address } => {
+                SimulationError::owned(
+                    OPCODE_VALIDATION_ERROR_CODE,
+                    format!("{entity} accesses un-deployed contract address {address:?}"),
+                    Some(json!({ "entity": entity, "address": address, "rule_id": "OP-041" })),
+                )
             }
+            SimulateValidationError::StakeValidation {
+                entity,
+                address,
+                code,
+                message,
+            } => SimulationError::owned(
+                code,
+                message,
+                Some(json!({ "entity": entity, "address": address })),
+            ),
             SimulateValidationError::UnknownError { error } => {
                 SimulationError::owned(ErrorCode::InternalError.code(), error, None::<bool>)
             }
         }
-    }
-}
+    }
+}
+
+impl SimulateValidationError {
+    // violated ERC-7562 rule (none for the checks that aren't part of the validation rules)
+    pub fn rule_id(&self) -> Option<&str> {
+        match self {
+            SimulateValidationError::OpcodeValidation { rule_id, .. }
+            | SimulateValidationError::StorageAccessValidation { rule_id, .. }
+            | SimulateValidationError::CallStackValidation { rule_id, .. } => Some(rule_id),
+            SimulateValidationError::CodeHashesValidation { .. } => Some("COD-010"),
+            SimulateValidationError::OutOfGas { .. } => Some("OP-020"),
+            SimulateValidationError::UndeployedContractAccess { .. } => Some("OP-041"),
+            _ => None,
+        }
+    }
+
+    // entity that violated the rule (if known) and the details of the violation for the report
+    pub fn rule_violation(&self) -> (Option<String>, RuleViolation) {
+        let message = SimulationError::from(self.clone()).message().to_string();
+        let violation = |rule: &str| RuleViolation {
+            rule: rule.to_string(),
+            rule_id: self.rule_id().map(str::to_string),
+            message: message.clone(),
+            ..Default::default()
+        };
+
+        match self {
+            SimulateValidationError::SignatureValidation {} => (None, violation("signature")),
+            SimulateValidationError::ExpirationValidation { paymaster, .. } => (
+                paymaster.map(|_| "paymaster".to_string()),
+                RuleViolation {
+                    address: *paymaster,
+                    ..violation("timestamps")
+                },
+            ),
+            SimulateValidationError::UserOperationRejected { .. } => {
+                (None, violation("simulation"))
+            }
+            SimulateValidationError::OpcodeValidation { entity, opcode, .. } => (
+                Some(entity.clone()),
+                RuleViolation {
+                    opcode: Some(opcode.clone()),
+                    ..violation("opcodes")
+                },
+            ),
+            SimulateValidationError::UserOperationExecution { .. } => {
+                (None, violation("execution"))
+            }
+            SimulateValidationError::StorageAccessValidation {
+                entity,
+                address,
+                slot,
+                ..
+            } => (
+                Some(entity.clone()),
+                RuleViolation {
+                    address: Some(*address),
+                    slot: Some(slot.clone()),
+                    ..violation("storage_access")
+                },
+            ),
+            SimulateValidationError::CallStackValidation {
+                entity, call_path, ..
+            } => (
+                entity.clone(),
+                RuleViolation {
+                    address: call_path.last().copied(),
+                    call_path: call_path.clone(),
+                    ..violation("call_stack")
+                },
+            ),
+            SimulateValidationError::CodeHashesValidation { changes, .. } => (
+                None,
+                RuleViolation {
+                    address: changes.first().map(|change| change.address),
+                    ..violation("code_hashes")
+                },
+            ),
+            SimulateValidationError::OutOfGas { entity } => {
+                (Some(entity.clone()), violation("out_of_gas"))
+            }
+            SimulateValidationError::UndeployedContractAccess { entity, address } => (
+                Some(entity.clone()),
+                RuleViolation {
+                    address: Some(*address),
+                    ..violation("undeployed_contract")
+                },
+            ),
+            SimulateValidationError::StakeValidation {
+                entity, address, ..
+            } => (
+                Some(entity.clone()),
+                RuleViolation {
+                    address: Some(*address),
+                    ..violation("stake")
+                },
+            ),
+            SimulateValidationError::UnknownError { .. } => (None, violation("unknown")),
+        }
+    }
+
+    // violation of the entity that would be allowed if the entity was staked
+    fn allowed_if_staked(&self, entity: &str) -> bool {
+        match self {
+            SimulateValidationError::StorageAccessValidation {
+                entity: violating_entity,
+                allowed_if_staked,
+                ..
+            } => *allowed_if_staked && violating_entity == entity,
+            SimulateValidationError::OpcodeValidation {
+                entity: violating_entity,
+                rule_id,
+                ..
+            } => rule_id == "OP-080" && violating_entity == entity,
+            SimulateValidationError::CallStackValidation {
+                entity: Some(violating_entity),
+                rule_id,
+                ..
+            } => rule_id == "EREP-050" && violating_entity == entity,
+            _ => false,
+        }
+    }
+}
+
+// Report of the violations, grouped by the entity that caused them
+fn build_validation_report(
+    stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
+    staked: [bool; NUMBER_LEVELS],
+    errors: Vec<SimulateValidationError>,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    for (index, stake_info) in stake_info_by_entity.iter().enumerate() {
+        // the account is always reported, the factory and paymaster only if used
+        if stake_info.address.is_zero() && LEVEL_TO_ENTITY[index] != "account" {
+            continue;
+        }
+        report.entities.push(EntityValidationReport {
+            entity: LEVEL_TO_ENTITY[index].to_string(),
+            stake_info: *stake_info,
+            staked: staked[index],
+            violations: vec![],
+        });
+    }
+
+    for error in errors {
+        let (entity, violation) = error.rule_violation();
+        match report
+            .entities
+            .iter_mut()
+            .find(|report| Some(&report.entity) == entity.as_ref())
+        {
+            Some(entity_report) => entity_report.violations.push(violation),
+            None => report.violations.push(violation),
+        }
+    }
+    report.valid = report.violations.is_empty()
+        && report
+            .entities
+            .iter()
+            .all(|entity_report| entity_report.violations.is_empty());
+
+    report
+}
+
+// Violated rules of the validation. The checks stop at the first violation, except when all
+// violations are collected for the validation report.
+#[derive(Debug, Default)]
+struct Violations {
+    collect_all: bool,
+    errors: Vec<SimulateValidationError>,
+}
+
+impl Violations {
+    fn all() -> Self {
+        Self {
+            collect_all: true,
+            errors: vec![],
+        }
+    }
+
+    fn push(&mut self, error: SimulateValidationError) -> Result<(), SimulateValidationError> {
+        if self.collect_all {
+            self.errors.push(error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    // errors of checks that couldn't run to the end are recorded as well
+    fn record<T>(&mut self, result: Result<T, SimulateValidationError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SimulationResult {
+    pub simulate_validation_result: SimulateValidationResult,
+    pub code_hashes: Vec<CodeHash>,
+    pub valid_after: Option<u64>,
+    pub storage_map: StorageMap,
+}
+
+// OP-011, OP-012, OP-031 and OP-080 (except the opcodes allowed by an alternative mempool)
+#[derive(Debug, Default)]
+pub struct ForbiddenOpcodes {
+    pub allowed: HashSet<String>,
+}
+
+impl ValidationRule for ForbiddenOpcodes {
+    fn name(&self) -> &str {
+        "opcodes"
+    }
+
+    fn relaxes(&self, error: &SimulateValidationError) -> bool {
+        match error {
+            SimulateValidationError::OpcodeValidation { opcode, .. } => {
+                self.allowed.contains(opcode)
+            }
+            _ => false,
+        }
+    }
+
+    fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError> {
+        let mut errors = vec![];
+
+        for (index, _) in LEVEL_TO_ENTITY.iter().enumerate() {
+            if let Some(level) = context.trace.number_levels.get(index) {
+                for opcode in level.opcodes.keys() {
+                    if self.allowed.contains(opcode) {
+                        continue;
+                    }
+                    if let Some(rule_id) = opcode_rule_violation(opcode, context.staked[index]) {
+                        errors.push(SimulateValidationError::OpcodeValidation {
+                            entity: LEVEL_TO_ENTITY[index].to_string(),
+                            opcode: opcode.clone(),
+                            rule_id: rule_id.to_string(),
+                        });
+                    }
+                }
+            }
+
+            if let Some(level) = context.trace.number_levels.get(index) {
+                if let Some(count) = level.opcodes.get(&*CREATE2_OPCODE) {
+                    if (LEVEL_TO_ENTITY[index] == "factory" && *count == 1)
+                        || self.allowed.contains(&*CREATE2_OPCODE)
+                    {
+                        continue;
+                    }
+                    errors.push(SimulateValidationError::OpcodeValidation {
+                        entity: LEVEL_TO_ENTITY[index].to_string(),
+                        opcode: CREATE2_OPCODE.to_string(),
+                        rule_id: "OP-031".to_string(),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+// OP-020
+#[derive(Debug, Default)]
+pub struct OutOfGas;
+
+impl ValidationRule for OutOfGas {
+    fn name(&self) -> &str {
+        "out_of_gas"
+    }
+
+    fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError> {
+        let mut errors = vec![];
+
+        for (index, entity) in LEVEL_TO_ENTITY.iter().enumerate() {
+            if let Some(level) = context.trace.number_levels.get(index) {
+                if level.oog {
+                    errors.push(SimulateValidationError::OutOfGas {
+                        entity: entity.to_string(),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+// OP-041, only the sender may be accessed before it is deployed (by the factory)
+#[derive(Debug, Default)]
+pub struct UndeployedContracts;
+
+impl ValidationRule for UndeployedContracts {
+    fn name(&self) -> &str {
+        "undeployed_contract"
+    }
+
+    fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError> {
+        let mut errors = vec![];
+
+        for (index, entity) in LEVEL_TO_ENTITY.iter().enumerate() {
+            if let Some(level) = context.trace.number_levels.get(index) {
+                for (address, size) in &level.contract_size {
+                    if *size == 0
+                        && *address != context.user_operation.sender
+                        && !is_precompile(address)
+                    {
+                        errors.push(SimulateValidationError::UndeployedContractAccess {
+                            entity: entity.to_string(),
+                            address: *address,
+                        });
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+// Stake info of the factory, account and paymaster of the user operation, addresses of entities
+// that aren't used are zero
+fn entities_stake_info(
+    user_operation: &UserOperation,
+    stakes: [(U256, U256); NUMBER_LEVELS],
+) -> [StakeInfo; NUMBER_LEVELS] {
+    let [factory_info, sender_info, paymaster_info] = stakes;
+    [
+        StakeInfo {
+            address: if user_operation.init_code.len() >= 20 {
+                Address::from_slice(&user_operation.init_code[0..20])
+            } else {
+                Address::zero()
+            },
+            stake: factory_info.0,
+            unstake_delay: factory_info.1,
+        },
+        StakeInfo {
+            address: user_operation.sender,
+            stake: sender_info.0,
+            unstake_delay: sender_info.1,
+        },
+        StakeInfo {
+            address: if user_operation.paymaster_and_data.len() >= 20 {
+                Address::from_slice(&user_operation.paymaster_and_data[0..20])
+            } else {
+                Address::zero()
+            },
+            stake: paymaster_info.0,
+            unstake_delay: paymaster_info.1,
+        },
+    ]
+}
+
+fn parse_slots(
+    keccak: &[Bytes],
+    stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
+) -> HashMap<Address, HashSet<Bytes>> {
+    let mut slots_by_entity = HashMap::new();
+
+    for kecc in keccak {
+        for entity in stake_info_by_entity {
+            if entity.address.is_zero() {
+                continue;
+            }
+
+            let entity_address_bytes =
+                Bytes::from([vec![0; 12], entity.address.to_fixed_bytes().to_vec()].concat());
+
+            if kecc.starts_with(&entity_address_bytes) {
+                let k = keccak256(kecc.clone());
+                slots_by_entity
+                    .entry(entity.address)
+                    .or_insert(HashSet::new())
+                    .insert(k.into());
+            }
+        }
+    }
+
+    slots_by_entity
+}
+
+fn associated_with_slot(
+    address: &Address,
+    slot: &String,
+    slots_by_entity: &HashMap<Address, HashSet<Bytes>>,
+) -> bool {
+    if *slot == address.to_string() {
+        return true;
+    }
+
+    if !slots_by_entity.contains_key(address) {
+        return false;
+    }
+
+    let slot_as_number = match U256::from_str_radix(slot, 16) {
+        Ok(slot) => slot,
+        Err(_) => return false,
+    };
+
+    if let Some(slots) = slots_by_entity.get(address) {
+        for slot_entity in slots {
+            let slot_entity_as_number = U256::from(slot_entity.as_ref());
+
+            if slot_as_number >= slot_entity_as_number
+                && slot_as_number < (slot_entity_as_number + 128)
+            {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
+fn parse_call_stack(trace: &JsTracerFrame) -> Vec<CallEntry> {
+    let mut calls: Vec<CallEntry> = vec![];
+    let mut stack: Vec<Call> = vec![];
+
+    for call in trace.calls.iter() {
+        if call.typ == *REVERT_OPCODE || call.typ == *RETURN_OPCODE {
+            let top = stack.pop();
+
+            if let Some(top) = top {
+                let path: Vec<Address> = stack.iter().filter_map(|call| call.to).collect();
+                if top.typ.contains(CREATE_OPCODE.as_str()) {
+                    calls.push(CallEntry {
+                        typ: top.typ,
+                        from: top.from,
+                        to: top.to,
+                        method: None,
+                        ret: None,
+                        rev: None,
+                        value: None,
+                        path,
+                    });
+                } else {
+                    // name of the known functions, otherwise the selector (none for the fallback)
+                    let method: Option<String> = match top.method {
+                        Some(method) if !method.is_empty() => Some(
+                            CONTRACTS_FUNCTIONS
+                                .get(method.as_ref())
+                                .cloned()
+                                .unwrap_or_else(|| method.to_string()),
+                        ),
+                        _ => None,
+                    };
+
+                    if call.typ == *REVERT_OPCODE {
+                        calls.push(CallEntry {
+                            typ: top.typ,
+                            from: top.from,
+                            to: top.to,
+                            method,
+                            ret: None,
+                            rev: call.data.clone(),
+                            value: top.value,
+                            path,
+                        });
+                    } else {
+                        calls.push(CallEntry {
+                            typ: top.typ,
+                            from: top.from,
+                            to: top.to,
+                            method,
+                            ret: call.data.clone(),
+                            rev: None,
+                            value: top.value,
+                            path,
+                        });
+                    }
+                }
+            }
+        } else {
+            stack.push(call.clone());
+        }
+    }
+
+    calls
+}
+
+// STO-010 to STO-033 (except the contracts which storage is allowed by an alternative mempool)
+#[derive(Debug, Default)]
+pub struct StorageAccess {
+    pub allowed: HashSet<Address>,
+}
+
+impl ValidationRule for StorageAccess {
+    fn name(&self) -> &str {
+        "storage_access"
+    }
+
+    fn relaxes(&self, error: &SimulateValidationError) -> bool {
+        match error {
+            SimulateValidationError::StorageAccessValidation { address, .. } => {
+                self.allowed.contains(address)
+            }
+            _ => false,
+        }
+    }
+
+    fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError> {
+        let mut errors = vec![];
+        let user_operation = context.user_operation;
+        let slots_by_entity = parse_slots(&context.trace.keccak, context.stake_info_by_entity);
+
+        for (index, stake_info) in context.stake_info_by_entity.iter().enumerate() {
+            if let Some(level) = context.trace.number_levels.get(index) {
+                for (address, access) in &level.access {
+                    if *address == user_operation.sender
+                        || *address == context.entry_point
+                        || self.allowed.contains(address)
+                    {
+                        continue;
+                    }
+
+                    let slots = access
+                        .reads
+                        .keys()
+                        .map(|slot| (slot, false))
+                        .chain(access.writes.keys().map(|slot| (slot, true)));
+                    for (slot, write) in slots {
+                        let slot_access = SlotAccess {
+                            index,
+                            address,
+                            write,
+                            associated_with_sender: associated_with_slot(
+                                &user_operation.sender,
+                                slot,
+                                &slots_by_entity,
+                            ),
+                            associated_with_entity: associated_with_slot(
+                                &stake_info.address,
+                                slot,
+                                &slots_by_entity,
+                            ),
+                        };
+
+                        if let Some((rule_id, allowed_if_staked, reason)) = storage_rule_violation(
+                            &slot_access,
+                            context.stake_info_by_entity,
+                            &context.staked,
+                        ) {
+                            errors.push(SimulateValidationError::StorageAccessValidation {
+                                entity: LEVEL_TO_ENTITY[index].to_string(),
+                                address: *address,
+                                slot: slot.clone(),
+                                access: if write { "write" } else { "read" }.to_string(),
+                                allowed_if_staked,
+                                reason,
+                                rule_id: rule_id.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+// OP-052 to OP-054, OP-061 and EREP-050
+#[derive(Debug, Default)]
+pub struct CallStack;
+
+impl ValidationRule for CallStack {
+    fn name(&self) -> &str {
+        "call_stack"
+    }
+
+    fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError> {
+        let mut errors = vec![];
+        let calls = parse_call_stack(context.trace);
+        let stake_info_by_entity = context.stake_info_by_entity;
+        let staked = context.staked;
+
+        let entry_point = context.entry_point;
+        for call in calls.iter() {
+            // entities may only deposit to the entry point (depositTo or the fallback)
+            if call.to == Some(entry_point)
+                && call.from != Some(entry_point)
+                && call.method.is_some()
+                && call.method != Some(DEPOSIT_TO_FUNCTION.clone())
+            {
+                errors.push(SimulateValidationError::CallStackValidation {
+                    entity: call_entity(call, stake_info_by_entity),
+                    call_path: call_path(call),
+                    message: format!(
+                        "Illegal call into entry point during validation: {}",
+                        call.method.clone().unwrap_or_default()
+                    ),
+                    rule_id: "OP-054".to_string(),
+                });
+            }
+
+            if call.to != Some(entry_point) && !call.value.unwrap_or_default().is_zero() {
+                errors.push(SimulateValidationError::CallStackValidation {
+                    entity: call_entity(call, stake_info_by_entity),
+                    call_path: call_path(call),
+                    message: format!(
+                        "Call with value to {:?} during validation (only allowed to the entry point)",
+                        call.to.unwrap_or_default()
+                    ),
+                    rule_id: "OP-061".to_string(),
+                });
+            }
+        }
+
+        for (index, stake_info) in stake_info_by_entity.iter().enumerate() {
+            if LEVEL_TO_ENTITY[index] == "paymaster" {
+                let call = calls.iter().find(|call| {
+                    call.method == Some(PAYMASTER_VALIDATION_FUNCTION.clone())
+                        && call.to == Some(stake_info.address)
+                });
+
+                if let Some(call) = call {
+                    if let Some(ret) = call.ret.as_ref() {
+                        let validate_paymaster_return: ValidatePaymasterUserOpReturn =
+                            match AbiDecode::decode(ret) {
+                                Ok(validate_paymaster_return) => validate_paymaster_return,
+                                Err(_) => {
+                                    errors.push(SimulateValidationError::UserOperationRejected {
+                                        message: "unknown error".to_string(),
+                                    });
+                                    continue;
+                                }
+                            };
+                        let paymaster_context = validate_paymaster_return.context;
+
+                        if !paymaster_context.is_empty() && !staked[index] {
+                            errors.push(SimulateValidationError::CallStackValidation {
+                                entity: Some(LEVEL_TO_ENTITY[index].to_string()),
+                                call_path: call_path(call),
+                                message: "Paymaster that is not staked should not return context"
+                                    .to_string(),
+                                rule_id: "EREP-050".to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
 
-#[derive(Debug)]
-pub struct SimulationResult {
-    pub simulate_validation_result: SimulateValidationResult,
-    pub code_hashes: Vec<CodeHash>,
-    pub valid_after: Option<u64>,
+        errors
+    }
 }
 
 impl<M: Middleware + 'static> UoPool<M> {
     async fn simulate_validation(
         &self,
         user_operation: &UserOperation,
+        state_override: &spoof::State,
     ) -> Result<SimulateValidationResult, SimulateValidationError> {
         match self
             .entry_point
-            .simulate_validation(user_operation.clone())
+            .simulate_validation(user_operation.clone(), state_override)
             .await
         {
             Ok(simulate_validation_result) => Ok(simulate_validation_result),
@@ -181,10 +1033,12 @@ impl<M: Middleware + 'static> UoPool<M> {
     async fn simulate_validation_trace(
         &self,
         user_operation: &UserOperation,
+        state_override: &spoof::State,
+        tracer: ValidationTracer,
     ) -> Result<GethTrace, SimulateValidationError> {
         match self
             .entry_point
-            .simulate_validation_trace(user_operation.clone())
+            .simulate_validation_trace(user_operation.clone(), state_override, tracer)
             .await
         {
             Ok(geth_trace) => Ok(geth_trace),
@@ -201,9 +1055,34 @@ impl<M: Middleware + 'static> UoPool<M> {
         }
     }
 
+    async fn simulate_validation_revm(
+        &self,
+        user_operation: &UserOperation,
+        state_override: &spoof::State,
+    ) -> Result<JsTracerFrame, SimulateValidationError> {
+        let data = self
+            .entry_point
+            .entry_point_api()
+            .simulate_validation(user_operation.clone().into())
+            .calldata()
+            .unwrap_or_default();
+
+        trace_call(
+            self.eth_provider.clone(),
+            self.entry_point.address(),
+            data,
+            state_override,
+        )
+        .await
+        .map_err(|error| SimulateValidationError::UserOperationRejected {
+            message: error.to_string(),
+        })
+    }
+
     fn signature(
         &self,
         simulate_validation_result: &SimulateValidationResult,
+        violations: &mut Violations,
     ) -> Result<(), SimulateValidationError> {
         let signature_check = match simulate_validation_result {
             SimulateValidationResult::ValidationResult(validation_result) => {
@@ -215,7 +1094,7 @@ impl<M: Middleware + 'static> UoPool<M> {
         };
 
         if signature_check {
-            return Err(SimulateValidationError::SignatureValidation {});
+            violations.push(SimulateValidationError::SignatureValidation {})?;
         }
 
         Ok(())
@@ -224,6 +1103,7 @@ impl<M: Middleware + 'static> UoPool<M> {
     fn timestamps(
         &self,
         simulate_validation_result: &SimulateValidationResult,
+        violations: &mut Violations,
     ) -> Result<Option<u64>, SimulateValidationError> {
         let (valid_after, valid_until) = match simulate_validation_result {
             SimulateValidationResult::ValidationResult(validation_result) => (
@@ -246,11 +1126,11 @@ impl<M: Middleware + 'static> UoPool<M> {
             .as_secs();
 
         if valid_until <= current_timestamp + EXPIRATION_TIMESTAMP_DIFF {
-            return Err(SimulateValidationError::ExpirationValidation {
+            violations.push(SimulateValidationError::ExpirationValidation {
                 valid_after,
                 valid_until,
                 paymaster: None, // TODO: fill with paymaster address this error was triggered by the paymaster
-            });
+            })?;
         }
 
         if valid_after > current_timestamp {
@@ -281,292 +1161,60 @@ impl<M: Middleware + 'static> UoPool<M> {
             ),
         };
 
-        // factory
-        stake_info_by_entity[0] = StakeInfo {
-            address: if user_operation.init_code.len() >= 20 {
-                Address::from_slice(&user_operation.init_code[0..20])
-            } else {
-                Address::zero()
-            },
-            stake: factory_info.0,
-            unstake_delay: factory_info.1,
-        };
-
-        // account
-        stake_info_by_entity[1] = StakeInfo {
-            address: user_operation.sender,
-            stake: sender_info.0,
-            unstake_delay: sender_info.1,
-        };
-
-        // paymaster
-        stake_info_by_entity[2] = StakeInfo {
-            address: if user_operation.paymaster_and_data.len() >= 20 {
-                Address::from_slice(&user_operation.paymaster_and_data[0..20])
-            } else {
-                Address::zero()
-            },
-            stake: paymaster_info.0,
-            unstake_delay: paymaster_info.1,
-        };
-    }
-
-    fn forbidden_opcodes(&self, trace: &JsTracerFrame) -> Result<(), SimulateValidationError> {
-        for (index, _) in LEVEL_TO_ENTITY.iter().enumerate() {
-            if let Some(level) = trace.number_levels.get(index) {
-                for opcode in level.opcodes.keys() {
-                    if FORBIDDEN_OPCODES.contains(opcode) {
-                        return Err(SimulateValidationError::OpcodeValidation {
-                            entity: LEVEL_TO_ENTITY[index].to_string(),
-                            opcode: opcode.clone(),
-                        });
-                    }
-                }
-            }
-
-            if let Some(level) = trace.number_levels.get(index) {
-                if let Some(count) = level.opcodes.get(&*CREATE2_OPCODE) {
-                    if LEVEL_TO_ENTITY[index] == "factory" && *count == 1 {
-                        continue;
-                    }
-                    return Err(SimulateValidationError::OpcodeValidation {
-                        entity: LEVEL_TO_ENTITY[index].to_string(),
-                        opcode: CREATE2_OPCODE.to_string(),
-                    });
-                }
-            }
-        }
-
-        Ok(())
-    }
-
-    fn parse_slots(
-        &self,
-        keccak: Vec<Bytes>,
-        stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
-        slots_by_entity: &mut HashMap<Address, HashSet<Bytes>>,
-    ) {
-        for kecc in keccak {
-            for entity in stake_info_by_entity {
-                if entity.address.is_zero() {
-                    continue;
-                }
-
-                let entity_address_bytes =
-                    Bytes::from([vec![0; 12], entity.address.to_fixed_bytes().to_vec()].concat());
-
-                if kecc.starts_with(&entity_address_bytes) {
-                    let k = keccak256(kecc.clone());
-                    slots_by_entity
-                        .entry(entity.address)
-                        .or_insert(HashSet::new())
-                        .insert(k.into());
-                }
-            }
-        }
+        *stake_info_by_entity =
+            entities_stake_info(user_operation, [factory_info, sender_info, paymaster_info]);
     }
 
-    fn associated_with_slot(
+    fn validation_context<'a>(
         &self,
-        address: &Address,
-        slot: &String,
-        slots_by_entity: &HashMap<Address, HashSet<Bytes>>,
-    ) -> Result<bool, SimulateValidationError> {
-        if *slot == address.to_string() {
-            return Ok(true);
-        }
-
-        if !slots_by_entity.contains_key(address) {
-            return Ok(false);
-        }
-
-        let slot_as_number = U256::from_str_radix(slot, 16)
-            .map_err(|_| SimulateValidationError::StorageAccessValidation { slot: slot.clone() })?;
-
-        if let Some(slots) = slots_by_entity.get(address) {
-            for slot_entity in slots {
-                let slot_entity_as_number = U256::from(slot_entity.as_ref());
-
-                if slot_as_number >= slot_entity_as_number
-                    && slot_as_number < (slot_entity_as_number + 128)
-                {
-                    return Ok(true);
-                }
-            }
+        user_operation: &'a UserOperation,
+        trace: &'a JsTracerFrame,
+        stake_info_by_entity: &'a [StakeInfo; NUMBER_LEVELS],
+    ) -> ValidationContext<'a> {
+        ValidationContext {
+            user_operation,
+            entry_point: self.entry_point.address(),
+            trace,
+            stake_info_by_entity,
+            staked: self.staked_entities(stake_info_by_entity),
         }
-
-        Ok(false)
     }
 
-    fn storage_access(
+    // entities with the minimum stake and unstake delay, which have relaxed validation rules
+    fn staked_entities(
         &self,
-        user_operation: &UserOperation,
         stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
-        trace: &JsTracerFrame,
-    ) -> Result<(), SimulateValidationError> {
-        let mut slots_by_entity = HashMap::new();
-        self.parse_slots(
-            trace.keccak.clone(),
-            stake_info_by_entity,
-            &mut slots_by_entity,
-        );
-
-        let mut slot_staked = String::new();
-
+    ) -> [bool; NUMBER_LEVELS] {
+        let mut staked = [false; NUMBER_LEVELS];
         for (index, stake_info) in stake_info_by_entity.iter().enumerate() {
-            if let Some(level) = trace.number_levels.get(index) {
-                for (address, access) in &level.access {
-                    if *address == user_operation.sender || *address == self.entry_point.address() {
-                        continue;
-                    }
-
-                    for slot in [
-                        access.reads.keys().cloned().collect::<Vec<String>>(),
-                        access.writes.keys().cloned().collect(),
-                    ]
-                    .concat()
-                    {
-                        slot_staked.clear();
-
-                        if self.associated_with_slot(
-                            &user_operation.sender,
-                            &slot,
-                            &slots_by_entity,
-                        )? {
-                            if user_operation.init_code.len() > 0 {
-                                slot_staked = slot.clone();
-                            } else {
-                                continue;
-                            }
-                        } else if *address == stake_info.address
-                            || self.associated_with_slot(
-                                &stake_info.address,
-                                &slot,
-                                &slots_by_entity,
-                            )?
-                        {
-                            slot_staked = slot.clone();
-                        } else {
-                            return Err(SimulateValidationError::StorageAccessValidation { slot });
-                        }
-
-                        if !slot_staked.is_empty() && stake_info.stake.is_zero() {
-                            return Err(SimulateValidationError::StorageAccessValidation {
-                                slot: slot_staked.clone(),
-                            });
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(())
-    }
-
-    fn parse_call_stack(
-        &self,
-        trace: &JsTracerFrame,
-        calls: &mut Vec<CallEntry>,
-    ) -> Result<(), SimulateValidationError> {
-        let mut stack: Vec<Call> = vec![];
-
-        for call in trace.calls.iter() {
-            if call.typ == *REVERT_OPCODE || call.typ == *RETURN_OPCODE {
-                let top = stack.pop();
-
-                if let Some(top) = top {
-                    if top.typ.contains(CREATE_OPCODE.as_str()) {
-                        calls.push(CallEntry {
-                            typ: top.typ,
-                            from: top.from,
-                            to: top.to,
-                            method: None,
-                            ret: None,
-                            rev: None,
-                            value: None,
-                        });
-                    } else {
-                        let method: Option<String> = {
-                            if let Some(method) = top.method {
-                                CONTRACTS_FUNCTIONS.get(method.as_ref()).cloned()
-                            } else {
-                                None
-                            }
-                        };
-
-                        if call.typ == *REVERT_OPCODE {
-                            calls.push(CallEntry {
-                                typ: top.typ,
-                                from: top.from,
-                                to: top.to,
-                                method,
-                                ret: None,
-                                rev: call.data.clone(),
-                                value: top.value,
-                            });
-                        } else {
-                            calls.push(CallEntry {
-                                typ: top.typ,
-                                from: top.from,
-                                to: top.to,
-                                method,
-                                ret: call.data.clone(),
-                                rev: None,
-                                value: None,
-                            });
-                        }
-                    }
-                }
-            } else {
-                stack.push(call.clone());
-            }
+            staked[index] = !stake_info.address.is_zero()
+                && self
+                    .reputation
+                    .verify_stake(LEVEL_TO_ENTITY[index], Some(*stake_info))
+                    .is_ok();
         }
-
-        Ok(())
+        staked
     }
 
-    fn call_stack(
-        &self,
-        stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
-        trace: &JsTracerFrame,
-    ) -> Result<(), SimulateValidationError> {
-        let mut calls: Vec<CallEntry> = vec![];
-        self.parse_call_stack(trace, &mut calls)?;
-
-        for (index, stake_info) in stake_info_by_entity.iter().enumerate() {
-            if LEVEL_TO_ENTITY[index] == "paymaster" {
-                let call = calls.iter().find(|call| {
-                    call.method == Some(PAYMASTER_VALIDATION_FUNCTION.clone())
-                        && call.to == Some(stake_info.address)
-                });
-
-                if let Some(call) = call {
-                    if let Some(ret) = call.ret.as_ref() {
-                        let validate_paymaster_return: ValidatePaymasterUserOpReturn =
-                            AbiDecode::decode(ret).map_err(|_| {
-                                SimulateValidationError::UserOperationRejected {
-                                    message: "unknown error".to_string(),
-                                }
-                            })?;
-                        let context = validate_paymaster_return.context;
-
-                        if !context.is_empty()
-                            && self
-                                .reputation
-                                .verify_stake("paymaster", Some(*stake_info))
-                                .is_err()
-                        {
-                            return Err(SimulateValidationError::CallStackValidation {
-                                message: "Paymaster that is not staked should not return context"
-                                    .to_string(),
-                            });
-                        }
+    // storage slots read during validation, used for conditional bundle submission (knownAccounts)
+    fn storage_map(&self, trace: &JsTracerFrame) -> StorageMap {
+        let mut storage_map = StorageMap::new();
+
+        for level in trace.number_levels.iter() {
+            for (address, access) in &level.access {
+                let slots = storage_map.entry(*address).or_default();
+                for slot in access.reads.keys() {
+                    if let Ok(slot) = U256::from_str_radix(slot, 16) {
+                        let mut bytes = [0u8; 32];
+                        slot.to_big_endian(&mut bytes);
+                        slots.insert(H256::from(bytes));
                     }
                 }
             }
         }
 
-        Ok(())
+        storage_map.retain(|_, slots| !slots.is_empty());
+        storage_map
     }
 
     async fn get_code_hashes(
@@ -608,6 +1256,7 @@ impl<M: Middleware + 'static> UoPool<M> {
         &self,
         user_operation: &UserOperation,
         trace: &JsTracerFrame,
+        violations: &mut Violations,
     ) -> Result<Vec<CodeHash>, SimulateValidationError> {
         let contract_addresses = trace
             .number_levels
@@ -631,13 +1280,14 @@ impl<M: Middleware + 'static> UoPool<M> {
             Ok(true) => {
                 // 2nd simulation
                 let prev_code_hashes = self.mempool.get_code_hashes(&user_operation_hash);
-                if !equal_code_hashes(code_hashes, &prev_code_hashes) {
-                    Err(SimulateValidationError::CodeHashesValidation {
+                let changes = code_hashes_diff(code_hashes, &prev_code_hashes);
+                if !changes.is_empty() {
+                    violations.push(SimulateValidationError::CodeHashesValidation {
                         message: "modified code after 1st simulation".to_string(),
-                    })
-                } else {
-                    Ok(code_hashes.to_vec())
+                        changes,
+                    })?;
                 }
+                Ok(code_hashes.to_vec())
             }
             Ok(false) => {
                 // 1st simulation
@@ -649,27 +1299,28 @@ impl<M: Middleware + 'static> UoPool<M> {
         }
     }
 
+    // The state override set is only used for estimations, user operations added to the mempool
+    // are simulated without it.
     pub async fn simulate_user_operation(
         &self,
         user_operation: &UserOperation,
+        state_override: &spoof::State,
     ) -> Result<SimulationResult, SimulateValidationError> {
-        let simulate_validation_result = self.simulate_validation(user_operation).await?;
+        let violations = &mut Violations::default();
+
+        let simulate_validation_result = self
+            .simulate_validation(user_operation, state_override)
+            .await?;
 
         // check signature
-        self.signature(&simulate_validation_result)?;
+        self.signature(&simulate_validation_result, violations)?;
 
         // check timestamps
-        let valid_after = self.timestamps(&simulate_validation_result)?;
+        let valid_after = self.timestamps(&simulate_validation_result, violations)?;
 
-        let geth_trace = self.simulate_validation_trace(user_operation).await?;
-
-        trace!("Simulate user operation {user_operation:?} with trace {geth_trace:?}");
-
-        let js_trace: JsTracerFrame = JsTracerFrame::try_from(geth_trace).map_err(|error| {
-            SimulateValidationError::UserOperationRejected {
-                message: error.to_string(),
-            }
-        })?;
+        let js_trace = self
+            .validation_trace(user_operation, state_override)
+            .await?;
 
         let mut stake_info_by_entity: [StakeInfo; NUMBER_LEVELS] = Default::default();
         self.extract_stake_info(
@@ -678,22 +1329,410 @@ impl<M: Middleware + 'static> UoPool<M> {
             &mut stake_info_by_entity,
         );
 
-        // may not invokes any forbidden opcodes
-        self.forbidden_opcodes(&js_trace)?;
-
-        // verify storage access
-        self.storage_access(user_operation, &stake_info_by_entity, &js_trace)?;
-
-        // verify call stack
-        self.call_stack(&stake_info_by_entity, &js_trace)?;
+        // verify opcodes, out of gas, undeployed contracts, storage access and call stack with the
+        // rules of the mempool
+        let context = self.validation_context(user_operation, &js_trace, &stake_info_by_entity);
+        for error in self.rules.check(&context) {
+            violations.push(error)?;
+        }
 
         // verify code hashes
-        let code_hashes = self.code_hashes(user_operation, &js_trace).await?;
+        let code_hashes = self
+            .code_hashes(user_operation, &js_trace, violations)
+            .await?;
 
         Ok(SimulationResult {
             simulate_validation_result,
             code_hashes,
             valid_after,
+            storage_map: self.storage_map(&js_trace),
         })
     }
+
+    // Runs all validation rules without stopping at the first violation (debug diagnostics)
+    pub async fn validation_report(&self, user_operation: &UserOperation) -> ValidationReport {
+        let violations = &mut Violations::all();
+        let state_override = &spoof::State::default();
+
+        let simulate_validation_result = violations.record(
+            self.simulate_validation(user_operation, state_override)
+                .await,
+        );
+        let stake_info = match simulate_validation_result {
+            Some(simulate_validation_result) => {
+                let result = self.signature(&simulate_validation_result, violations);
+                violations.record(result);
+                let result = self.timestamps(&simulate_validation_result, violations);
+                violations.record(result);
+
+                let mut stake_info: [StakeInfo; NUMBER_LEVELS] = Default::default();
+                self.extract_stake_info(
+                    user_operation,
+                    &simulate_validation_result,
+                    &mut stake_info,
+                );
+                stake_info
+            }
+            // the entities are taken from the user operation, without stake, so the rules are
+            // still checked when simulateValidation reverts
+            None => entities_stake_info(user_operation, Default::default()),
+        };
+
+        let trace = self.validation_trace(user_operation, state_override).await;
+        if let Some(js_trace) = violations.record(trace) {
+            let context = self.validation_context(user_operation, &js_trace, &stake_info);
+            violations.errors.extend(self.rules.check(&context));
+            let result = self
+                .code_hashes(user_operation, &js_trace, violations)
+                .await;
+            violations.record(result);
+        }
+
+        let stake_violations =
+            self.stake_violations(user_operation, &stake_info, &violations.errors);
+        violations.errors.extend(stake_violations);
+
+        let staked = self.staked_entities(&stake_info);
+        build_validation_report(&stake_info, staked, std::mem::take(&mut violations.errors))
+    }
+
+    // Stake and reputation checks of the sanity check: banned entities, and unstaked entities
+    // which need a stake (for their violations or more user operations of the sender)
+    fn stake_violations(
+        &self,
+        user_operation: &UserOperation,
+        stake_info_by_entity: &[StakeInfo; NUMBER_LEVELS],
+        errors: &[SimulateValidationError],
+    ) -> Vec<SimulateValidationError> {
+        let mut violations = vec![];
+        for (index, stake_info) in stake_info_by_entity.iter().enumerate() {
+            let entity = LEVEL_TO_ENTITY[index];
+            if stake_info.address.is_zero() {
+                continue;
+            }
+
+            if self.reputation.get_status(&stake_info.address) == ReputationStatus::BANNED {
+                violations.push(SimulateValidationError::StakeValidation {
+                    entity: entity.to_string(),
+                    address: stake_info.address,
+                    code: ENTITY_BANNED_ERROR_CODE,
+                    message: format!("{entity} with address {:?} is banned", stake_info.address),
+                });
+                continue;
+            }
+
+            let needs_stake = errors.iter().any(|error| error.allowed_if_staked(entity))
+                || (index == 1 && self.sender_needs_stake(user_operation));
+            if !needs_stake {
+                continue;
+            }
+            if let Err(error) = self.reputation.verify_stake(entity, Some(*stake_info)) {
+                let error = ReputationError::from(error);
+                violations.push(SimulateValidationError::StakeValidation {
+                    entity: entity.to_string(),
+                    address: stake_info.address,
+                    code: error.code(),
+                    message: error.message().to_string(),
+                });
+            }
+        }
+        violations
+    }
+
+    // the sender has the maximum number of user operations of an unstaked sender in the mempool,
+    // and the user operation doesn't replace one of them
+    fn sender_needs_stake(&self, user_operation: &UserOperation) -> bool {
+        self.mempool.get_number_by_sender(&user_operation.sender) >= MAX_UOS_PER_UNSTAKED_SENDER
+            && !self
+                .mempool
+                .get_all_by_sender(&user_operation.sender)
+                .iter()
+                .any(|user_operation_prev| user_operation_prev.nonce == user_operation.nonce)
+    }
+
+    async fn validation_trace(
+        &self,
+        user_operation: &UserOperation,
+        state_override: &spoof::State,
+    ) -> Result<JsTracerFrame, SimulateValidationError> {
+        let js_trace = match self.simulator.validation_tracer() {
+            Some(tracer) => {
+                let geth_trace = self
+                    .simulate_validation_trace(user_operation, state_override, tracer)
+                    .await?;
+
+                trace!("Simulate user operation {user_operation:?} with trace {geth_trace:?}");
+
+                tracer.frame(geth_trace).map_err(|error| {
+                    SimulateValidationError::UserOperationRejected {
+                        message: error.to_string(),
+                    }
+                })?
+            }
+            None => {
+                let js_trace = self
+                    .simulate_validation_revm(user_operation, state_override)
+                    .await?;
+
+                trace!("Simulate user operation {user_operation:?} with revm trace {js_trace:?}");
+
+                js_trace
+            }
+        };
+
+        Ok(js_trace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{reputation::Reputation, MemoryMempool, MemoryReputation};
+    use aa_bundler_contracts::EntryPoint;
+    use aa_bundler_primitives::{BAN_SLACK, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK};
+    use ethers::providers::Provider;
+    use std::sync::Arc;
+
+    #[test]
+    fn precompiles() {
+        assert!(is_precompile(&Address::from_low_u64_be(1)));
+        assert!(is_precompile(&Address::from_low_u64_be(9)));
+        assert!(is_precompile(&Address::from_low_u64_be(0x0a)));
+        assert!(!is_precompile(&Address::zero()));
+        assert!(!is_precompile(&Address::from_low_u64_be(0x0b)));
+        assert!(!is_precompile(&Address::random()));
+    }
+
+    #[test]
+    fn violations() {
+        let error = SimulateValidationError::OpcodeValidation {
+            entity: "paymaster".to_string(),
+            opcode: "TIMESTAMP".to_string(),
+            rule_id: "OP-011".to_string(),
+        };
+
+        // stops at the first violation
+        let mut violations = Violations::default();
+        assert!(violations.push(error.clone()).is_err());
+        assert!(violations.errors.is_empty());
+
+        let mut violations = Violations::all();
+        assert!(violations.push(error.clone()).is_ok());
+        assert_eq!(violations.record::<()>(Err(error)), None);
+        assert_eq!(violations.errors.len(), 2);
+
+        let address = Address::random();
+        let error = SimulateValidationError::StorageAccessValidation {
+            entity: "account".to_string(),
+            address,
+            slot: "1".to_string(),
+            access: "write".to_string(),
+            allowed_if_staked: false,
+            reason: "write to a slot that isn't associated with the sender or the entity"
+                .to_string(),
+            rule_id: "STO-033".to_string(),
+        };
+        let (entity, violation) = error.rule_violation();
+        assert_eq!(entity, Some("account".to_string()));
+        assert_eq!(violation.rule, "storage_access");
+        assert_eq!(violation.rule_id, Some("STO-033".to_string()));
+        assert_eq!(violation.address, Some(address));
+        assert_eq!(violation.slot, Some("1".to_string()));
+    }
+
+    #[test]
+    fn storage_access_error_data() {
+        let address = Address::random();
+        let error = SimulationError::from(SimulateValidationError::StorageAccessValidation {
+            entity: "paymaster".to_string(),
+            address,
+            slot: "2a".to_string(),
+            access: "write".to_string(),
+            allowed_if_staked: true,
+            reason: "storage of the entity and the entity isn't staked".to_string(),
+            rule_id: "STO-031".to_string(),
+        });
+
+        assert_eq!(error.code(), OPCODE_VALIDATION_ERROR_CODE);
+        let data: serde_json::Value =
+            serde_json::from_str(error.data().expect("error data").get()).unwrap();
+        assert_eq!(
+            data,
+            json!({
+                "entity": "paymaster", "address": address, "slot": "2a", "access": "write",
+                "allowed_if_staked": true, "reason": "storage of the entity and the entity isn't staked",
+                "rule_id": "STO-031",
+            })
+        );
+    }
+
+    #[test]
+    fn opcode_rules() {
+        assert_eq!(opcode_rule_violation("TIMESTAMP", true), Some("OP-011"));
+        assert_eq!(opcode_rule_violation("GAS", false), Some("OP-012"));
+        assert_eq!(opcode_rule_violation("BALANCE", false), Some("OP-080"));
+        assert_eq!(opcode_rule_violation("SELFBALANCE", true), None);
+        assert_eq!(opcode_rule_violation("SLOAD", false), None);
+    }
+
+    #[test]
+    fn storage_rules() {
+        let factory = Address::random();
+        let paymaster = Address::random();
+        let token = Address::random();
+        let mut stake_info_by_entity: [StakeInfo; NUMBER_LEVELS] = Default::default();
+        stake_info_by_entity[0].address = factory;
+        stake_info_by_entity[1].address = Address::random();
+        stake_info_by_entity[2].address = paymaster;
+        let rule = |access: &SlotAccess, staked: [bool; NUMBER_LEVELS]| {
+            storage_rule_violation(access, &stake_info_by_entity, &staked)
+                .map(|(rule_id, allowed_if_staked, _)| (rule_id, allowed_if_staked))
+        };
+
+        // the sender's slots need a staked factory while the sender is deployed
+        let access = SlotAccess {
+            index: 1,
+            address: &token,
+            write: true,
+            associated_with_sender: true,
+            associated_with_entity: true,
+        };
+        assert_eq!(rule(&access, [false; 3]), Some(("STO-022", false)));
+        assert_eq!(rule(&access, [true, false, false]), None);
+        let mut existing_sender = stake_info_by_entity;
+        existing_sender[0].address = Address::zero();
+        assert_eq!(
+            storage_rule_violation(&access, &existing_sender, &[false; 3]),
+            None
+        );
+
+        // own storage and associated slots of staked entities
+        let access = SlotAccess {
+            index: 2,
+            address: &paymaster,
+            write: true,
+            associated_with_sender: false,
+            associated_with_entity: false,
+        };
+        assert_eq!(rule(&access, [false; 3]), Some(("STO-031", true)));
+        assert_eq!(rule(&access, [false, false, true]), None);
+        let access = SlotAccess {
+            address: &token,
+            associated_with_entity: true,
+            ..access
+        };
+        assert_eq!(rule(&access, [false; 3]), Some(("STO-032", true)));
+        assert_eq!(rule(&access, [false, false, true]), None);
+
+        // staked entities may only read other slots of non-entity contracts
+        let access = SlotAccess {
+            associated_with_entity: false,
+            ..access
+        };
+        assert_eq!(
+            rule(&access, [false, false, true]),
+            Some(("STO-033", false))
+        );
+        let access = SlotAccess {
+            write: false,
+            ..access
+        };
+        assert_eq!(rule(&access, [false; 3]), Some(("STO-033", true)));
+        assert_eq!(rule(&access, [false, false, true]), None);
+        let access = SlotAccess {
+            address: &factory,
+            ..access
+        };
+        assert_eq!(rule(&access, [true; 3]), Some(("STO-033", false)));
+    }
+
+    #[test]
+    fn validation_report_with_violations_across_entities() {
+        let (provider, _) = Provider::mocked();
+        let provider = Arc::new(provider);
+        let mut reputation = Box::<MemoryReputation>::default();
+        reputation.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            U256::exp10(18),
+            U256::zero(),
+        );
+        let paymaster = Address::random();
+        reputation.add_blacklist(&paymaster);
+        let uopool = UoPool::new(
+            EntryPoint::new(provider.clone(), Address::random()),
+            Box::<MemoryMempool>::default(),
+            reputation,
+            provider,
+            U256::from(1_500_000),
+            U256::zero(),
+            U256::from(5),
+        );
+
+        let user_operation = UserOperation::random();
+        let stake_info_by_entity = [
+            StakeInfo {
+                address: Address::random(),
+                ..Default::default()
+            },
+            StakeInfo {
+                address: user_operation.sender,
+                ..Default::default()
+            },
+            StakeInfo {
+                address: paymaster,
+                ..Default::default()
+            },
+        ];
+        let mut errors = vec![
+            SimulateValidationError::SignatureValidation {},
+            SimulateValidationError::StorageAccessValidation {
+                entity: "factory".to_string(),
+                address: Address::random(),
+                slot: "1".to_string(),
+                access: "read".to_string(),
+                allowed_if_staked: true,
+                reason: "slot isn't associated with the sender or the entity and the entity isn't staked"
+                    .to_string(),
+                rule_id: "STO-033".to_string(),
+            },
+            SimulateValidationError::OpcodeValidation {
+                entity: "account".to_string(),
+                opcode: "TIMESTAMP".to_string(),
+                rule_id: "OP-011".to_string(),
+            },
+        ];
+        let stake_violations =
+            uopool.stake_violations(&user_operation, &stake_info_by_entity, &errors);
+        errors.extend(stake_violations);
+        let report = build_validation_report(
+            &stake_info_by_entity,
+            uopool.staked_entities(&stake_info_by_entity),
+            errors,
+        );
+
+        let rules = |entity: &str| {
+            report
+                .entities
+                .iter()
+                .find(|entity_report| entity_report.entity == entity)
+                .map(|entity_report| {
+                    entity_report
+                        .violations
+                        .iter()
+                        .map(|violation| violation.rule.clone())
+                        .collect::<Vec<String>>()
+                })
+                .unwrap()
+        };
+        assert!(!report.valid);
+        // the unstaked factory needs a stake for the storage access, the account doesn't
+        assert_eq!(rules("factory"), vec!["storage_access", "stake"]);
+        assert_eq!(rules("account"), vec!["opcodes"]);
+        assert_eq!(rules("paymaster"), vec!["stake"]);
+        assert!(report.entities[2].violations[0].message.contains("banned"));
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].rule, "signature");
+    }
 }
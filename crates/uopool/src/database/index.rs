@@ -0,0 +1,146 @@
+use aa_bundler_primitives::UserOperationHash;
+use ethers::{
+    prelude::{EthAbiCodec, EthAbiType, LogMeta},
+    types::{Address, H256, U256},
+};
+use reth_db::{
+    database::Database,
+    mdbx::EnvironmentKind,
+    table,
+    transaction::{DbTx, DbTxMut},
+    Error, TableType,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::{
+    mempool::{DBError, Env},
+    utils::{WrapUserOperationHash, WrapUserOperationLocation},
+};
+
+// Location of the UserOperationEvent of an included user operation
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, EthAbiCodec, EthAbiType,
+)]
+pub struct UserOperationLocation {
+    pub entry_point: Address,
+    pub transaction_hash: H256,
+    pub block_hash: H256,
+    pub block_number: u64,
+    pub log_index: U256,
+}
+
+impl From<&LogMeta> for UserOperationLocation {
+    fn from(log_meta: &LogMeta) -> Self {
+        Self {
+            entry_point: log_meta.address,
+            transaction_hash: log_meta.transaction_hash,
+            block_hash: log_meta.block_hash,
+            block_number: log_meta.block_number.as_u64(),
+            log_index: log_meta.log_index,
+        }
+    }
+}
+
+table!(
+    /// UserOperationLocation DB
+    ( UserOperationLocationDB ) WrapUserOperationHash | WrapUserOperationLocation
+);
+
+pub const INDEX_TABLES: [(TableType, &str); 1] =
+    [(TableType::Table, UserOperationLocationDB::const_name())];
+
+// Index of user operation hashes to the location of their UserOperationEvent, so receipts can be
+// found without querying logs
+#[derive(Debug)]
+pub struct DatabaseUserOperationIndex<E: EnvironmentKind> {
+    _path: PathBuf,
+    env: Env<E>,
+}
+
+impl<E: EnvironmentKind> DatabaseUserOperationIndex<E> {
+    pub fn new(path: PathBuf) -> anyhow::Result<Self> {
+        let env = Env::open(path.as_path(), INDEX_TABLES.len())?;
+
+        Ok(Self { _path: path, env })
+    }
+
+    /// Creates all the defined tables, if necessary.
+    pub fn create_tables(&self) -> Result<(), Error> {
+        self.env.create_tables(&INDEX_TABLES)
+    }
+
+    pub fn insert(
+        &self,
+        user_operation_hash: &UserOperationHash,
+        location: UserOperationLocation,
+    ) -> Result<(), DBError> {
+        let wrap_user_operation_hash: WrapUserOperationHash = (*user_operation_hash).into();
+
+        let tx = self.env.tx_mut()?;
+        tx.put::<UserOperationLocationDB>(wrap_user_operation_hash, location.into())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get(
+        &self,
+        user_operation_hash: &UserOperationHash,
+    ) -> Result<Option<UserOperationLocation>, DBError> {
+        let wrap_user_operation_hash: WrapUserOperationHash = (*user_operation_hash).into();
+
+        let tx = self.env.tx()?;
+        let res = tx.get::<UserOperationLocationDB>(wrap_user_operation_hash)?;
+        tx.commit()?;
+
+        Ok(res.map(|location| location.into()))
+    }
+
+    pub fn remove(&self, user_operation_hash: &UserOperationHash) -> Result<(), DBError> {
+        let wrap_user_operation_hash: WrapUserOperationHash = (*user_operation_hash).into();
+
+        let tx = self.env.tx_mut()?;
+        tx.delete::<UserOperationLocationDB>(wrap_user_operation_hash, None)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_db::mdbx::NoWriteMap;
+    use tempdir::TempDir;
+
+    #[test]
+    fn database_user_operation_index() {
+        let dir = TempDir::new("test-userop-index").unwrap();
+        let index: DatabaseUserOperationIndex<NoWriteMap> =
+            DatabaseUserOperationIndex::new(dir.into_path()).unwrap();
+        index
+            .create_tables()
+            .expect("Create mdbx database tables failed");
+
+        let user_operation_hash = UserOperationHash::from(H256::random());
+        let location = UserOperationLocation {
+            entry_point: Address::random(),
+            transaction_hash: H256::random(),
+            block_hash: H256::random(),
+            block_number: 100,
+            log_index: U256::from(3),
+        };
+
+        assert_eq!(index.get(&user_operation_hash), Ok(None));
+        index
+            .insert(&user_operation_hash, location.clone())
+            .unwrap();
+        assert_eq!(index.get(&user_operation_hash), Ok(Some(location)));
+        assert_eq!(
+            index.get(&UserOperationHash::from(H256::random())),
+            Ok(None)
+        );
+
+        index.remove(&user_operation_hash).unwrap();
+        assert_eq!(index.get(&user_operation_hash), Ok(None));
+    }
+}
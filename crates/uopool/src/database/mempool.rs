@@ -14,7 +14,10 @@ use reth_db::{
     transaction::{DbTx, DbTxMut},
     Error, TableType,
 };
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
 use crate::mempool::Mempool;
 
@@ -122,6 +125,37 @@ impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
         Ok(hash)
     }
 
+    fn add_with_code_hashes(
+        &mut self,
+        user_operation: UserOperation,
+        entry_point: &Address,
+        chain_id: &U256,
+        code_hashes: &Self::CodeHashes,
+    ) -> Result<UserOperationHash, DBError> {
+        let hash = user_operation.hash(entry_point, chain_id);
+        let tx = self.env.tx_mut()?;
+
+        let wrap_user_operation_hash: WrapUserOperationHash = hash.into();
+        let wrap_user_operation: WrapUserOperation = user_operation.clone().into();
+
+        tx.put::<UserOperationDB>(
+            wrap_user_operation_hash.clone(),
+            wrap_user_operation.clone(),
+        )?;
+        tx.put::<SenderUserOperationDB>(user_operation.sender.into(), wrap_user_operation)?;
+        if tx
+            .get::<CodeHashDB>(wrap_user_operation_hash.clone())?
+            .is_some()
+        {
+            tx.delete::<CodeHashDB>(wrap_user_operation_hash.clone(), None)?;
+        }
+        for code_hash in code_hashes {
+            tx.put::<CodeHashDB>(wrap_user_operation_hash.clone(), code_hash.clone().into())?;
+        }
+        tx.commit()?;
+        Ok(hash)
+    }
+
     fn get(
         &self,
         user_operation_hash: &UserOperationHash,
@@ -206,6 +240,13 @@ impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
         let wrap_user_operation_hash: WrapUserOperationHash = (*user_operation_hash).into();
 
         let tx = self.env.tx_mut()?;
+        // code hashes are only kept with their user operation (and removed with it)
+        if tx
+            .get::<UserOperationDB>(wrap_user_operation_hash.clone())?
+            .is_none()
+        {
+            return Err(DBError::NotFound);
+        }
         let res = tx.get::<CodeHashDB>(wrap_user_operation_hash.clone())?;
         if res.is_some() {
             tx.delete::<CodeHashDB>(wrap_user_operation_hash.clone(), None)?;
@@ -274,6 +315,7 @@ impl<E: EnvironmentKind> Mempool for DatabaseMempool<E> {
             .and_then(|tx| {
                 tx.clear::<UserOperationDB>()?;
                 tx.clear::<SenderUserOperationDB>()?;
+                tx.clear::<CodeHashDB>()?;
                 tx.commit()
             })
             .expect("Clear database failed");
@@ -292,10 +334,10 @@ fn default_page_size() -> usize {
     os_page_size.clamp(min_page_size, libmdbx_max_page_size)
 }
 
-impl<E: EnvironmentKind> DatabaseMempool<E> {
-    pub fn new(path: PathBuf) -> anyhow::Result<Self> {
+impl<E: EnvironmentKind> Env<E> {
+    pub fn open(path: &Path, max_dbs: usize) -> anyhow::Result<Self> {
         let env = Environment::new()
-            .set_max_dbs(TABLES.len())
+            .set_max_dbs(max_dbs)
             .set_geometry(Geometry {
                 size: Some(0..(1024 * 1024 * 1024 * 1024 * 4)), // TODO: reevaluate (4 tb)
                 growth_step: Some(1024 * 1024 * 256),           // TODO: reevaluate (256 mb)
@@ -310,24 +352,20 @@ impl<E: EnvironmentKind> DatabaseMempool<E> {
                 coalesce: true,
                 ..Default::default()
             })
-            .open(path.as_path())
+            .open(path)
             .map_err(|e| Error::DatabaseLocation(e.into()))?;
 
-        Ok(Self {
-            _path: path,
-            env: Env { inner: env },
-        })
+        Ok(Self { inner: env })
     }
 
-    /// Creates all the defined tables, if necessary.
-    pub fn create_tables(&self) -> Result<(), Error> {
+    /// Creates the tables, if necessary.
+    pub fn create_tables(&self, tables: &[(TableType, &str)]) -> Result<(), Error> {
         let tx = self
-            .env
             .inner
             .begin_rw_txn()
             .map_err(|e| Error::InitTransaction(e.into()))?;
 
-        for (table_type, table) in TABLES {
+        for (table_type, table) in tables {
             let flags = match table_type {
                 TableType::Table => DatabaseFlags::default(),
                 TableType::DupSort => DatabaseFlags::DUP_SORT,
@@ -343,6 +381,19 @@ impl<E: EnvironmentKind> DatabaseMempool<E> {
     }
 }
 
+impl<E: EnvironmentKind> DatabaseMempool<E> {
+    pub fn new(path: PathBuf) -> anyhow::Result<Self> {
+        let env = Env::open(path.as_path(), TABLES.len())?;
+
+        Ok(Self { _path: path, env })
+    }
+
+    /// Creates all the defined tables, if necessary.
+    pub fn create_tables(&self) -> Result<(), Error> {
+        self.env.create_tables(&TABLES)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
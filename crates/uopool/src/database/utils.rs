@@ -1,4 +1,6 @@
 use aa_bundler_primitives::{CodeHash, UserOperation, UserOperationHash};
+
+use super::index::UserOperationLocation;
 use ethers::{
     abi::{AbiDecode, AbiEncode},
     prelude::{EthAbiCodec, EthAbiType},
@@ -100,3 +102,4 @@ construct_wrap_hash!(UserOperationHash, WrapUserOperationHash, 32);
 
 construct_wrap_struct!(CodeHash, WrapCodeHash);
 construct_wrap_struct!(UserOperation, WrapUserOperation);
+construct_wrap_struct!(UserOperationLocation, WrapUserOperationLocation);
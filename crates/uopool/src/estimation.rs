@@ -0,0 +1,332 @@
+use std::future::Future;
+
+use aa_bundler_contracts::{decode_revert_reason, EntryPointErr, SimulateValidationResult};
+use aa_bundler_primitives::UserOperation;
+use ethers::{
+    providers::{call_raw::spoof, Middleware},
+    types::{Address, Bytes, U256},
+};
+use tracing::trace;
+
+use crate::{canonical::simulation::SimulateValidationError, UoPool};
+
+// binary search stops when the interval is smaller than this
+const GAS_TOLERANCE: u64 = 1000;
+// upper bound for the call gas limit search
+const MAX_CALL_GAS_LIMIT: u64 = 10_000_000;
+pub const DEFAULT_VERIFICATION_GAS_MARGIN: u64 = 10;
+
+// Find the lowest gas limit (within tolerance) for which `succeeds` returns true, assuming it
+// succeeds with `high`. Any error other than "gas too low" should be returned by `succeeds`.
+pub async fn binary_search_gas<F, Fut, E>(
+    mut low: U256,
+    mut high: U256,
+    tolerance: U256,
+    mut succeeds: F,
+) -> Result<U256, E>
+where
+    F: FnMut(U256) -> Fut,
+    Fut: Future<Output = Result<bool, E>>,
+{
+    while high.saturating_sub(low) > tolerance {
+        let mid = (low + high) / 2;
+        if succeeds(mid).await? {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+
+    Ok(high)
+}
+
+// Lowest call gas limit (within tolerance) for which the user operation pays the same amount as
+// with the upper bound. EntryPoint charges the gas used, so the paid amount shouldn't depend on
+// the call gas limit as long as the execution succeeds. If it does, the upper bound is returned.
+pub async fn search_call_gas_limit<F, Fut, E>(
+    max_call_gas_limit: U256,
+    mut paid: F,
+) -> Result<U256, E>
+where
+    F: FnMut(U256) -> Fut,
+    Fut: Future<Output = Result<U256, E>>,
+{
+    let paid_with_max = paid(max_call_gas_limit).await?;
+
+    binary_search_gas(
+        U256::zero(),
+        max_call_gas_limit,
+        U256::from(GAS_TOLERANCE),
+        |call_gas_limit| {
+            let paid_with_limit = paid(call_gas_limit);
+            async move { Ok(paid_with_limit.await? == paid_with_max) }
+        },
+    )
+    .await
+}
+
+impl<M: Middleware + 'static> UoPool<M> {
+    // Gas fees are set to zero, so the prefund (and the deposit of the account or paymaster)
+    // doesn't depend on the gas limit that is tried.
+    async fn simulate_validation_with_gas(
+        &self,
+        user_operation: &UserOperation,
+        verification_gas_limit: U256,
+        state_override: &spoof::State,
+    ) -> Result<SimulateValidationResult, EntryPointErr> {
+        self.entry_point
+            .simulate_validation(
+                UserOperation {
+                    verification_gas_limit,
+                    max_fee_per_gas: U256::zero(),
+                    max_priority_fee_per_gas: U256::zero(),
+                    ..user_operation.clone()
+                },
+                state_override,
+            )
+            .await
+    }
+
+    pub async fn estimate_verification_gas_limit(
+        &self,
+        user_operation: &UserOperation,
+        state_override: &spoof::State,
+    ) -> Result<U256, SimulateValidationError> {
+        let pre_op_gas = match self
+            .simulate_validation_with_gas(user_operation, self.max_verification_gas, state_override)
+            .await
+        {
+            Ok(SimulateValidationResult::ValidationResult(validation_result)) => {
+                validation_result.return_info.0
+            }
+            Ok(SimulateValidationResult::ValidationResultWithAggregation(
+                validation_result_with_aggregation,
+            )) => validation_result_with_aggregation.return_info.0,
+            Err(EntryPointErr::FailedOp(failed_op)) => {
+                return Err(SimulateValidationError::UserOperationRejected {
+                    message: format!("{failed_op}"),
+                })
+            }
+            Err(error) => {
+                return Err(SimulateValidationError::UnknownError {
+                    error: error.to_string(),
+                })
+            }
+        };
+
+        // preOpGas includes preVerificationGas, the rest is gas used by the validation (and by the
+        // account deployment if init code is set). Because of the 63/64 rule, the gas limit needed
+        // can be higher than the gas used, especially when the account is deployed by a factory.
+        let gas_used = pre_op_gas.saturating_sub(user_operation.pre_verification_gas);
+
+        let verification_gas_limit = binary_search_gas(
+            gas_used.min(self.max_verification_gas),
+            self.max_verification_gas,
+            U256::from(GAS_TOLERANCE),
+            |verification_gas_limit| async move {
+                match self
+                    .simulate_validation_with_gas(
+                        user_operation,
+                        verification_gas_limit,
+                        state_override,
+                    )
+                    .await
+                {
+                    Ok(_) => Ok(true),
+                    // out of gas (AA13, AA23, AA33, AA40, AA41), try with more gas
+                    Err(EntryPointErr::FailedOp(_)) => Ok(false),
+                    Err(error) => Err(SimulateValidationError::UnknownError {
+                        error: error.to_string(),
+                    }),
+                }
+            },
+        )
+        .await?;
+
+        trace!(
+            "Estimated verification gas limit {verification_gas_limit} (gas used {gas_used}) for user operation {user_operation:?}"
+        );
+
+        Ok(
+            (verification_gas_limit * (100 + self.verification_gas_margin) / 100)
+                .min(self.max_verification_gas),
+        )
+    }
+
+    // If the balance isn't overridden, the sender gets enough balance to pay the prefund, so
+    // counterfactual accounts without deposit can be estimated too. Paymasters are expected to
+    // have a deposit.
+    fn call_gas_state_override(
+        user_operation: &UserOperation,
+        state_override: &spoof::State,
+    ) -> spoof::State {
+        let mut state = state_override.clone();
+        let sender = state.account(user_operation.sender);
+        if sender.balance.is_none() {
+            sender.balance(U256::from(u128::MAX));
+        }
+        state
+    }
+
+    // Gas paid by the user operation with the given call gas limit. If the fees are zero, the gas
+    // price is set to 1 wei so the paid amount still reflects the gas used.
+    async fn simulate_handle_op_paid(
+        &self,
+        user_operation: &UserOperation,
+        call_gas_limit: U256,
+        state_override: &spoof::State,
+    ) -> Result<U256, EntryPointErr> {
+        let mut user_operation = UserOperation {
+            call_gas_limit,
+            ..user_operation.clone()
+        };
+        if user_operation.max_fee_per_gas.is_zero() {
+            user_operation.max_fee_per_gas = U256::one();
+            user_operation.max_priority_fee_per_gas = U256::one();
+        }
+
+        self.entry_point
+            .simulate_handle_op(
+                user_operation,
+                Address::zero(),
+                Bytes::default(),
+                state_override,
+            )
+            .await
+            .map(|execution_result| execution_result.paid)
+    }
+
+    // The verification gas limit of the user operation should already be estimated.
+    pub async fn estimate_call_gas_limit(
+        &self,
+        user_operation: &UserOperation,
+        state_override: &spoof::State,
+    ) -> Result<U256, SimulateValidationError> {
+        if user_operation.call_data.is_empty() {
+            return Ok(U256::zero());
+        }
+        let state_override = &Self::call_gas_state_override(user_operation, state_override);
+
+        let map_err = |error: EntryPointErr| match error {
+            EntryPointErr::FailedOp(failed_op) => SimulateValidationError::UserOperationRejected {
+                message: format!("{failed_op}"),
+            },
+            _ => SimulateValidationError::UnknownError {
+                error: error.to_string(),
+            },
+        };
+
+        // The call data is executed as the target of simulateHandleOp, after the account is
+        // deployed (if init code is set), to get the revert reason if the execution fails.
+        let execution_result = self
+            .entry_point
+            .simulate_handle_op(
+                UserOperation {
+                    call_data: Bytes::default(),
+                    call_gas_limit: U256::zero(),
+                    ..user_operation.clone()
+                },
+                user_operation.sender,
+                user_operation.call_data.clone(),
+                state_override,
+            )
+            .await
+            .map_err(map_err)?;
+        if !execution_result.target_success {
+            return Err(SimulateValidationError::UserOperationExecution {
+                message: decode_revert_reason(&execution_result.target_result),
+            });
+        }
+
+        // The amount paid includes postOp of the paymaster
+        let call_gas_limit = search_call_gas_limit(
+            U256::from(MAX_CALL_GAS_LIMIT),
+            |call_gas_limit| async move {
+                self.simulate_handle_op_paid(user_operation, call_gas_limit, state_override)
+                    .await
+                    .map_err(map_err)
+            },
+        )
+        .await?;
+
+        trace!("Estimated call gas limit {call_gas_limit} for user operation {user_operation:?}");
+
+        Ok(call_gas_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn binary_search_gas_limit() {
+        let required = U256::from(123_456);
+
+        let result: Result<U256, ()> = binary_search_gas(
+            U256::from(50_000),
+            U256::from(1_500_000),
+            U256::from(GAS_TOLERANCE),
+            |gas| async move { Ok(gas >= required) },
+        )
+        .await;
+        let gas = result.unwrap();
+        assert!(gas >= required);
+        assert!(gas - required <= U256::from(GAS_TOLERANCE));
+
+        // interval within tolerance is not searched
+        let result: Result<U256, ()> = binary_search_gas(
+            required,
+            required + 500,
+            U256::from(GAS_TOLERANCE),
+            |_| async move { Err(()) },
+        )
+        .await;
+        assert_eq!(result.unwrap(), required + 500);
+
+        let result: Result<U256, &str> = binary_search_gas(
+            U256::from(0),
+            U256::from(1_500_000),
+            U256::from(GAS_TOLERANCE),
+            |_| async move { Err("network error") },
+        )
+        .await;
+        assert_eq!(result, Err("network error"));
+    }
+
+    #[tokio::test]
+    async fn call_gas_limit_search() {
+        let max_call_gas_limit = U256::from(MAX_CALL_GAS_LIMIT);
+        let required = U256::from(150_000);
+
+        // out of gas below the required gas, the call uses all of the call gas limit
+        let result: Result<U256, ()> =
+            search_call_gas_limit(max_call_gas_limit, |gas| async move {
+                Ok(if gas >= required {
+                    U256::from(1_000_000)
+                } else {
+                    gas
+                })
+            })
+            .await;
+        let gas = result.unwrap();
+        assert!(gas >= required);
+        assert!(gas - required <= U256::from(GAS_TOLERANCE));
+
+        // the search never goes above the upper bound
+        let result: Result<U256, ()> =
+            search_call_gas_limit(max_call_gas_limit, |gas| async move { Ok(gas) }).await;
+        assert_eq!(result.unwrap(), max_call_gas_limit);
+
+        let result: Result<U256, &str> =
+            search_call_gas_limit(max_call_gas_limit, |gas| async move {
+                if gas == max_call_gas_limit {
+                    Ok(U256::from(1_000_000))
+                } else {
+                    Err("network error")
+                }
+            })
+            .await;
+        assert_eq!(result, Err("network error"));
+    }
+}
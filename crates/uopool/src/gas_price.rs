@@ -0,0 +1,108 @@
+use aa_bundler_primitives::{UserOperationGasPrice, UserOperationGasPriceSuggestion};
+use anyhow::format_err;
+use ethers::{
+    providers::Middleware,
+    types::{BlockNumber, U256},
+};
+
+use crate::UoPool;
+
+// number of recent blocks the base fees and tips are taken from
+const FEE_HISTORY_BLOCKS: u64 = 10;
+// tip percentiles for slow, standard and fast
+const TIP_PERCENTILES: [f64; 3] = [25.0, 50.0, 75.0];
+// base fee multipliers (in percent) for slow, standard and fast, the fast one covers the base
+// fee increase of 6 full blocks
+const BASE_FEE_MULTIPLIERS: [u64; 3] = [100, 125, 200];
+
+// nearest rank percentile of sorted values
+fn percentile(sorted: &[U256], percentile: f64) -> Option<U256> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted.get(rank).copied()
+}
+
+impl<M: Middleware + 'static> UoPool<M> {
+    pub async fn suggest_gas_price(&self) -> anyhow::Result<UserOperationGasPriceSuggestion> {
+        let fee_history = self
+            .eth_provider
+            .fee_history(FEE_HISTORY_BLOCKS, BlockNumber::Latest, &TIP_PERCENTILES)
+            .await
+            .map_err(|error| format_err!("Failed to get fee history: {error:?}"))?;
+
+        // the last base fee is the one of the next block, the sanity check uses the latest one
+        let base_fees = &fee_history.base_fee_per_gas;
+        let base_fee = base_fees
+            .iter()
+            .rev()
+            .take(2)
+            .max()
+            .copied()
+            .unwrap_or_default();
+        let max_base_fee = base_fees.iter().max().copied().unwrap_or_default();
+
+        let mut tips: Vec<U256> = self
+            .mempool
+            .get_all()
+            .into_iter()
+            .map(|user_operation| user_operation.max_priority_fee_per_gas)
+            .collect();
+        tips.sort();
+
+        let suggest = |level: usize| {
+            // tips of the user operations in the mempool, or the tips in recent blocks if the
+            // mempool is empty
+            let tip = percentile(&tips, TIP_PERCENTILES[level])
+                .unwrap_or_else(|| {
+                    let rewards: Vec<U256> = fee_history
+                        .reward
+                        .iter()
+                        .filter_map(|reward| reward.get(level).copied())
+                        .collect();
+                    if rewards.is_empty() {
+                        U256::zero()
+                    } else {
+                        rewards
+                            .iter()
+                            .fold(U256::zero(), |sum, reward| sum + reward)
+                            / U256::from(rewards.len())
+                    }
+                })
+                .max(self.min_priority_fee_per_gas);
+
+            let mut level_base_fee = base_fee * U256::from(BASE_FEE_MULTIPLIERS[level]) / 100;
+            if level > 0 {
+                level_base_fee = level_base_fee.max(max_base_fee);
+            }
+
+            UserOperationGasPrice {
+                max_fee_per_gas: level_base_fee + tip,
+                max_priority_fee_per_gas: tip,
+            }
+        };
+
+        Ok(UserOperationGasPriceSuggestion {
+            slow: suggest(0),
+            standard: suggest(1),
+            fast: suggest(2),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tip_percentiles() {
+        assert_eq!(percentile(&[], 50.0), None);
+
+        let tips: Vec<U256> = (1..=5).map(U256::from).collect();
+        assert_eq!(percentile(&tips, 25.0), Some(U256::from(2)));
+        assert_eq!(percentile(&tips, 50.0), Some(U256::from(3)));
+        assert_eq!(percentile(&tips, 75.0), Some(U256::from(4)));
+        assert_eq!(percentile(&tips[..1], 75.0), Some(U256::from(1)));
+    }
+}
@@ -1,16 +1,30 @@
 #![allow(dead_code)]
 
 mod database;
+mod estimation;
+mod gas_price;
 mod memory;
 mod mempool;
 mod reputation;
+mod rules;
+mod simulator;
 mod uopool;
 mod utils;
 
-pub use database::mempool::DatabaseMempool;
+pub use database::{
+    index::{DatabaseUserOperationIndex, UserOperationLocation},
+    mempool::DatabaseMempool,
+};
+pub use estimation::DEFAULT_VERIFICATION_GAS_MARGIN;
 pub use memory::{mempool::MemoryMempool, reputation::MemoryReputation};
-pub use mempool::{mempool_id, MempoolId};
+pub use mempool::{alternative_mempool_id, mempool_id, MempoolId};
 pub use reputation::Reputation;
+pub use reth_db::mdbx::NoWriteMap;
+pub use rules::{
+    AlternativeMempoolConfig, RuleSet, RuleSetConfig, RuleSetRegistry, ValidationContext,
+    ValidationRule,
+};
+pub use simulator::{trace_call, Simulator};
 pub use uopool::UoPool;
 pub use utils::Overhead;
 
@@ -35,6 +35,20 @@ impl Mempool for MemoryMempool {
         Ok(hash)
     }
 
+    fn add_with_code_hashes(
+        &mut self,
+        user_operation: UserOperation,
+        entry_point: &Address,
+        chain_id: &U256,
+        code_hashes: &Self::CodeHashes,
+    ) -> anyhow::Result<UserOperationHash> {
+        let hash = self.add(user_operation, entry_point, chain_id)?;
+        self.code_hashes_by_user_operation
+            .insert(hash, code_hashes.clone());
+
+        Ok(hash)
+    }
+
     fn get(
         &self,
         user_operation_hash: &UserOperationHash,
@@ -72,6 +86,11 @@ impl Mempool for MemoryMempool {
         user_operation_hash: &UserOperationHash,
         code_hashes: &Self::CodeHashes,
     ) -> anyhow::Result<(), Self::Error> {
+        // code hashes are only kept with their user operation (and removed with it)
+        if !self.user_operations.contains_key(user_operation_hash) {
+            return Err(anyhow::anyhow!("User operation not found"));
+        }
+
         self.code_hashes_by_user_operation
             .insert(*user_operation_hash, code_hashes.clone());
         Ok(())
@@ -209,6 +209,7 @@ impl Reputation for MemoryReputation {
 #[cfg(test)]
 mod tests {
     use aa_bundler_primitives::{BAN_SLACK, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK};
+    use std::sync::{Arc, Mutex};
 
     use super::*;
 
@@ -289,4 +290,23 @@ mod tests {
             ReputationStatus::BANNED
         );
     }
+
+    #[test]
+    fn shared_reputation() {
+        let reputation = Arc::new(Mutex::new(MemoryReputation::default()));
+        let mut canonical = reputation.clone();
+        let alternative = reputation;
+        canonical.init(
+            MIN_INCLUSION_RATE_DENOMINATOR,
+            THROTTLING_SLACK,
+            BAN_SLACK,
+            U256::from(1),
+            U256::from(0),
+        );
+
+        // an entity banned in the canonical mempool is banned in the alternative mempool as well
+        let address = Address::random();
+        canonical.add_blacklist(&address);
+        assert_eq!(alternative.get_status(&address), ReputationStatus::BANNED);
+    }
 }
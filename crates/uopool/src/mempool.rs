@@ -20,6 +20,22 @@ pub fn mempool_id(entry_point: &Address, chain_id: &U256) -> MempoolId {
     )
 }
 
+// Alternative mempools are identified by their name as well, so their ids (and gossip topics)
+// never collide with the canonical mempool of the entry point
+pub fn alternative_mempool_id(entry_point: &Address, chain_id: &U256, name: &str) -> MempoolId {
+    H256::from_slice(
+        keccak256(
+            [
+                to_checksum(entry_point, None).encode(),
+                chain_id.encode(),
+                name.to_string().encode(),
+            ]
+            .concat(),
+        )
+        .as_slice(),
+    )
+}
+
 pub trait Mempool: Debug {
     type UserOperations: IntoIterator<Item = UserOperation>;
     type CodeHashes: IntoIterator<Item = CodeHash>;
@@ -30,6 +46,15 @@ pub trait Mempool: Debug {
         entry_point: &Address,
         chain_id: &U256,
     ) -> Result<UserOperationHash, Self::Error>;
+    // Adds the user operation together with the code hashes of the contracts it accessed during
+    // validation, either both are stored or none
+    fn add_with_code_hashes(
+        &mut self,
+        user_operation: UserOperation,
+        entry_point: &Address,
+        chain_id: &U256,
+        code_hashes: &Self::CodeHashes,
+    ) -> Result<UserOperationHash, Self::Error>;
     fn get(
         &self,
         user_operation_hash: &UserOperationHash,
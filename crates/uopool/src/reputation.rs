@@ -1,4 +1,7 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
 use aa_bundler_primitives::{
     get_addr, BadReputationError, ReputationEntry, ReputationStatus, StakeInfo,
@@ -53,3 +56,99 @@ pub trait Reputation: Debug {
     fn get_all(&self) -> Self::ReputationEntries;
     fn clear(&mut self);
 }
+
+fn lock<R>(reputation: &Mutex<R>) -> MutexGuard<'_, R> {
+    reputation
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Reputation shared by the canonical and alternative mempools of an entry point
+impl<R: Reputation> Reputation for Arc<Mutex<R>> {
+    type ReputationEntries = R::ReputationEntries;
+
+    fn init(
+        &mut self,
+        min_inclusion_denominator: u64,
+        throttling_slack: u64,
+        ban_slack: u64,
+        min_stake: U256,
+        min_unstake_delay: U256,
+    ) {
+        lock(self).init(
+            min_inclusion_denominator,
+            throttling_slack,
+            ban_slack,
+            min_stake,
+            min_unstake_delay,
+        )
+    }
+
+    fn get(&mut self, address: &Address) -> ReputationEntry {
+        lock(self).get(address)
+    }
+
+    fn increment_seen(&mut self, address: &Address) {
+        lock(self).increment_seen(address)
+    }
+
+    fn increment_included(&mut self, address: &Address) {
+        lock(self).increment_included(address)
+    }
+
+    fn update_hourly(&mut self) {
+        lock(self).update_hourly()
+    }
+
+    fn add_whitelist(&mut self, address: &Address) -> bool {
+        lock(self).add_whitelist(address)
+    }
+
+    fn remove_whitelist(&mut self, address: &Address) -> bool {
+        lock(self).remove_whitelist(address)
+    }
+
+    fn is_whitelist(&self, address: &Address) -> bool {
+        lock(self).is_whitelist(address)
+    }
+
+    fn add_blacklist(&mut self, address: &Address) -> bool {
+        lock(self).add_blacklist(address)
+    }
+
+    fn remove_blacklist(&mut self, address: &Address) -> bool {
+        lock(self).remove_blacklist(address)
+    }
+
+    fn is_blacklist(&self, address: &Address) -> bool {
+        lock(self).is_blacklist(address)
+    }
+
+    fn get_status(&self, address: &Address) -> ReputationStatus {
+        lock(self).get_status(address)
+    }
+
+    fn update_handle_ops_reverted(&mut self, address: &Address) {
+        lock(self).update_handle_ops_reverted(address)
+    }
+
+    fn verify_stake(
+        &self,
+        title: &str,
+        stake_info: Option<StakeInfo>,
+    ) -> Result<(), BadReputationError> {
+        lock(self).verify_stake(title, stake_info)
+    }
+
+    fn set(&mut self, reputation_entries: Self::ReputationEntries) {
+        lock(self).set(reputation_entries)
+    }
+
+    fn get_all(&self) -> Self::ReputationEntries {
+        lock(self).get_all()
+    }
+
+    fn clear(&mut self) {
+        lock(self).clear()
+    }
+}
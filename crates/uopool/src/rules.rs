@@ -0,0 +1,279 @@
+use aa_bundler_contracts::JsTracerFrame;
+use aa_bundler_primitives::{StakeInfo, UserOperation};
+use anyhow::format_err;
+use ethers::types::{Address, U256};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    canonical::simulation::{
+        CallStack, ForbiddenOpcodes, OutOfGas, SimulateValidationError, StorageAccess,
+        UndeployedContracts, NUMBER_LEVELS,
+    },
+    mempool::{alternative_mempool_id, MempoolId},
+};
+
+// Traced validation of a user operation that the rules are checked against
+pub struct ValidationContext<'a> {
+    pub user_operation: &'a UserOperation,
+    pub entry_point: Address,
+    pub trace: &'a JsTracerFrame,
+    // 0 - factory, 1 - sender/account, 2 - paymaster
+    pub stake_info_by_entity: &'a [StakeInfo; NUMBER_LEVELS],
+    // entities with the minimum stake and unstake delay
+    pub staked: [bool; NUMBER_LEVELS],
+}
+
+pub trait ValidationRule: Debug + Send + Sync {
+    // name of the rule in validation reports (opcodes, storage_access, ...)
+    fn name(&self) -> &str;
+    fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError>;
+    // the violation of the canonical rule isn't a violation of this rule (e.g. an allowed opcode)
+    fn relaxes(&self, _error: &SimulateValidationError) -> bool {
+        false
+    }
+}
+
+// Relaxations of the canonical rules for an alternative mempool
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RuleSetConfig {
+    // opcodes that entities may use (e.g. TIMESTAMP)
+    pub allowed_opcodes: Vec<String>,
+    // contracts which storage entities may access
+    pub allowed_storage: Vec<Address>,
+    // rules (by name) or ERC-7562 rule ids (e.g. OP-080) that aren't enforced
+    pub skip: Vec<String>,
+}
+
+// Validation rules of a mempool, checked in order
+#[derive(Clone, Debug)]
+pub struct RuleSet {
+    rules: Vec<Arc<dyn ValidationRule>>,
+    skip: HashSet<String>,
+}
+
+impl Default for RuleSet {
+    // the canonical mempool rules
+    fn default() -> Self {
+        Self::from_config(&RuleSetConfig::default())
+    }
+}
+
+impl RuleSet {
+    pub fn from_config(config: &RuleSetConfig) -> Self {
+        Self {
+            rules: vec![
+                Arc::new(ForbiddenOpcodes {
+                    allowed: config
+                        .allowed_opcodes
+                        .iter()
+                        .map(|opcode| opcode.to_uppercase())
+                        .collect(),
+                }),
+                Arc::new(OutOfGas),
+                Arc::new(UndeployedContracts),
+                Arc::new(StorageAccess {
+                    allowed: config.allowed_storage.iter().copied().collect(),
+                }),
+                Arc::new(CallStack),
+            ],
+            skip: config.skip.iter().cloned().collect(),
+        }
+    }
+
+    pub fn push(&mut self, rule: Arc<dyn ValidationRule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError> {
+        self.rules
+            .iter()
+            .filter(|rule| !self.skip.contains(rule.name()))
+            .flat_map(|rule| rule.check(context))
+            .filter(|error| {
+                error
+                    .rule_id()
+                    .map_or(true, |rule_id| !self.skip.contains(rule_id))
+            })
+            .collect()
+    }
+
+    // The rule set doesn't enforce the rule violated by a user operation (which may be valid for
+    // this rule set). Violations without a rule id (signature, reputation, ...) apply to all.
+    pub fn relaxes(&self, error: &SimulateValidationError) -> bool {
+        let Some(rule_id) = error.rule_id() else {
+            return false;
+        };
+        let (_, violation) = error.rule_violation();
+        self.rules
+            .iter()
+            .find(|rule| rule.name() == violation.rule)
+            .map_or(false, |rule| {
+                self.skip.contains(rule.name())
+                    || self.skip.contains(rule_id)
+                    || rule.relaxes(error)
+            })
+    }
+}
+
+// An alternative mempool of an entry point with relaxed validation rules
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct AlternativeMempoolConfig {
+    pub entry_point: Address,
+    #[serde(flatten)]
+    pub rules: RuleSetConfig,
+}
+
+// Rule sets of the alternative mempools by mempool id, which run alongside the canonical mempools
+#[derive(Clone, Debug, Default)]
+pub struct RuleSetRegistry {
+    rule_sets: HashMap<MempoolId, (Address, RuleSet)>,
+}
+
+impl RuleSetRegistry {
+    // The config file is a JSON object with the names of the alternative mempools as keys, e.g.
+    // {"timestamp": {"entry_point": "0x…", "allowed_opcodes": ["TIMESTAMP"], "skip": ["OP-080"]}}
+    pub fn from_config_file(path: &Path, chain_id: &U256) -> anyhow::Result<Self> {
+        let configs: HashMap<String, AlternativeMempoolConfig> =
+            serde_json::from_str(&fs::read_to_string(path)?).map_err(|error| {
+                format_err!("Failed to parse validation rules config file: {error}")
+            })?;
+
+        let mut registry = Self::default();
+        for (name, config) in configs {
+            registry.register(
+                &name,
+                config.entry_point,
+                chain_id,
+                RuleSet::from_config(&config.rules),
+            );
+        }
+        Ok(registry)
+    }
+
+    // Registers the rule set of an alternative mempool and returns its id
+    pub fn register(
+        &mut self,
+        name: &str,
+        entry_point: Address,
+        chain_id: &U256,
+        rule_set: RuleSet,
+    ) -> MempoolId {
+        let id = alternative_mempool_id(&entry_point, chain_id, name);
+        self.rule_sets.insert(id, (entry_point, rule_set));
+        id
+    }
+
+    pub fn get(&self, id: &MempoolId) -> Option<&RuleSet> {
+        self.rule_sets.get(id).map(|(_, rule_set)| rule_set)
+    }
+
+    // Alternative mempools with their entry points
+    pub fn iter(&self) -> impl Iterator<Item = (&MempoolId, &Address, &RuleSet)> {
+        self.rule_sets
+            .iter()
+            .map(|(id, (entry_point, rule_set))| (id, entry_point, rule_set))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::mempool_id;
+    use aa_bundler_contracts::Level;
+
+    // custom rule registered in code
+    #[derive(Debug)]
+    struct RequirePaymaster;
+
+    impl ValidationRule for RequirePaymaster {
+        fn name(&self) -> &str {
+            "require_paymaster"
+        }
+
+        fn check(&self, context: &ValidationContext) -> Vec<SimulateValidationError> {
+            if context.stake_info_by_entity[2].address.is_zero() {
+                vec![SimulateValidationError::UserOperationRejected {
+                    message: "paymaster is required".to_string(),
+                }]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    #[test]
+    fn alternative_mempool_rules() {
+        let user_operation = UserOperation::random();
+        let mut stake_info_by_entity: [StakeInfo; NUMBER_LEVELS] = Default::default();
+        stake_info_by_entity[1].address = user_operation.sender;
+        let mut account = Level::default();
+        account.opcodes.insert("TIMESTAMP".to_string(), 1);
+        account.opcodes.insert("BALANCE".to_string(), 1);
+        let trace = JsTracerFrame {
+            number_levels: vec![Level::default(), account, Level::default()],
+            ..Default::default()
+        };
+        let context = ValidationContext {
+            user_operation: &user_operation,
+            entry_point: Address::random(),
+            trace: &trace,
+            stake_info_by_entity: &stake_info_by_entity,
+            staked: [false; NUMBER_LEVELS],
+        };
+        let rule_ids = |rule_set: RuleSet| {
+            let mut rule_ids: Vec<String> = rule_set
+                .check(&context)
+                .iter()
+                .filter_map(|error| error.rule_id().map(str::to_string))
+                .collect();
+            rule_ids.sort();
+            rule_ids
+        };
+
+        assert_eq!(rule_ids(RuleSet::default()), vec!["OP-011", "OP-080"]);
+
+        let dir = tempdir::TempDir::new("test-validation-rules").unwrap();
+        let path = dir.path().join("rules.json");
+        let chain_id = U256::from(5);
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"timestamp": {{"entry_point": "{:?}", "allowed_opcodes": ["timestamp"], "skip": ["OP-080"]}}}}"#,
+                context.entry_point
+            ),
+        )
+        .unwrap();
+        let mut registry = RuleSetRegistry::from_config_file(&path, &chain_id).unwrap();
+        let id = alternative_mempool_id(&context.entry_point, &chain_id, "timestamp");
+
+        assert_ne!(id, mempool_id(&context.entry_point, &chain_id));
+        assert!(rule_ids(registry.get(&id).unwrap().clone()).is_empty());
+
+        // only the violations of relaxed rules may be valid in the alternative mempool
+        let timestamp = SimulateValidationError::OpcodeValidation {
+            entity: "account".to_string(),
+            opcode: "TIMESTAMP".to_string(),
+            rule_id: "OP-011".to_string(),
+        };
+        let rule_set = registry.get(&id).unwrap();
+        assert!(rule_set.relaxes(&timestamp));
+        assert!(!rule_set.relaxes(&SimulateValidationError::SignatureValidation {}));
+        assert!(!RuleSet::default().relaxes(&timestamp));
+        assert!(registry.get(&MempoolId::random()).is_none());
+
+        let mut rule_set = RuleSet::default();
+        rule_set.push(Arc::new(RequirePaymaster));
+        let id = registry.register("paymaster", context.entry_point, &chain_id, rule_set);
+
+        assert_eq!(registry.iter().count(), 2);
+        assert_eq!(registry.get(&id).unwrap().check(&context).len(), 3);
+    }
+}
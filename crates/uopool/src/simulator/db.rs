@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use anyhow::format_err;
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockId, H256, U64},
+};
+use revm::{
+    db::DatabaseRef,
+    primitives::{AccountInfo, Bytecode, B160, B256, U256 as rU256},
+};
+use tokio::runtime::Handle;
+
+use super::{from_revm_u256, to_revm_u256};
+
+// State is fetched lazily from the provider (at a fixed block). The requests are blocking, so the
+// database should only be used outside of the async context (e.g. in spawn_blocking).
+pub struct ProviderDb<M: Middleware> {
+    provider: Arc<M>,
+    block: BlockId,
+    handle: Handle,
+}
+
+impl<M: Middleware + 'static> ProviderDb<M> {
+    pub fn new(provider: Arc<M>, block_number: U64, handle: Handle) -> Self {
+        Self {
+            provider,
+            block: block_number.into(),
+            handle,
+        }
+    }
+}
+
+impl<M: Middleware + 'static> DatabaseRef for ProviderDb<M> {
+    type Error = anyhow::Error;
+
+    fn basic(&self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        let address = Address::from(address.0);
+        let (balance, nonce, code) = self.handle.block_on(async {
+            tokio::try_join!(
+                self.provider.get_balance(address, Some(self.block)),
+                self.provider
+                    .get_transaction_count(address, Some(self.block)),
+                self.provider.get_code(address, Some(self.block)),
+            )
+        })?;
+
+        Ok(Some(AccountInfo::new(
+            to_revm_u256(balance),
+            nonce.as_u64(),
+            Bytecode::new_raw(code.0),
+        )))
+    }
+
+    // code is loaded together with the account
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        Err(format_err!(
+            "Code {:?} should be loaded with the account",
+            H256::from(code_hash.0)
+        ))
+    }
+
+    fn storage(&self, address: B160, index: rU256) -> Result<rU256, Self::Error> {
+        let value = self.handle.block_on(self.provider.get_storage_at(
+            Address::from(address.0),
+            H256::from_slice(&index.to_be_bytes::<32>()),
+            Some(self.block),
+        ))?;
+
+        Ok(rU256::from_be_bytes(value.0))
+    }
+
+    fn block_hash(&self, number: rU256) -> Result<B256, Self::Error> {
+        let number = from_revm_u256(number).as_u64();
+        let block = self
+            .handle
+            .block_on(self.provider.get_block(number))?
+            .ok_or_else(|| format_err!("Block {number} not found"))?;
+
+        Ok(B256(block.hash.unwrap_or_default().0))
+    }
+}
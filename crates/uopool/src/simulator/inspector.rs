@@ -0,0 +1,434 @@
+use aa_bundler_contracts::{Call, JsTracerFrame, Level, Log};
+use ethers::types::{Address, Bytes, U256};
+use revm::{
+    interpreter::{
+        opcode, CallInputs, CallScheme, CreateInputs, CreateScheme, Gas, InstructionResult,
+        Interpreter,
+    },
+    primitives::{Bytes as rBytes, B160},
+    Database, EVMData, Inspector,
+};
+
+use super::from_revm_u256;
+
+// Same output as the JS tracer (JS_TRACER), collected from the revm execution of the call.
+// Depth 1 is the top-level call (the entry point).
+#[derive(Debug)]
+pub struct JsTracerInspector<'a> {
+    frame: &'a mut JsTracerFrame,
+    depth: usize,
+    number_counter: usize,
+    last_op: String,
+    // index of the call entry of each create frame, the address is known only at the end
+    create_entries: Vec<usize>,
+}
+
+fn opcode_name(op: u8) -> String {
+    match op {
+        // revm names it SHA3
+        0x20 => "KECCAK256".to_string(),
+        _ => opcode::OPCODE_JUMPMAP[op as usize]
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("opcode {op:#x} not defined")),
+    }
+}
+
+// opcodes that aren't counted (same as in the JS tracer)
+fn ignored_opcode(opcode: &str) -> bool {
+    opcode.starts_with("DUP")
+        || opcode.starts_with("PUSH")
+        || opcode.starts_with("SWAP")
+        || matches!(
+            opcode,
+            "POP"
+                | "ADD"
+                | "SUB"
+                | "MUL"
+                | "DIV"
+                | "EQ"
+                | "LT"
+                | "LTE"
+                | "GT"
+                | "GTE"
+                | "SLT"
+                | "SGT"
+                | "SGTE"
+                | "SHL"
+                | "SHR"
+                | "AND"
+                | "OR"
+                | "NOT"
+                | "ISZERO"
+        )
+}
+
+// stack index of the address whose code size is recorded, CREATE2 isn't included because its
+// stack holds the init code offset and not an address
+fn code_size_address_index(opcode: &str) -> Option<usize> {
+    if opcode.starts_with("EXT") {
+        Some(0)
+    } else if matches!(opcode, "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL") {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+// memory slice padded with zeros, the memory isn't expanded yet before the opcode is executed
+fn memory_slice(interp: &Interpreter, offset: U256, length: U256) -> Bytes {
+    if length.is_zero() {
+        return Bytes::default();
+    }
+    let (offset, length) = (offset.low_u64() as usize, length.low_u64() as usize);
+    let memory = interp.memory.data();
+    let mut data = vec![0; length];
+    if offset < memory.len() {
+        let end = memory.len().min(offset + length);
+        data[..end - offset].copy_from_slice(&memory[offset..end]);
+    }
+    data.into()
+}
+
+fn peek(interp: &Interpreter, index: usize) -> U256 {
+    interp
+        .stack
+        .peek(index)
+        .map(from_revm_u256)
+        .unwrap_or_default()
+}
+
+fn code_size<DB: Database>(data: &mut EVMData<'_, DB>, address: B160) -> u64 {
+    // accounts created (or changed) during the execution are in the journaled state
+    if let Some(account) = data.journaled_state.state.get(&address) {
+        if let Some(code) = &account.info.code {
+            return code.len() as u64;
+        }
+    }
+    match data.db.basic(address) {
+        Ok(Some(info)) => match info.code {
+            Some(code) => code.len() as u64,
+            None => data
+                .db
+                .code_by_hash(info.code_hash)
+                .map(|code| code.len() as u64)
+                .unwrap_or_default(),
+        },
+        _ => 0,
+    }
+}
+
+fn call_type(scheme: CallScheme) -> &'static str {
+    match scheme {
+        CallScheme::Call => "CALL",
+        CallScheme::CallCode => "CALLCODE",
+        CallScheme::DelegateCall => "DELEGATECALL",
+        CallScheme::StaticCall => "STATICCALL",
+    }
+}
+
+fn is_success(result: InstructionResult) -> bool {
+    matches!(
+        result,
+        InstructionResult::Continue
+            | InstructionResult::Stop
+            | InstructionResult::Return
+            | InstructionResult::SelfDestruct
+    )
+}
+
+fn is_out_of_gas(result: InstructionResult) -> bool {
+    matches!(
+        result,
+        InstructionResult::OutOfGas
+            | InstructionResult::MemoryOOG
+            | InstructionResult::MemoryLimitOOG
+            | InstructionResult::PrecompileOOG
+            | InstructionResult::InvalidOperandOOG
+    )
+}
+
+impl<'a> JsTracerInspector<'a> {
+    pub fn new(frame: &'a mut JsTracerFrame) -> Self {
+        Self {
+            frame,
+            depth: 0,
+            number_counter: 0,
+            last_op: String::new(),
+            create_entries: vec![],
+        }
+    }
+
+    fn current_level(&mut self) -> &mut Level {
+        if self.frame.number_levels.is_empty() {
+            self.frame.number_levels.push(Level::default());
+        }
+        self.frame
+            .number_levels
+            .last_mut()
+            .expect("number levels are not empty")
+    }
+
+    fn count_opcode(&mut self, opcode: &str) {
+        *self
+            .current_level()
+            .opcodes
+            .entry(opcode.to_string())
+            .or_insert(0) += 1;
+    }
+
+    fn exit(&mut self, result: InstructionResult, gas_used: u64, output: &rBytes) {
+        // the JS tracer marks the level when the gas left is lower than the cost of an opcode
+        if is_out_of_gas(result) {
+            self.current_level().oog = true;
+        }
+        if !is_success(result) {
+            self.frame.debug.push(format!(
+                "fault depth={} gas_used={gas_used} err=execution reverted",
+                self.depth
+            ));
+        }
+        self.frame.calls.push(Call {
+            typ: if is_success(result) {
+                "RETURN".to_string()
+            } else {
+                "REVERT".to_string()
+            },
+            gas_used: Some(gas_used),
+            data: Some(output.clone().into()),
+            ..Default::default()
+        });
+    }
+
+    fn step_top_level(&mut self, interp: &Interpreter, opcode: &str) {
+        // exit isn't called on the top-level return/revert, so it is reconstructed from the opcode
+        if opcode == "REVERT" || opcode == "RETURN" {
+            let data = memory_slice(interp, peek(interp, 0), peek(interp, 1));
+            self.frame.debug.push(format!("{opcode} {data}"));
+            self.frame.calls.push(Call {
+                typ: opcode.to_string(),
+                gas_used: Some(0),
+                data: Some(data),
+                ..Default::default()
+            });
+        }
+
+        // NUMBER opcode at top level splits levels
+        if opcode == "NUMBER" {
+            self.number_counter += 1;
+        }
+        while self.frame.number_levels.len() <= self.number_counter {
+            self.frame.number_levels.push(Level::default());
+        }
+        self.last_op.clear();
+    }
+
+    fn step_inner<DB: Database>(
+        &mut self,
+        interp: &Interpreter,
+        data: &mut EVMData<'_, DB>,
+        opcode: &str,
+    ) {
+        if self.last_op == "GAS" && !opcode.contains("CALL") {
+            // count GAS opcode only if not followed by CALL
+            self.count_opcode("GAS");
+        }
+        if opcode != "GAS" && !ignored_opcode(opcode) {
+            self.count_opcode(opcode);
+        }
+
+        if let Some(index) = code_size_address_index(opcode) {
+            let mut address_bytes = [0u8; 32];
+            peek(interp, index).to_big_endian(&mut address_bytes);
+            let address = Address::from_slice(&address_bytes[12..]);
+            if !self.current_level().contract_size.contains_key(&address) {
+                let size = code_size(data, B160(address.0));
+                self.current_level().contract_size.insert(address, size);
+            }
+        }
+        self.last_op = opcode.to_string();
+
+        match opcode {
+            "SLOAD" | "SSTORE" => {
+                let slot = format!("{:x}", peek(interp, 0));
+                let address = Address::from(interp.contract.address.0);
+                let access = self.current_level().access.entry(address).or_default();
+                let slots = if opcode == "SLOAD" {
+                    &mut access.reads
+                } else {
+                    &mut access.writes
+                };
+                *slots.entry(slot).or_insert(0) += 1;
+            }
+            "KECCAK256" => {
+                // only the keccak inputs that could be mapping keys are collected
+                let length = peek(interp, 1);
+                if length > U256::from(20) && length < U256::from(512) {
+                    let input = memory_slice(interp, peek(interp, 0), length);
+                    self.frame.keccak.push(input);
+                }
+            }
+            _ if opcode.starts_with("LOG") => {
+                let count: usize = opcode[3..].parse().unwrap_or_default();
+                let topics = (0..count)
+                    .map(|index| format!("0x{:x}", peek(interp, 2 + index)))
+                    .collect();
+                let data = memory_slice(interp, peek(interp, 0), peek(interp, 1));
+                self.frame.logs.push(Log { topics, data });
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<'a, DB: Database> Inspector<DB> for JsTracerInspector<'a> {
+    fn step(
+        &mut self,
+        interp: &mut Interpreter,
+        data: &mut EVMData<'_, DB>,
+        _is_static: bool,
+    ) -> InstructionResult {
+        let opcode = opcode_name(interp.current_opcode());
+        if self.depth <= 1 {
+            self.step_top_level(interp, &opcode);
+        } else {
+            self.step_inner(interp, data, &opcode);
+        }
+
+        InstructionResult::Continue
+    }
+
+    fn call(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+        _is_static: bool,
+    ) -> (InstructionResult, Gas, rBytes) {
+        self.depth += 1;
+        // enter isn't called for the top-level call
+        if self.depth > 1 {
+            let to = Address::from(inputs.contract.0);
+            self.frame.debug.push(format!(
+                "enter gas={} type={} to={to:?}",
+                inputs.gas_limit,
+                call_type(inputs.context.scheme)
+            ));
+            self.frame.calls.push(Call {
+                typ: call_type(inputs.context.scheme).to_string(),
+                from: Some(Address::from(inputs.transfer.source.0)),
+                to: Some(to),
+                method: Some(
+                    inputs
+                        .input
+                        .iter()
+                        .take(4)
+                        .cloned()
+                        .collect::<Vec<u8>>()
+                        .into(),
+                ),
+                gas: Some(inputs.gas_limit),
+                value: Some(from_revm_u256(inputs.transfer.value)),
+                ..Default::default()
+            });
+        }
+
+        (InstructionResult::Continue, Gas::new(0), rBytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: rBytes,
+        _is_static: bool,
+    ) -> (InstructionResult, Gas, rBytes) {
+        if self.depth > 1 {
+            let gas_used = inputs.gas_limit.saturating_sub(remaining_gas.remaining());
+            self.exit(ret, gas_used, &out);
+        }
+        self.depth = self.depth.saturating_sub(1);
+
+        (ret, remaining_gas, out)
+    }
+
+    fn create(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<B160>, Gas, rBytes) {
+        self.depth += 1;
+        let typ = match inputs.scheme {
+            CreateScheme::Create => "CREATE",
+            CreateScheme::Create2 { .. } => "CREATE2",
+        };
+        self.create_entries.push(self.frame.calls.len());
+        self.frame.calls.push(Call {
+            typ: typ.to_string(),
+            from: Some(Address::from(inputs.caller.0)),
+            method: Some(
+                inputs
+                    .init_code
+                    .iter()
+                    .take(4)
+                    .cloned()
+                    .collect::<Vec<u8>>()
+                    .into(),
+            ),
+            gas: Some(inputs.gas_limit),
+            value: Some(from_revm_u256(inputs.value)),
+            ..Default::default()
+        });
+
+        (
+            InstructionResult::Continue,
+            None,
+            Gas::new(0),
+            rBytes::new(),
+        )
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<B160>,
+        remaining_gas: Gas,
+        out: rBytes,
+    ) -> (InstructionResult, Option<B160>, Gas, rBytes) {
+        if let Some(index) = self.create_entries.pop() {
+            self.frame.calls[index].to = address.map(|address| Address::from(address.0));
+        }
+        let gas_used = inputs.gas_limit.saturating_sub(remaining_gas.remaining());
+        self.exit(ret, gas_used, &out);
+        self.depth = self.depth.saturating_sub(1);
+
+        (ret, address, remaining_gas, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcode_names() {
+        assert_eq!(opcode_name(0x20), "KECCAK256");
+        assert_eq!(opcode_name(0x42), "TIMESTAMP");
+        assert_eq!(opcode_name(0xf1), "CALL");
+        assert!(ignored_opcode("PUSH1"));
+        assert!(ignored_opcode("ISZERO"));
+        assert!(!ignored_opcode("SLOAD"));
+        assert!(!ignored_opcode("GAS"));
+    }
+
+    #[test]
+    fn code_size_addresses() {
+        assert_eq!(code_size_address_index("EXTCODESIZE"), Some(0));
+        assert_eq!(code_size_address_index("CALL"), Some(1));
+        assert_eq!(code_size_address_index("STATICCALL"), Some(1));
+        assert_eq!(code_size_address_index("CREATE2"), None);
+        assert_eq!(code_size_address_index("SLOAD"), None);
+    }
+}
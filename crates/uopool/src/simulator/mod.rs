@@ -0,0 +1,189 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use aa_bundler_contracts::{JsTracerFrame, ValidationTracer};
+use anyhow::format_err;
+use ethers::{
+    providers::{call_raw::spoof, Middleware},
+    types::{Address, BlockNumber, Bytes, U256},
+};
+use revm::{
+    db::CacheDB,
+    primitives::{Bytecode, ExecutionResult, TransactTo, B160, B256, U256 as rU256},
+    Database, EVM,
+};
+use tokio::runtime::Handle;
+
+use self::{db::ProviderDb, inspector::JsTracerInspector};
+
+mod db;
+mod inspector;
+
+// How the validation of user operations is traced
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Simulator {
+    // debug_traceCall with the JS tracer on the execution client
+    #[default]
+    JsTracer,
+    // debug_traceCall with the native bundlerCollectorTracer (ERC-4337 enabled geth forks)
+    NativeTracer,
+    // in-process revm execution with state fetched from the execution client
+    Revm,
+}
+
+impl FromStr for Simulator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "js-tracer" => Ok(Self::JsTracer),
+            "native-tracer" => Ok(Self::NativeTracer),
+            "revm" => Ok(Self::Revm),
+            _ => Err(format!(
+                "{s} is not a valid simulator (js-tracer, native-tracer, revm)"
+            )),
+        }
+    }
+}
+
+impl Simulator {
+    // tracer of debug_traceCall, none if the validation isn't traced by the execution client
+    pub fn validation_tracer(&self) -> Option<ValidationTracer> {
+        match self {
+            Self::JsTracer => Some(ValidationTracer::Js),
+            Self::NativeTracer => Some(ValidationTracer::BundlerCollector),
+            Self::Revm => None,
+        }
+    }
+}
+
+pub(crate) fn to_revm_u256(value: U256) -> rU256 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    rU256::from_be_bytes(bytes)
+}
+
+pub(crate) fn from_revm_u256(value: rU256) -> U256 {
+    U256::from_big_endian(&value.to_be_bytes::<32>())
+}
+
+fn apply_state_override<M: Middleware + 'static>(
+    db: &mut CacheDB<ProviderDb<M>>,
+    state_override: &spoof::State,
+) -> anyhow::Result<()> {
+    // the accounts of the state override set aren't accessible, so it is converted through JSON
+    let accounts: HashMap<Address, spoof::Account> =
+        serde_json::from_value(serde_json::to_value(state_override)?)?;
+
+    for (address, account) in accounts {
+        let address = B160(address.0);
+        let mut info = db.basic(address)?.unwrap_or_default();
+        if let Some(balance) = account.balance {
+            info.balance = to_revm_u256(balance);
+        }
+        if let Some(nonce) = account.nonce {
+            info.nonce = nonce.as_u64();
+        }
+        if let Some(code) = account.code {
+            let code = Bytecode::new_raw(code.0);
+            info.code_hash = code.hash();
+            info.code = Some(code);
+        }
+        db.insert_account_info(address, info);
+
+        match account.storage {
+            Some(spoof::Storage::Diff(storage)) => {
+                for (slot, value) in storage {
+                    db.insert_account_storage(
+                        address,
+                        rU256::from_be_bytes(slot.0),
+                        rU256::from_be_bytes(value.0),
+                    )?;
+                }
+            }
+            Some(spoof::Storage::Replace(storage)) => {
+                db.replace_account_storage(
+                    address,
+                    storage
+                        .into_iter()
+                        .map(|(slot, value)| {
+                            (rU256::from_be_bytes(slot.0), rU256::from_be_bytes(value.0))
+                        })
+                        .collect(),
+                )?;
+            }
+            None => (),
+        }
+    }
+
+    Ok(())
+}
+
+// Executes the call on the latest block with revm and collects the same trace as the JS tracer
+// (debug_traceCall). Like eth_call, the call is sent from the zero address without gas price.
+pub async fn trace_call<M: Middleware + 'static>(
+    provider: Arc<M>,
+    to: Address,
+    data: Bytes,
+    state_override: &spoof::State,
+) -> anyhow::Result<JsTracerFrame> {
+    let block = provider
+        .get_block(BlockNumber::Latest)
+        .await?
+        .ok_or_else(|| format_err!("Latest block not found"))?;
+    let block_number = block
+        .number
+        .ok_or_else(|| format_err!("Latest block number not found"))?;
+    let chain_id = provider.get_chainid().await?;
+
+    let mut db = CacheDB::new(ProviderDb::new(provider, block_number, Handle::current()));
+    let state_override = state_override.clone();
+
+    tokio::task::spawn_blocking(move || {
+        apply_state_override(&mut db, &state_override)?;
+
+        let mut evm = EVM::new();
+        evm.database(db);
+
+        evm.env.cfg.chain_id = to_revm_u256(chain_id);
+        evm.env.block.number = rU256::from(block_number.as_u64());
+        evm.env.block.timestamp = to_revm_u256(block.timestamp);
+        evm.env.block.coinbase = B160(block.author.unwrap_or_default().0);
+        evm.env.block.difficulty = to_revm_u256(block.difficulty);
+        evm.env.block.prevrandao = block.mix_hash.map(|mix_hash| B256(mix_hash.0));
+        evm.env.block.gas_limit = to_revm_u256(block.gas_limit);
+        evm.env.block.basefee = rU256::ZERO;
+
+        evm.env.tx.caller = B160::zero();
+        evm.env.tx.transact_to = TransactTo::Call(B160(to.0));
+        evm.env.tx.data = data.0;
+        evm.env.tx.gas_limit = block.gas_limit.as_u64();
+        evm.env.tx.gas_price = rU256::ZERO;
+
+        let mut frame = JsTracerFrame::default();
+        let result = evm
+            .inspect(JsTracerInspector::new(&mut frame))
+            .map_err(|error| format_err!("Revm execution error: {error:?}"))?;
+        if let ExecutionResult::Halt { reason, .. } = result.result {
+            return Err(format_err!("Revm execution halted: {reason:?}"));
+        }
+
+        Ok(frame)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulator_from_str() {
+        assert_eq!("revm".parse(), Ok(Simulator::Revm));
+        assert_eq!("js-tracer".parse(), Ok(Simulator::JsTracer));
+        assert_eq!("native-tracer".parse(), Ok(Simulator::NativeTracer));
+        assert!("geth".parse::<Simulator>().is_err());
+
+        let value = U256::from_dec_str("123456789012345678901234567890").unwrap();
+        assert_eq!(from_revm_u256(to_revm_u256(value)), value);
+    }
+}
@@ -1,19 +1,23 @@
 use std::sync::Arc;
 
-use aa_bundler_contracts::{EntryPoint, UserOperationEventFilter};
+use aa_bundler_contracts::{EntryPoint, L1GasOracle, UserOperationEventFilter};
 use aa_bundler_primitives::{CodeHash, ReputationEntry, UserOperation, UserOperationHash};
 use ethers::{
     prelude::LogMeta,
     providers::Middleware,
-    types::{Address, H256, U256},
+    types::{Address, H256, U256, U64},
 };
 use jsonrpsee::types::ErrorObject;
 use tracing::warn;
 
 use crate::{
     canonical::{sanity_check::SanityCheckResult, simulation::SimulationResult},
+    estimation::DEFAULT_VERIFICATION_GAS_MARGIN,
     mempool::MempoolBox,
     reputation::ReputationBox,
+    rules::RuleSet,
+    simulator::Simulator,
+    utils::Overhead,
 };
 
 type VecUo = Vec<UserOperation>;
@@ -33,6 +37,13 @@ pub struct UoPool<M: Middleware> {
     pub max_verification_gas: U256,
     pub min_priority_fee_per_gas: U256,
     pub chain_id: U256,
+    pub l1_gas_oracle: L1GasOracle,
+    pub gas_overhead: Overhead,
+    // safety margin (in percent) added to the estimated verification gas limit
+    pub verification_gas_margin: u64,
+    pub simulator: Simulator,
+    // validation rules of the mempool (canonical by default)
+    pub rules: RuleSet,
 }
 
 impl<M: Middleware + 'static> UoPool<M> {
@@ -53,9 +64,34 @@ impl<M: Middleware + 'static> UoPool<M> {
             max_verification_gas,
             min_priority_fee_per_gas,
             chain_id,
+            l1_gas_oracle: L1GasOracle::from_chain_id(chain_id),
+            gas_overhead: Overhead::default(),
+            verification_gas_margin: DEFAULT_VERIFICATION_GAS_MARGIN,
+            simulator: Simulator::default(),
+            rules: RuleSet::default(),
         }
     }
 
+    // calldata cost of the user operation, plus the L1 data cost on rollups
+    pub async fn calculate_pre_verification_gas(
+        &self,
+        user_operation: &UserOperation,
+    ) -> anyhow::Result<U256> {
+        let l1_gas = self
+            .l1_gas_oracle
+            .l1_gas(
+                self.eth_provider.clone(),
+                self.entry_point.address(),
+                user_operation,
+            )
+            .await?;
+
+        Ok(self
+            .gas_overhead
+            .calculate_pre_verification_gas(user_operation)
+            .saturating_add(l1_gas))
+    }
+
     pub async fn verify_user_operation(
         &self,
         user_operation: &UserOperation,
@@ -64,7 +100,9 @@ impl<M: Middleware + 'static> UoPool<M> {
         let sanity_check_result = self.validate_user_operation(user_operation).await?;
 
         // simulation
-        let simulation_result = self.simulate_user_operation(user_operation).await?;
+        let simulation_result = self
+            .simulate_user_operation(user_operation, &Default::default())
+            .await?;
 
         Ok(VerificationResult {
             sanity_check_result,
@@ -72,15 +110,18 @@ impl<M: Middleware + 'static> UoPool<M> {
         })
     }
 
+    // logs are only queried from the given block on, because providers limit the block range
     pub async fn get_user_operation_event_meta(
         &self,
         user_operation_hash: H256,
+        from_block: U64,
     ) -> anyhow::Result<Option<(UserOperationEventFilter, LogMeta)>> {
         let mut event: Option<(UserOperationEventFilter, LogMeta)> = None;
         let filter = self
             .entry_point
             .entry_point_api()
             .event::<UserOperationEventFilter>()
+            .from_block(from_block)
             .topic1(user_operation_hash);
         let res: Vec<(UserOperationEventFilter, LogMeta)> = filter.query_with_meta().await?;
         if res.len() >= 2 {
@@ -1,39 +1,76 @@
-use aa_bundler_primitives::{CodeHash, UserOperation};
+use aa_bundler_contracts::{ARBITRUM_CHAIN_IDS, OPTIMISM_CHAIN_IDS};
+use aa_bundler_primitives::{CodeHash, CodeHashChange, CodeHashChangeKind, UserOperation};
+use anyhow::format_err;
 use ethers::types::{u256_from_f64_saturating, Address, H256, U256};
 use lazy_static::__Deref;
-use std::collections::HashMap;
+use serde::{de, Deserialize, Deserializer};
+use std::{collections::HashMap, fs, path::Path};
 
-pub fn equal_code_hashes(code_hashes: &Vec<CodeHash>, prev_code_hashes: &Vec<CodeHash>) -> bool {
-    if prev_code_hashes.len() != code_hashes.len() {
-        return false;
-    }
-
-    let code_hashes_map = code_hashes
+// Per-address differences of the code hashes from the previous simulation, sorted by address
+pub fn code_hashes_diff(
+    code_hashes: &[CodeHash],
+    prev_code_hashes: &[CodeHash],
+) -> Vec<CodeHashChange> {
+    let current = code_hashes
+        .iter()
+        .map(|code_hash| (code_hash.address, code_hash.hash))
+        .collect::<HashMap<Address, H256>>();
+    let previous = prev_code_hashes
         .iter()
         .map(|code_hash| (code_hash.address, code_hash.hash))
         .collect::<HashMap<Address, H256>>();
 
-    for code_hash in prev_code_hashes {
-        if let Some(hash) = code_hashes_map.get(&code_hash.address) {
-            if hash != &code_hash.hash {
-                return false;
-            }
-        } else {
-            return false;
-        }
-    }
+    let mut changes: Vec<CodeHashChange> = current
+        .iter()
+        .filter_map(|(address, hash)| match previous.get(address) {
+            None => Some(CodeHashChange {
+                address: *address,
+                change: CodeHashChangeKind::Added,
+                previous: None,
+                current: Some(*hash),
+            }),
+            Some(prev_hash) if prev_hash != hash => Some(CodeHashChange {
+                address: *address,
+                change: CodeHashChangeKind::Changed,
+                previous: Some(*prev_hash),
+                current: Some(*hash),
+            }),
+            Some(_) => None,
+        })
+        .chain(
+            previous
+                .iter()
+                .filter(|(address, _)| !current.contains_key(*address))
+                .map(|(address, prev_hash)| CodeHashChange {
+                    address: *address,
+                    change: CodeHashChangeKind::Removed,
+                    previous: Some(*prev_hash),
+                    current: None,
+                }),
+        )
+        .collect();
+    changes.sort_by_key(|change| change.address);
 
-    true
+    changes
 }
 
 // https://github.com/eth-infinitism/bundler/blob/main/packages/sdk/src/calcPreVerificationGas.ts#L44-L52
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
 pub struct Overhead {
+    #[serde(deserialize_with = "deserialize_u256")]
     pub fixed: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
     pub per_user_op: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
     pub per_user_op_word: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
     pub zero_byte: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
     pub non_zero_byte: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
     pub bundle_size: U256,
+    #[serde(deserialize_with = "deserialize_u256")]
     pub sig_size: U256,
 }
 
@@ -51,7 +88,58 @@ impl Default for Overhead {
     }
 }
 
+// accepts numbers, decimal strings and hex strings (0x prefixed)
+fn deserialize_u256<'de, D>(deserializer: D) -> Result<U256, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(u64),
+        String(String),
+    }
+
+    match Value::deserialize(deserializer)? {
+        Value::Number(value) => Ok(U256::from(value)),
+        Value::String(value) => match value.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok(),
+            None => U256::from_dec_str(&value).ok(),
+        }
+        .ok_or_else(|| de::Error::custom(format!("{value} is not a valid U256"))),
+    }
+}
+
 impl Overhead {
+    // Built-in presets by chain id, other chains use the default values
+    pub fn from_chain_id(chain_id: U256) -> Self {
+        let chain_id = chain_id.as_u64();
+        // Optimism and Arbitrum chains, same as the L1 gas oracle presets
+        // (cheap L2 execution, bundles carry more user operations)
+        if OPTIMISM_CHAIN_IDS.contains(&chain_id) || ARBITRUM_CHAIN_IDS.contains(&chain_id) {
+            Self {
+                bundle_size: U256::from(5),
+                ..Default::default()
+            }
+        } else {
+            // Ethereum mainnet and other chains
+            Self::default()
+        }
+    }
+
+    // Presets by chain id, the config file is a JSON object with chain ids as keys, e.g.
+    // {"1": {"fixed": 21000, "bundle_size": 1}, "10": {"bundle_size": 5}}
+    // Missing chains fall back to the built-in presets and missing fields to the default values.
+    pub fn from_config_file(path: &Path, chain_id: U256) -> anyhow::Result<Self> {
+        let presets: HashMap<String, Overhead> =
+            serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|error| format_err!("Failed to parse overhead config file: {error}"))?;
+        Ok(presets
+            .get(&chain_id.to_string())
+            .copied()
+            .unwrap_or_else(|| Self::from_chain_id(chain_id)))
+    }
+
     pub fn calculate_pre_verification_gas(&self, user_operation: &UserOperation) -> U256 {
         let user_operation_packed = user_operation.pack();
         let call_data_cost: U256 = U256::from(
@@ -116,9 +204,104 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn code_hashes_changes() {
+        let code_hash = |address: u64, hash: u64| CodeHash {
+            address: Address::from_low_u64_be(address),
+            hash: H256::from_low_u64_be(hash),
+        };
+        let prev_code_hashes = vec![code_hash(1, 1), code_hash(2, 2), code_hash(3, 3)];
+
+        assert!(code_hashes_diff(&prev_code_hashes, &prev_code_hashes).is_empty());
+        assert_eq!(
+            code_hashes_diff(
+                &[code_hash(4, 4), code_hash(3, 3), code_hash(1, 5)],
+                &prev_code_hashes
+            ),
+            vec![
+                CodeHashChange {
+                    address: Address::from_low_u64_be(1),
+                    change: CodeHashChangeKind::Changed,
+                    previous: Some(H256::from_low_u64_be(1)),
+                    current: Some(H256::from_low_u64_be(5)),
+                },
+                CodeHashChange {
+                    address: Address::from_low_u64_be(2),
+                    change: CodeHashChangeKind::Removed,
+                    previous: Some(H256::from_low_u64_be(2)),
+                    current: None,
+                },
+                CodeHashChange {
+                    address: Address::from_low_u64_be(4),
+                    change: CodeHashChangeKind::Added,
+                    previous: None,
+                    current: Some(H256::from_low_u64_be(4)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn overhead_config_file() {
+        let dir = tempdir::TempDir::new("test-overhead-config").unwrap();
+        let path = dir.path().join("overhead.json");
+        std::fs::write(
+            &path,
+            r#"{"1": {"fixed": "0x5208", "bundle_size": 5}, "10": {"non_zero_byte": "12"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Overhead::from_config_file(&path, U256::from(1)).unwrap(),
+            Overhead {
+                bundle_size: U256::from(5),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Overhead::from_config_file(&path, U256::from(10))
+                .unwrap()
+                .non_zero_byte,
+            U256::from(12)
+        );
+        assert_eq!(
+            Overhead::from_config_file(&path, U256::from(5)).unwrap(),
+            Overhead::default()
+        );
+        assert_eq!(
+            Overhead::from_config_file(&path, U256::from(42161)).unwrap(),
+            Overhead::from_chain_id(U256::from(42161))
+        );
+    }
+
+    #[test]
+    fn overhead_presets() {
+        assert_eq!(Overhead::from_chain_id(U256::from(1)), Overhead::default());
+        assert_eq!(
+            Overhead::from_chain_id(U256::from(10)).bundle_size,
+            U256::from(5)
+        );
+        assert_eq!(
+            Overhead::from_chain_id(U256::from(42161)).bundle_size,
+            U256::from(5)
+        );
+        assert_eq!(
+            Overhead::from_chain_id(U256::from(8453)).bundle_size,
+            U256::from(5)
+        );
+        assert_eq!(
+            Overhead::from_chain_id(U256::from(421614)).bundle_size,
+            U256::from(5)
+        );
+        assert_eq!(
+            Overhead::from_chain_id(U256::from(1337)),
+            Overhead::default()
+        );
+    }
+
     pub fn mempool_test_case<T>(mut mempool: T, not_found_error_message: &str)
     where
-        T: Mempool<UserOperations = Vec<UserOperation>> + Debug,
+        T: Mempool<UserOperations = Vec<UserOperation>, CodeHashes = Vec<CodeHash>> + Debug,
         T::Error: Debug + ToString,
     {
         let entry_point = Address::random();
@@ -193,8 +376,65 @@ pub mod tests {
         assert_eq!(mempool.get_all_by_sender(&senders[0]).len(), 2);
         assert_eq!(mempool.get_all_by_sender(&senders[2]).len(), 2);
 
+        // code hashes are removed with their user operation
+        let code_hashes = vec![CodeHash {
+            address: Address::random(),
+            hash: H256::random(),
+        }];
+        user_operation_hash = mempool
+            .add(UserOperation::random(), &entry_point, &chain_id)
+            .unwrap();
+        mempool
+            .set_code_hashes(&user_operation_hash, &code_hashes)
+            .unwrap();
+        assert!(mempool.has_code_hashes(&user_operation_hash).unwrap());
+        assert_eq!(mempool.get_code_hashes(&user_operation_hash), code_hashes);
+        mempool.remove(&user_operation_hash).unwrap();
+        assert!(!mempool.has_code_hashes(&user_operation_hash).unwrap());
+        assert!(mempool.get_code_hashes(&user_operation_hash).is_empty());
+        assert!(mempool
+            .set_code_hashes(&user_operation_hash, &code_hashes)
+            .is_err());
+
+        // the user operation is stored together with its code hashes
+        user_operation = UserOperation::random();
+        user_operation_hash = mempool
+            .add_with_code_hashes(
+                user_operation.clone(),
+                &entry_point,
+                &chain_id,
+                &code_hashes,
+            )
+            .unwrap();
+        assert_eq!(
+            mempool.get(&user_operation_hash).unwrap().unwrap(),
+            user_operation
+        );
+        assert_eq!(mempool.get_code_hashes(&user_operation_hash), code_hashes);
+        assert_eq!(mempool.get_all_by_sender(&user_operation.sender).len(), 1);
+
+        // adding the user operation again replaces its code hashes
+        let new_code_hashes = vec![CodeHash {
+            address: Address::random(),
+            hash: H256::random(),
+        }];
+        mempool
+            .add_with_code_hashes(
+                user_operation.clone(),
+                &entry_point,
+                &chain_id,
+                &new_code_hashes,
+            )
+            .unwrap();
+        assert_eq!(
+            mempool.get_code_hashes(&user_operation_hash),
+            new_code_hashes
+        );
+
         assert_eq!(mempool.clear(), ());
 
+        assert!(!mempool.has_code_hashes(&user_operation_hash).unwrap());
+
         assert_eq!(mempool.get_all().len(), 0);
         assert_eq!(mempool.get_all_by_sender(&senders[0]).len(), 0);
 
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use aa_bundler_contracts::EntryPoint;
+use aa_bundler_primitives::UserOperation;
+use aa_bundler_uopool::{MemoryMempool, MemoryReputation, UoPool};
+use ethers::{
+    providers::{call_raw::spoof, Middleware},
+    types::{Address, Bytes, U256},
+    utils::{parse_units, GethInstance},
+};
+
+use crate::common::{
+    deploy_entry_point, deploy_test_opcode_account,
+    gen::{EntryPointContract, TestOpcodesAccount},
+    setup_geth, ClientType, DeployedContract,
+};
+
+// upper bound of the call gas limit search
+const MAX_CALL_GAS_LIMIT: u64 = 10_000_000;
+
+struct Context<M> {
+    _geth: GethInstance,
+    client: Arc<M>,
+    entry_point: DeployedContract<EntryPointContract<M>>,
+    account: DeployedContract<TestOpcodesAccount<M>>,
+}
+
+async fn setup() -> anyhow::Result<Context<ClientType>> {
+    let (_geth, _client) = setup_geth().await?;
+    let client = Arc::new(_client);
+    let entry_point = deploy_entry_point(client.clone()).await?;
+    let account = deploy_test_opcode_account(client.clone()).await?;
+    entry_point
+        .contract()
+        .deposit_to(account.address)
+        .value(parse_units("1", "ether").unwrap())
+        .send()
+        .await?;
+
+    Ok(Context {
+        _geth,
+        client,
+        entry_point,
+        account,
+    })
+}
+
+async fn paid(
+    entry_point: &EntryPoint<ClientType>,
+    user_operation: &UserOperation,
+    call_gas_limit: U256,
+) -> anyhow::Result<U256> {
+    let execution_result = entry_point
+        .simulate_handle_op(
+            UserOperation {
+                call_gas_limit,
+                ..user_operation.clone()
+            },
+            Address::zero(),
+            Bytes::default(),
+            &spoof::State::default(),
+        )
+        .await
+        .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+    Ok(execution_result.paid)
+}
+
+#[tokio::test]
+async fn estimate_call_gas_limit() -> anyhow::Result<()> {
+    let context = setup().await?;
+    let entry_point = EntryPoint::new(context.client.clone(), context.entry_point.address);
+    let uopool = UoPool::new(
+        EntryPoint::new(context.client.clone(), context.entry_point.address),
+        Box::<MemoryMempool>::default(),
+        Box::<MemoryReputation>::default(),
+        context.client.clone(),
+        U256::from(3000000),
+        U256::zero(),
+        context.client.get_chainid().await?,
+    );
+
+    // the account emits an event, the gas price is 1 wei so the paid amount is the gas used
+    let user_operation = UserOperation {
+        sender: context.account.address,
+        nonce: U256::zero(),
+        init_code: Bytes::default(),
+        call_data: context
+            .account
+            .contract()
+            .exec_send_message()
+            .calldata()
+            .unwrap(),
+        call_gas_limit: U256::zero(),
+        verification_gas_limit: U256::from(1000000),
+        pre_verification_gas: U256::from(50000),
+        max_fee_per_gas: U256::one(),
+        max_priority_fee_per_gas: U256::one(),
+        paymaster_and_data: Bytes::default(),
+        signature: Bytes::default(),
+    };
+
+    let call_gas_limit = uopool
+        .estimate_call_gas_limit(&user_operation, &spoof::State::default())
+        .await
+        .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+
+    // the search ends well below the upper bound, with the same amount paid
+    assert!(!call_gas_limit.is_zero());
+    assert!(call_gas_limit < U256::from(MAX_CALL_GAS_LIMIT) / 10);
+    assert_eq!(
+        paid(&entry_point, &user_operation, call_gas_limit).await?,
+        paid(
+            &entry_point,
+            &user_operation,
+            U256::from(MAX_CALL_GAS_LIMIT)
+        )
+        .await?
+    );
+    Ok(())
+}
@@ -3,6 +3,8 @@
 #[cfg(test)]
 mod common;
 #[cfg(test)]
+mod estimation_tests;
+#[cfg(test)]
 mod tracer_tests;
 #[cfg(test)]
 mod validate_tests;
@@ -5,7 +5,7 @@ use ethers::{
     abi::{RawLog, Token},
     contract::EthLogDecode,
     prelude::BaseContract,
-    providers::Middleware,
+    providers::{call_raw::spoof, Middleware},
     types::{
         Bytes, GethDebugTracerType, GethDebugTracingCallOptions, GethDebugTracingOptions,
         TransactionRequest, H256,
@@ -203,3 +203,88 @@ async fn should_ignore_gas_used_as_part_of_call() -> anyhow::Result<()> {
     assert_eq!(ret.number_levels[0].opcodes.get("GAS"), None);
     Ok(())
 }
+
+// the revm simulator must produce the same trace as the JS tracer
+async fn assert_revm_trace_equal<M: Middleware + 'static>(
+    context: &Context<M>,
+    function_data: Vec<u8>,
+) -> anyhow::Result<()> {
+    let function_data = Bytes::from(function_data);
+    let js_trace = trace_call(context, function_data.clone()).await?;
+    let revm_trace = aa_bundler_uopool::trace_call(
+        context.client.clone(),
+        context.tracer_test.address,
+        function_data,
+        &spoof::State::default(),
+    )
+    .await?;
+
+    assert_eq!(revm_trace.number_levels.len(), js_trace.number_levels.len());
+    for (revm_level, js_level) in revm_trace
+        .number_levels
+        .iter()
+        .zip(js_trace.number_levels.iter())
+    {
+        assert_eq!(revm_level.opcodes, js_level.opcodes);
+        assert_eq!(revm_level.access, js_level.access);
+        assert_eq!(revm_level.contract_size, js_level.contract_size);
+    }
+    assert_eq!(revm_trace.keccak, js_trace.keccak);
+    assert_eq!(
+        revm_trace
+            .logs
+            .iter()
+            .map(|log| &log.data)
+            .collect::<Vec<_>>(),
+        js_trace
+            .logs
+            .iter()
+            .map(|log| &log.data)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        revm_trace
+            .calls
+            .iter()
+            .map(|call| call.typ.as_str())
+            .collect::<Vec<_>>(),
+        js_trace
+            .calls
+            .iter()
+            .map(|call| call.typ.as_str())
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn revm_trace_equal_to_js_tracer() -> anyhow::Result<()> {
+    let context = setup().await?;
+    let contract: &BaseContract = context.tracer_test.contract().deref().deref();
+    let exec_self = contract.abi().function("execSelf")?;
+
+    let call_timestamp = contract
+        .abi()
+        .function("callTimeStamp")?
+        .encode_input(&[])?;
+    assert_revm_trace_equal(&context, call_timestamp.clone()).await?;
+    assert_revm_trace_equal(
+        &context,
+        exec_self.encode_input(&[Token::Bytes(call_timestamp), Token::Bool(true)])?,
+    )
+    .await?;
+
+    let test_call_gas = contract.abi().function("testCallGas")?.encode_input(&[])?;
+    assert_revm_trace_equal(
+        &context,
+        exec_self.encode_input(&[Token::Bytes(test_call_gas), Token::Bool(false)])?,
+    )
+    .await?;
+
+    assert_revm_trace_equal(
+        &context,
+        exec_self.encode_input(&[Token::Bytes(vec![0xde, 0xad]), Token::Bool(true)])?,
+    )
+    .await?;
+    Ok(())
+}
@@ -1,6 +1,11 @@
-use aa_bundler_primitives::UserOperation;
+use aa_bundler_contracts::EntryPoint;
+use aa_bundler_primitives::{
+    SimulationError, UserOperation, BAN_SLACK, MIN_INCLUSION_RATE_DENOMINATOR, THROTTLING_SLACK,
+};
+use aa_bundler_uopool::{MemoryMempool, MemoryReputation, Reputation, UoPool};
 use ethers::abi::Token;
 use ethers::prelude::BaseContract;
+use ethers::providers::call_raw::spoof;
 use ethers::types::transaction::eip2718::TypedTransaction;
 use ethers::types::Address;
 use ethers::utils::{parse_units, GethInstance};
@@ -82,10 +87,10 @@ async fn setup() -> anyhow::Result<TestContext<ClientType>> {
 }
 
 async fn create_storage_factory_init_code(
+    context: &TestContext<ClientType>,
     salt: u64,
     init_func: String,
 ) -> anyhow::Result<(Bytes, Bytes)> {
-    let context = setup().await?;
     let contract: &BaseContract = context.storage_factory.contract().deref().deref();
 
     let function = contract.abi().function("create")?;
@@ -96,8 +101,10 @@ async fn create_storage_factory_init_code(
     init_code.extend_from_slice(init_func.as_ref());
     Ok((Bytes::from(init_code), Bytes::from(init_func)))
 }
-async fn create_opcode_factory_init_code(init_func: String) -> anyhow::Result<(Bytes, Bytes)> {
-    let context = setup().await?;
+async fn create_opcode_factory_init_code(
+    context: &TestContext<ClientType>,
+    init_func: String,
+) -> anyhow::Result<(Bytes, Bytes)> {
     let contract: &BaseContract = context.opcodes_factory.contract().deref().deref();
 
     let token = vec![Token::String(init_func)];
@@ -110,14 +117,13 @@ async fn create_opcode_factory_init_code(init_func: String) -> anyhow::Result<(B
 }
 
 async fn create_test_user_op(
+    context: &TestContext<ClientType>,
     validate_rule: String,
     pm_rule: Option<String>,
     init_code: Bytes,
     init_func: Bytes,
     factory_address: Address,
 ) -> anyhow::Result<UserOperation> {
-    let context = setup().await?;
-
     let paymaster_and_data = if let Some(rule) = pm_rule {
         let mut data = vec![];
         data.extend_from_slice(context.paymaster.address.as_bytes());
@@ -162,11 +168,10 @@ async fn create_test_user_op(
 }
 
 async fn existing_storage_account_user_op(
+    context: &TestContext<ClientType>,
     validate_rule: String,
     pm_rule: String,
 ) -> anyhow::Result<UserOperation> {
-    let context = setup().await?;
-
     let mut paymaster_and_data = vec![];
     paymaster_and_data.extend_from_slice(context.paymaster.address.as_bytes());
     paymaster_and_data.extend_from_slice(pm_rule.as_bytes());
@@ -187,12 +192,73 @@ async fn existing_storage_account_user_op(
     })
 }
 
-fn validate(_user_op: UserOperation) -> anyhow::Result<()> {
-    // TODO
-    Ok(())
+fn paymaster_context_user_op(sender: Address, paymaster: Address) -> UserOperation {
+    let mut paymaster_and_data = vec![];
+    paymaster_and_data.extend_from_slice(paymaster.as_bytes());
+    paymaster_and_data.extend_from_slice("postOp-context".as_bytes());
+
+    UserOperation {
+        sender,
+        nonce: U256::zero(),
+        init_code: Bytes::default(),
+        call_data: Bytes::default(),
+        call_gas_limit: U256::from(0),
+        verification_gas_limit: U256::from(50000),
+        pre_verification_gas: U256::from(0),
+        max_fee_per_gas: U256::from(0),
+        max_priority_fee_per_gas: U256::from(0),
+        paymaster_and_data: Bytes::from(paymaster_and_data),
+        signature: Bytes::default(),
+    }
+}
+
+// Simulates the user operation like the mempool does, entities with 0.1 ether of stake are staked.
+// Errors of the validation rules start with the ERC-7562 rule id, e.g. [STO-021].
+async fn validate(context: &TestContext<ClientType>, user_op: UserOperation) -> anyhow::Result<()> {
+    let mut reputation = Box::<MemoryReputation>::default();
+    reputation.init(
+        MIN_INCLUSION_RATE_DENOMINATOR,
+        THROTTLING_SLACK,
+        BAN_SLACK,
+        U256::from(parse_units("0.1", "ether")?),
+        U256::zero(),
+    );
+
+    let uopool = UoPool::new(
+        EntryPoint::new(context.client.clone(), context.entry_point.address),
+        Box::<MemoryMempool>::default(),
+        reputation,
+        context.client.clone(),
+        U256::from(3000000),
+        U256::zero(),
+        context.client.get_chainid().await?,
+    );
+
+    match uopool
+        .simulate_user_operation(&user_op, &spoof::State::default())
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            let rule_id = error.rule_id().map(str::to_string);
+            let message = SimulationError::from(error).message().to_string();
+            match rule_id {
+                Some(rule_id) => Err(anyhow::anyhow!("[{rule_id}] {message}")),
+                None => Err(anyhow::anyhow!(message)),
+            }
+        }
+    }
+}
+
+fn assert_rule(error: anyhow::Error, rule_id: &str) {
+    assert!(
+        error.to_string().starts_with(&format!("[{rule_id}]")),
+        "expected violation of {rule_id}: {error}"
+    );
 }
 
 async fn test_user_op(
+    context: &TestContext<ClientType>,
     validate_rule: String,
     pm_rule: Option<String>,
     init_code: Bytes,
@@ -200,6 +266,7 @@ async fn test_user_op(
     factory_address: Address,
 ) -> anyhow::Result<()> {
     let user_op = create_test_user_op(
+        context,
         validate_rule,
         pm_rule,
         init_code,
@@ -207,22 +274,24 @@ async fn test_user_op(
         factory_address,
     )
     .await?;
-    validate(user_op)
+    validate(context, user_op).await
 }
 
 async fn test_existing_user_op(validate_rule: String, pm_rule: String) -> anyhow::Result<()> {
-    let user_op = existing_storage_account_user_op(validate_rule, pm_rule).await?;
-    validate(user_op)
+    let context = setup().await?;
+    let user_op = existing_storage_account_user_op(&context, validate_rule, pm_rule).await?;
+    validate(&context, user_op).await
 }
 
+// The opcodes factory deploys the account with CREATE2
 #[tokio::test]
-#[ignore]
 async fn accept_plain_request() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_opcode_factory_init_code("".to_string())
+    let (init_code, init_func) = create_opcode_factory_init_code(&context, "".to_string())
         .await
         .unwrap();
     test_user_op(
+        &context,
         "".to_string(),
         None,
         init_code,
@@ -235,13 +304,13 @@ async fn accept_plain_request() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-#[ignore]
 async fn reject_unkown_rule() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_opcode_factory_init_code("".to_string())
+    let (init_code, init_func) = create_opcode_factory_init_code(&context, "".to_string())
         .await
         .unwrap();
     test_user_op(
+        &context,
         "<unknown-rule>".to_string(),
         None,
         init_code,
@@ -254,13 +323,13 @@ async fn reject_unkown_rule() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_with_bad_opcode_in_ctr() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_opcode_factory_init_code("coinbase".to_string())
+    let (init_code, init_func) = create_opcode_factory_init_code(&context, "coinbase".to_string())
         .await
         .unwrap();
-    test_user_op(
+    let error = test_user_op(
+        &context,
         "".to_string(),
         None,
         init_code,
@@ -269,17 +338,18 @@ async fn fail_with_bad_opcode_in_ctr() -> anyhow::Result<()> {
     )
     .await
     .expect_err("factory uses banned opcode: COINBASE");
+    assert_rule(error, "OP-011");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_with_bad_opcode_in_paymaster() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_opcode_factory_init_code("".to_string())
+    let (init_code, init_func) = create_opcode_factory_init_code(&context, "".to_string())
         .await
         .unwrap();
-    test_user_op(
+    let error = test_user_op(
+        &context,
         "".to_string(),
         Some("coinbase".to_string()),
         init_code,
@@ -288,17 +358,18 @@ async fn fail_with_bad_opcode_in_paymaster() -> anyhow::Result<()> {
     )
     .await
     .expect_err("paymaster uses banned opcode: COINBASE");
+    assert_rule(error, "OP-011");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_with_bad_opcode_in_validation() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_opcode_factory_init_code("".to_string())
+    let (init_code, init_func) = create_opcode_factory_init_code(&context, "".to_string())
         .await
         .unwrap();
-    test_user_op(
+    let error = test_user_op(
+        &context,
         "blockhash".to_string(),
         None,
         init_code,
@@ -307,17 +378,18 @@ async fn fail_with_bad_opcode_in_validation() -> anyhow::Result<()> {
     )
     .await
     .expect_err("account uses banned opcode: BLOCKHASH");
+    assert_rule(error, "OP-011");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_if_create_too_many() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_opcode_factory_init_code("".to_string())
+    let (init_code, init_func) = create_opcode_factory_init_code(&context, "".to_string())
         .await
         .unwrap();
-    test_user_op(
+    let error = test_user_op(
+        &context,
         "create2".to_string(),
         None,
         init_code,
@@ -325,18 +397,19 @@ async fn fail_if_create_too_many() -> anyhow::Result<()> {
         context.opcodes_factory.address,
     )
     .await
-    .expect("account uses banned opcode: CREATE2");
+    .expect_err("account uses banned opcode: CREATE2");
+    assert_rule(error, "OP-031");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_referencing_self_token() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_storage_factory_init_code(0, "".to_string())
+    let (init_code, init_func) = create_storage_factory_init_code(&context, 0, "".to_string())
         .await
         .unwrap();
-    test_user_op(
+    let error = test_user_op(
+        &context,
         "balance-self".to_string(),
         None,
         init_code,
@@ -345,11 +418,11 @@ async fn fail_referencing_self_token() -> anyhow::Result<()> {
     )
     .await
     .expect_err("unstaked account accessed");
+    assert_rule(error, "STO-022");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn account_succeeds_referecing_its_own_balance() {
     test_existing_user_op("balance-self".to_string(), "".to_string())
         .await
@@ -357,15 +430,14 @@ async fn account_succeeds_referecing_its_own_balance() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn account_fail_to_read_allowance_of_address() {
-    test_existing_user_op("allowance-self-1".to_string(), "".to_string())
+    let error = test_existing_user_op("allowance-self-1".to_string(), "".to_string())
         .await
         .expect_err("account has forbidden read");
+    assert_rule(error, "STO-033");
 }
 
 #[tokio::test]
-#[ignore]
 async fn account_can_reference_its_own_allowance_on_other_contract_balance() {
     test_existing_user_op("allowance-1-self".to_string(), "".to_string())
         .await
@@ -373,7 +445,6 @@ async fn account_can_reference_its_own_allowance_on_other_contract_balance() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn access_self_struct_data() {
     test_existing_user_op("struct-self".to_string(), "".to_string())
         .await
@@ -381,21 +452,21 @@ async fn access_self_struct_data() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_to_access_other_address_struct_data() {
-    test_existing_user_op("struct-1".to_string(), "".to_string())
+    let error = test_existing_user_op("struct-1".to_string(), "".to_string())
         .await
         .expect_err("account has forbidden read");
+    assert_rule(error, "STO-033");
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_if_referencing_other_token_balance() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_storage_factory_init_code(0, "".to_string())
+    let (init_code, init_func) = create_storage_factory_init_code(&context, 0, "".to_string())
         .await
         .unwrap();
-    test_user_op(
+    let error = test_user_op(
+        &context,
         "balance-1".to_string(),
         None,
         init_code,
@@ -404,11 +475,11 @@ async fn fail_if_referencing_other_token_balance() -> anyhow::Result<()> {
     )
     .await
     .expect_err("account has forbidden read");
+    assert_rule(error, "STO-033");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_if_referencing_self_token_balance_after_wallet_creation() {
     test_existing_user_op("balance-self".to_string(), "".to_string())
         .await
@@ -416,7 +487,6 @@ async fn fail_if_referencing_self_token_balance_after_wallet_creation() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_with_unstaked_paymaster_returning_context() -> anyhow::Result<()> {
     let context = setup().await?;
     let pm = deploy_test_storage_account(context.client.clone())
@@ -426,29 +496,37 @@ async fn fail_with_unstaked_paymaster_returning_context() -> anyhow::Result<()>
         .await
         .expect("deploy succeed");
 
-    let mut paymaster_and_data = vec![];
-    paymaster_and_data.extend_from_slice(pm.address.as_bytes());
-    paymaster_and_data.extend_from_slice("postOp-context".as_bytes());
+    let user_op = paymaster_context_user_op(acct.address, pm.address);
+    let error = validate(&context, user_op)
+        .await
+        .expect_err("unstaked paymaster must not return context");
+    assert_rule(error, "EREP-050");
+    Ok(())
+}
 
-    let user_op = UserOperation {
-        sender: acct.address,
-        nonce: U256::zero(),
-        init_code: Bytes::default(),
-        call_data: Bytes::default(),
-        call_gas_limit: U256::from(0),
-        verification_gas_limit: U256::from(50000),
-        pre_verification_gas: U256::from(0),
-        max_fee_per_gas: U256::from(0),
-        max_priority_fee_per_gas: U256::from(0),
-        paymaster_and_data: Bytes::from(paymaster_and_data),
-        signature: Bytes::default(),
-    };
-    validate(user_op).expect_err("unstaked paymaster must not return context");
+#[tokio::test]
+async fn succeed_with_staked_paymaster_returning_context() -> anyhow::Result<()> {
+    let context = setup().await?;
+    let pm = deploy_test_storage_account(context.client.clone())
+        .await
+        .expect("deploy succeed");
+    pm.contract()
+        .add_stake(context.entry_point.address)
+        .value(parse_units("0.1", "ether").unwrap())
+        .send()
+        .await?;
+    let acct = deploy_test_recursion_account(context.client.clone(), context.entry_point.address)
+        .await
+        .expect("deploy succeed");
+
+    let user_op = paymaster_context_user_op(acct.address, pm.address);
+    validate(&context, user_op)
+        .await
+        .expect("staked paymaster may return context");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_with_validation_recursively_calls_handle_ops() -> anyhow::Result<()> {
     let context = setup().await?;
     let acct = deploy_test_recursion_account(context.client.clone(), context.entry_point.address)
@@ -467,18 +545,21 @@ async fn fail_with_validation_recursively_calls_handle_ops() -> anyhow::Result<(
         paymaster_and_data: Bytes::default(),
         signature: Bytes::from("handleOps".as_bytes().to_vec()),
     };
-    validate(user_op).expect_err("illegal call into EntryPoint");
+    let error = validate(&context, user_op)
+        .await
+        .expect_err("illegal call into EntryPoint");
+    assert_rule(error, "OP-054");
     Ok(())
 }
 
 #[tokio::test]
-#[ignore]
 async fn succeed_with_inner_revert() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_storage_factory_init_code(0, "".to_string())
+    let (init_code, init_func) = create_storage_factory_init_code(&context, 0, "".to_string())
         .await
         .unwrap();
     test_user_op(
+        &context,
         "inner-revert".to_string(),
         None,
         init_code,
@@ -491,13 +572,13 @@ async fn succeed_with_inner_revert() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-#[ignore]
 async fn fail_with_inner_oog_revert() -> anyhow::Result<()> {
     let context = setup().await?;
-    let (init_code, init_func) = create_storage_factory_init_code(0, "".to_string())
+    let (init_code, init_func) = create_storage_factory_init_code(&context, 0, "".to_string())
         .await
         .unwrap();
-    test_user_op(
+    let error = test_user_op(
+        &context,
         "oog".to_string(),
         None,
         init_code,
@@ -506,5 +587,6 @@ async fn fail_with_inner_oog_revert() -> anyhow::Result<()> {
     )
     .await
     .expect_err("oog");
+    assert_rule(error, "OP-020");
     Ok(())
 }